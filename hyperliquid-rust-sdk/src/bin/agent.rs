@@ -20,7 +20,7 @@ async fn main() {
         This agent cannot transfer or withdraw funds, but can for example place orders.
     */
 
-    let (private_key, response) = exchange_client.approve_agent(None).await.unwrap();
+    let (private_key, response) = exchange_client.approve_agent(None, None).await.unwrap();
     info!("Agent creation response: {response:?}");
 
     let wallet: LocalWallet = private_key.parse().unwrap();
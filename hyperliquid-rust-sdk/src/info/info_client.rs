@@ -3,7 +3,7 @@ use crate::{
         CandlesSnapshotResponse, FundingHistoryResponse, L2SnapshotResponse, OpenOrdersResponse,
         OrderInfo, RecentTradesResponse, UserFillsResponse, UserStateResponse,
     },
-    meta::{Meta, SpotMeta, SpotMetaAndAssetCtxs},
+    meta::{Meta, MetaAndAssetCtxs, SpotMeta, SpotMetaAndAssetCtxs},
     prelude::*,
     req::HttpClient,
     ws::{Subscription, WsManager},
@@ -53,6 +53,7 @@ pub enum InfoRequest {
         oid: u64,
     },
     Meta,
+    MetaAndAssetCtxs,
     SpotMeta,
     SpotMetaAndAssetCtxs,
     AllMids,
@@ -205,6 +206,11 @@ impl InfoClient {
         self.send_info_request(input).await
     }
 
+    pub async fn meta_and_asset_contexts(&self) -> Result<Vec<MetaAndAssetCtxs>> {
+        let input = InfoRequest::MetaAndAssetCtxs;
+        self.send_info_request(input).await
+    }
+
     pub async fn spot_meta(&self) -> Result<SpotMeta> {
         let input = InfoRequest::SpotMeta;
         self.send_info_request(input).await
@@ -17,5 +17,8 @@ pub use exchange::*;
 pub use helpers::{bps_diff, truncate_float, BaseUrl};
 pub use info::{info_client::*, *};
 pub use market_maker::{MarketMaker, MarketMakerInput, MarketMakerRestingOrder};
-pub use meta::{AssetMeta, Meta, SpotAssetMeta, SpotMeta};
+pub use meta::{
+    AssetMeta, Meta, MetaAndAssetCtxs, PerpAssetContext, SpotAssetContext, SpotAssetMeta, SpotMeta,
+    SpotMetaAndAssetCtxs,
+};
 pub use ws::*;
@@ -45,6 +45,25 @@ impl SpotMeta {
     }
 }
 
+#[derive(Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum MetaAndAssetCtxs {
+    Meta(Meta),
+    Context(Vec<PerpAssetContext>),
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PerpAssetContext {
+    pub day_ntl_vlm: String,
+    pub mark_px: String,
+    pub mid_px: Option<String>,
+    pub prev_day_px: String,
+    pub funding: String,
+    pub open_interest: String,
+    pub oracle_px: String,
+}
+
 #[derive(Deserialize, Debug, Clone)]
 #[serde(untagged)]
 pub enum SpotMetaAndAssetCtxs {
@@ -68,6 +87,8 @@ pub struct SpotAssetContext {
 pub struct AssetMeta {
     pub name: String,
     pub sz_decimals: u32,
+    #[serde(default)]
+    pub is_delisted: bool,
 }
 
 #[derive(Deserialize, Debug, Clone)]
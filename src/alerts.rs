@@ -1,25 +1,386 @@
-use crate::db::{Database, AlertTable};
-use hyperliquid_rust_sdk::InfoClient;
+use crate::assets::AssetRegistry;
+use crate::db::{Database, ActiveWindow, AlertDirection, AlertKind, AlertTable, AlertTarget, MarketType, NewAlert, RearmMode, HOURS_PER_YEAR};
+use crate::book::OrderBook;
+use crate::expressions::{Expression, ExpressionTick, MarketSnapshot};
+use crate::indicators::{IndicatorCondition, IndicatorEngine};
+use crate::reports;
+use crate::subscriptions::{Feed, SubscriptionManager};
+use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use hyperliquid_rust_sdk::{CandleData, InfoClient, PerpAssetContext};
 use rusqlite::Result;
 use teloxide::types::ChatId;
+use std::collections::{HashMap, VecDeque};
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
+/// Longest window a windowed change alert can look back over.
+pub const MAX_WINDOW_MINUTES: i64 = 24 * 60;
+/// Minimum spacing between two samples kept in a `RollingHistory`.
+const SAMPLE_INTERVAL_SECS: i64 = 5;
+
+/// Recent samples of one value of a token (mark price, open interest), kept
+/// for windowed change alerts.
+#[derive(Debug, Default)]
+pub struct RollingHistory {
+    samples: VecDeque<(DateTime<Utc>, f64)>,
+}
+
+impl RollingHistory {
+    pub fn record(&mut self, at: DateTime<Utc>, value: f64) {
+        if self.samples.back().is_some_and(|(last, _)| at - *last < Duration::seconds(SAMPLE_INTERVAL_SECS)) {
+            return;
+        }
+        self.samples.push_back((at, value));
+        let oldest = at - Duration::minutes(MAX_WINDOW_MINUTES);
+        while self.samples.front().is_some_and(|(sampled_at, _)| *sampled_at < oldest) {
+            self.samples.pop_front();
+        }
+    }
+
+    /// The lowest and highest value sampled since `since`.
+    pub fn range_since(&self, since: DateTime<Utc>) -> Option<(f64, f64)> {
+        self.samples
+            .iter()
+            .filter(|(sampled_at, _)| *sampled_at >= since)
+            .fold(None, |range, &(_, value)| match range {
+                None => Some((value, value)),
+                Some((low, high)) => Some((f64::min(low, value), f64::max(high, value))),
+            })
+    }
+}
+
+/// A price update for one token, along with what is needed to evaluate
+/// percentage-change alerts against it.
+pub struct PriceTick<'a> {
+    pub previous_px: f64,
+    pub mark_px: f64,
+    pub prev_day_px: Option<f64>,
+    pub history: &'a RollingHistory,
+    pub at: DateTime<Utc>,
+    /// Only present on perp updates.
+    pub perp: Option<PerpTick<'a>>,
+}
+
+/// Funding, premium and open interest from a perp update. Funding is the
+/// hourly rate in percent and premium is the mark/oracle premium in bps.
+pub struct PerpTick<'a> {
+    pub funding_pct: f64,
+    pub previous_funding_pct: f64,
+    pub premium_bps: f64,
+    pub previous_premium_bps: f64,
+    pub open_interest: f64,
+    pub open_interest_history: &'a RollingHistory,
+}
+
+/// Funding and premium as last seen for a perp, used as the previous values
+/// when detecting crossings.
+#[derive(Debug, Clone, Copy)]
+pub struct PerpMetrics {
+    pub funding_pct: f64,
+    pub premium_bps: f64,
+}
+
+fn change_pct(from: f64, to: f64) -> f64 {
+    (to - from) / from * 100.0
+}
+
+/// Whether a rise of `rise_pct` or a drop of `drop_pct` (negative) satisfies
+/// a change alert in `direction` with a threshold of `threshold_pct`.
+fn is_change_reached(direction: AlertDirection, rise_pct: f64, drop_pct: f64, threshold_pct: f64) -> bool {
+    let rose = rise_pct >= threshold_pct;
+    let dropped = drop_pct <= -threshold_pct;
+    match direction {
+        AlertDirection::Above => rose,
+        AlertDirection::Below => dropped,
+        AlertDirection::Cross => rose || dropped,
+    }
+}
+
+/// Whether `current` moved at least `threshold_pct` away from the lowest
+/// (rises) or highest (drops) value sampled in the last `window_minutes`.
+fn is_window_change_reached(
+    direction: AlertDirection,
+    history: &RollingHistory,
+    since: DateTime<Utc>,
+    current: f64,
+    threshold_pct: f64,
+) -> bool {
+    let Some((low, high)) = history.range_since(since) else {
+        return false;
+    };
+    is_change_reached(direction, change_pct(low, current), change_pct(high, current), threshold_pct)
+}
+
+/// Whether a non-price alert fires on `tick`. Changes against a fixed
+/// reference fire when the move first reaches the threshold, and funding and
+/// premium alerts when the value crosses it; windowed changes fire whenever
+/// the window contains a move that large.
+fn is_tick_triggered(alert: &AlertTable, tick: &PriceTick) -> bool {
+    let since = tick.at - Duration::minutes(alert.window_minutes.unwrap_or(MAX_WINDOW_MINUTES));
+    let threshold_pct = alert.change_pct.unwrap_or_default();
+    let reference = match alert.kind {
+        AlertKind::Price
+        | AlertKind::Spread
+        | AlertKind::BidDepth
+        | AlertKind::AskDepth
+        | AlertKind::LargeLevel
+        | AlertKind::Indicator
+        | AlertKind::Expression
+        | AlertKind::Ratio
+        | AlertKind::Basis => return false,
+        AlertKind::DayChange => tick.prev_day_px,
+        AlertKind::FromSet | AlertKind::Trailing => Some(alert.price),
+        AlertKind::WindowChange => {
+            return is_window_change_reached(alert.direction, tick.history, since, tick.mark_px, threshold_pct);
+        }
+        AlertKind::Funding => {
+            return tick.perp.as_ref().is_some_and(|perp| {
+                alert.direction.is_triggered(perp.previous_funding_pct, perp.funding_pct, alert.price)
+            });
+        }
+        AlertKind::Premium => {
+            return tick.perp.as_ref().is_some_and(|perp| {
+                alert.direction.is_triggered(perp.previous_premium_bps, perp.premium_bps, alert.price)
+            });
+        }
+        AlertKind::OpenInterestChange => {
+            return tick.perp.as_ref().is_some_and(|perp| {
+                is_window_change_reached(alert.direction, perp.open_interest_history, since, perp.open_interest, threshold_pct)
+            });
+        }
+    };
+    let Some(reference) = reference.filter(|reference| *reference > 0.0) else {
+        return false;
+    };
+    let previous = change_pct(reference, tick.previous_px);
+    let current = change_pct(reference, tick.mark_px);
+    is_change_reached(alert.direction, current, current, threshold_pct)
+        && !is_change_reached(alert.direction, previous, previous, threshold_pct)
+}
+
+/// An order book update for one token, from the feed named by `feed`.
+pub struct BookTick<'a> {
+    pub feed: Feed,
+    pub book: &'a OrderBook,
+    pub previous_book: Option<&'a OrderBook>,
+}
+
+/// The indicator condition of an indicator alert.
+fn indicator_condition(target: &AlertTarget) -> Option<IndicatorCondition> {
+    if target.kind != AlertKind::Indicator {
+        return None;
+    }
+    target.expression.as_deref()?.parse().ok()
+}
+
+/// The expression of an expression alert.
+fn expression(target: &AlertTarget) -> Option<Expression> {
+    if target.kind != AlertKind::Expression {
+        return None;
+    }
+    target.expression.as_deref()?.parse().ok()
+}
+
+/// The coin of the second leg of a ratio or basis alert.
+fn other_leg(target: &AlertTarget) -> Option<&str> {
+    match target.kind {
+        AlertKind::Ratio | AlertKind::Basis => target.expression.as_deref(),
+        _ => None,
+    }
+}
+
+/// The ratio or basis between the marks of two legs.
+fn pair_value(kind: AlertKind, first: f64, second: f64) -> Option<f64> {
+    if second <= 0.0 {
+        return None;
+    }
+    match kind {
+        AlertKind::Ratio => Some(first / second),
+        AlertKind::Basis => Some((first - second) / second * 10_000.0),
+        _ => None,
+    }
+}
+
+/// Whether a ratio or basis alert on `token` fires on `tick`: the value
+/// between its legs crosses the threshold as either leg updates.
+fn is_pair_triggered(alert: &AlertTable, other_token: &str, tick: &ExpressionTick) -> bool {
+    let value = |first: Option<MarketSnapshot>, second: Option<MarketSnapshot>| {
+        pair_value(alert.kind, first?.mark_px, second?.mark_px)
+    };
+    let (Some(previous), Some(current)) = (
+        value(tick.before(&alert.token), tick.before(other_token)),
+        value(tick.after(&alert.token), tick.after(other_token)),
+    ) else {
+        return false;
+    };
+    alert.direction.is_triggered(previous, current, alert.price)
+}
+
+/// What a ratio, basis or expression alert reads, with its coins resolved to
+/// the tokens of their feeds.
+enum CrossMarket {
+    Pair { other_token: String },
+    Expression { expression: Expression, tokens: HashMap<String, String> },
+}
+
+/// The ratio, basis and expression alerts by id, along with the ids of the
+/// alerts that read each token, so a tick only evaluates the alerts on its
+/// token.
+#[derive(Default)]
+struct CrossMarketIndex {
+    alerts: HashMap<i64, CrossMarket>,
+    by_token: HashMap<String, Vec<i64>>,
+}
+
+impl CrossMarketIndex {
+    /// Indexes `alerts`, reading coins missing from the asset registry from
+    /// their perp if they name one.
+    fn build(alerts: Vec<AlertTable>, assets: &AssetRegistry) -> Self {
+        let market_token = |coin: &str| match assets.lookup(coin).or_else(|_| assets.lookup(&format!("{coin}-PERP"))) {
+            Ok((_, token)) => Some(token),
+            Err(_) => coin.strip_suffix("-PERP").map(str::to_string),
+        };
+        let mut index = Self::default();
+        for alert in alerts {
+            let target = alert.target();
+            let (reads, tokens) = if let Some(other_token) = other_leg(&target).and_then(market_token) {
+                let tokens = vec![alert.token.clone(), other_token.clone()];
+                (CrossMarket::Pair { other_token }, tokens)
+            } else if let Some(expression) = expression(&target) {
+                let tokens: HashMap<String, String> = expression
+                    .coins()
+                    .into_iter()
+                    .filter_map(|coin| Some((coin.to_string(), market_token(coin)?)))
+                    .collect();
+                let read = tokens.values().cloned().collect();
+                (CrossMarket::Expression { expression, tokens }, read)
+            } else {
+                continue;
+            };
+            for token in tokens {
+                let ids = index.by_token.entry(token).or_default();
+                if !ids.contains(&alert.id) {
+                    ids.push(alert.id);
+                }
+            }
+            index.alerts.insert(alert.id, reads);
+        }
+        index
+    }
+}
+
+/// The armed ratio, basis and expression alerts that read one token, to be
+/// evaluated against a tick on it.
+pub struct CrossMarketAlerts {
+    alerts: Vec<AlertTable>,
+    index: Arc<CrossMarketIndex>,
+}
+
+impl CrossMarketAlerts {
+    /// Returns the ratio and basis alerts whose threshold was crossed with
+    /// `tick`, and the expression alerts that became true.
+    pub fn triggered(self, tick: &ExpressionTick) -> Vec<AlertTable> {
+        let index = self.index;
+        self.alerts
+            .into_iter()
+            .filter(|alert| match index.alerts.get(&alert.id) {
+                Some(CrossMarket::Pair { other_token }) => is_pair_triggered(alert, other_token, tick),
+                Some(CrossMarket::Expression { expression, tokens }) => {
+                    expression.is_triggered(tick, |coin| tokens.get(coin).cloned())
+                }
+                None => false,
+            })
+            .collect()
+    }
+}
+
+/// The websocket feed an alert with `target` is evaluated on.
+pub fn feed_for(target: &AlertTarget) -> Feed {
+    match target.kind {
+        AlertKind::Spread => Feed::Bbo,
+        AlertKind::BidDepth | AlertKind::AskDepth | AlertKind::LargeLevel => Feed::L2Book,
+        AlertKind::Indicator => match indicator_condition(target) {
+            Some(condition) => Feed::Candle(condition.interval),
+            None => Feed::AssetCtx,
+        },
+        _ => Feed::AssetCtx,
+    }
+}
+
+/// The value of `book` an order book alert compares against its threshold.
+fn book_value(alert: &AlertTable, book: &OrderBook) -> Option<f64> {
+    let band_pct = alert.change_pct.unwrap_or(1.0);
+    match alert.kind {
+        AlertKind::Spread => book.spread_bps(),
+        AlertKind::BidDepth => book.bid_depth(band_pct),
+        AlertKind::AskDepth => book.ask_depth(band_pct),
+        AlertKind::LargeLevel => book.largest_level(),
+        _ => None,
+    }
+}
+
+/// Whether an order book alert fires on `tick`: its value crosses the
+/// threshold between the previous and current book from the alert's feed.
+fn is_book_triggered(alert: &AlertTable, tick: &BookTick) -> bool {
+    if feed_for(&alert.target()) != tick.feed {
+        return false;
+    }
+    let Some(current) = book_value(alert, tick.book) else {
+        return false;
+    };
+    let previous = tick.previous_book.and_then(|book| book_value(alert, book)).unwrap_or(current);
+    alert.direction.is_triggered(previous, current, alert.price)
+}
+
 #[derive(Clone)]
 pub struct AlertService {
     db: Database,
     info_client: Arc<Mutex<InfoClient>>,
+    subscriptions: SubscriptionManager,
+    indicators: IndicatorEngine,
+    assets: AssetRegistry,
+    // Built from the database on first use and dropped whenever an alert is
+    // added, changed or removed.
+    cross_market: Arc<Mutex<Option<Arc<CrossMarketIndex>>>>,
 }
 
 impl AlertService {
-    pub fn new(db: Database, info_client: Arc<Mutex<InfoClient>>) -> Self {
-        Self { db, info_client }
+    pub fn new(db: Database, info_client: Arc<Mutex<InfoClient>>, subscriptions: SubscriptionManager, assets: AssetRegistry) -> Self {
+        Self {
+            db,
+            indicators: IndicatorEngine::new(info_client.clone()),
+            info_client,
+            subscriptions,
+            assets,
+            cross_market: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Returns the alerts on `token` whose target was reached by `tick`.
+    /// Alerts that expired or are outside their active window never fire.
+    pub async fn get_triggered_alerts(&self, market: MarketType, token: &str, tick: &PriceTick<'_>) -> Result<Vec<AlertTable>> {
+        let lower_alert_price = tick.previous_px.min(tick.mark_px) * 0.999;
+        let upper_alert_price = tick.previous_px.max(tick.mark_px) * 1.001;
+        let alerts = self.db.get_triggered_alerts(market, token, lower_alert_price, upper_alert_price).await?;
+        let other_alerts = self.db.get_armed_non_price_alerts(market, token).await?;
+        Ok(alerts
+            .into_iter()
+            .filter(|alert| alert.direction.is_triggered(tick.previous_px, tick.mark_px, alert.price))
+            .chain(other_alerts.into_iter().filter(|alert| is_tick_triggered(alert, tick)))
+            .filter(|alert| alert.is_active_at(tick.at))
+            .collect())
     }
 
-    pub async fn get_triggered_alerts(&self, mark_px: f64) -> Result<Vec<AlertTable>> {
-        let lower_alert_price = mark_px * 0.999;
-        let upper_alert_price = mark_px * 1.001;
-        self.db.get_triggered_alerts(lower_alert_price, upper_alert_price).await
+    /// Returns the order book alerts on `token` whose threshold was crossed by
+    /// `tick`.
+    pub async fn get_triggered_book_alerts(&self, token: &str, tick: &BookTick<'_>) -> Result<Vec<AlertTable>> {
+        let alerts = self.db.get_armed_book_alerts(token).await?;
+        let now = Utc::now();
+        Ok(alerts
+            .into_iter()
+            .filter(|alert| alert.is_active_at(now) && is_book_triggered(alert, tick))
+            .collect())
     }
 
     pub async fn set_alert_cooldowns(&self, alerts: &[AlertTable]) -> Result<()> {
@@ -29,6 +390,69 @@ impl AlertService {
         Ok(())
     }
 
+    /// Follows `mark_px` with the extreme of the trailing alerts on `token`.
+    pub async fn update_trailing_extremes(&self, market: MarketType, token: &str, mark_px: f64) -> Result<usize> {
+        self.db.update_trailing_extremes(market, token, mark_px).await
+    }
+
+    /// Re-arms the fired band alerts on `token` that the price has left.
+    pub async fn rearm_band_alerts(&self, market: MarketType, token: &str, mark_px: f64) -> Result<usize> {
+        self.db.rearm_band_alerts(market, token, mark_px).await
+    }
+
+    /// Changes how an alert owned by `chat_id` re-arms after firing, and
+    /// re-arms it. Returns `false` if the chat has no alert with that id.
+    pub async fn set_rearm_mode(&self, alert_id: i64, chat_id: ChatId, rearm: RearmMode) -> anyhow::Result<bool> {
+        let Some(alert) = self.db.get_alert_for_chat(alert_id, chat_id).await? else {
+            return Ok(false);
+        };
+        if matches!(rearm, RearmMode::Band(_)) && alert.kind != AlertKind::Price {
+            anyhow::bail!("Only price alerts can re-arm on a band");
+        }
+        self.db.set_alert_rearm(alert_id, chat_id, rearm).await?;
+        Ok(true)
+    }
+
+    /// Sets or clears the expiry of an alert owned by `chat_id`, re-arming
+    /// it if it had expired and the new expiry is still ahead. Returns
+    /// `false` if the chat has no alert with that id.
+    pub async fn set_expiry(&self, alert_id: i64, chat_id: ChatId, expires_at: Option<DateTime<Utc>>) -> Result<bool> {
+        let Some(alert) = self.db.get_alert_for_chat(alert_id, chat_id).await? else {
+            return Ok(false);
+        };
+        self.db.set_alert_expiry(alert_id, chat_id, expires_at).await?;
+        if !alert.is_active && expires_at.is_none_or(|expires_at| expires_at > Utc::now()) {
+            self.db.set_alert_active(alert_id, true).await?;
+            if let Err(e) = self.acquire_subscription(&alert.token, &alert.target()).await {
+                log::error!("Failed to subscribe to {}: {e}", alert.token);
+            }
+        }
+        Ok(true)
+    }
+
+    /// The timezone `chat_id` shows times and reads dates in.
+    pub async fn timezone(&self, chat_id: ChatId) -> Result<Tz> {
+        self.db.get_chat_timezone(chat_id).await
+    }
+
+    /// Sets or clears the active window of an alert owned by `chat_id`.
+    /// Returns `false` if the chat has no alert with that id.
+    pub async fn set_active_window(&self, alert_id: i64, chat_id: ChatId, window: Option<&ActiveWindow>) -> Result<bool> {
+        Ok(self.db.set_alert_window(alert_id, chat_id, window).await? > 0)
+    }
+
+    /// Deactivates every alert that expired and returns them, so their chats
+    /// can be told. They stay listed until deleted or given a new expiry.
+    pub async fn expire_alerts(&self) -> Result<Vec<AlertTable>> {
+        let mut alerts = self.db.get_expired_alerts(Utc::now()).await?;
+        for alert in &mut alerts {
+            self.db.set_alert_active(alert.id, false).await?;
+            self.release_subscription(alert).await;
+            alert.is_active = false;
+        }
+        Ok(alerts)
+    }
+
     pub async fn reset_cooldowns(&self) -> Result<usize> {
         let result = self.db.reset_cooldowns().await?;
         if result > 0 {
@@ -45,18 +469,548 @@ impl AlertService {
         self.db.get_all_alerts_for_chat(chat_id).await
     }
 
-    pub async fn create_alert(&self, public_key: &str, chat_id: ChatId, coin: &str, price: f64) -> Result<()> {
-        let token = self.get_token(coin).await.unwrap();
+    /// Creates an alert on `coin` and returns the stored target, which for
+    /// alerts relative to the current price includes that price.
+    pub async fn create_alert(&self, public_key: &str, chat_id: ChatId, coin: &str, target: AlertTarget) -> anyhow::Result<AlertTarget> {
+        let (market, token) = self.assets.resolve(coin).await?;
+        if target.kind.is_perp_only() && market != MarketType::Perp {
+            anyhow::bail!("Funding, open interest and premium alerts need a perp, e.g. {coin}-PERP");
+        }
+        let target = self.with_reference_price(&token, target).await?;
+        self.feeds_for(&token, &target).await?;
+
+        self.db.insert_alert(&NewAlert {
+            public_key: public_key.to_string(),
+            chat_id,
+            coin: coin.to_string(),
+            token: token.clone(),
+            market,
+            target: target.clone(),
+        }).await?;
+        self.invalidate_cross_market().await;
+        if let Err(e) = self.acquire_subscription(&token, &target).await {
+            log::error!("Failed to subscribe to {token}: {e}");
+        }
+        Ok(target)
+    }
+
+    /// Creates an expression alert, stored against every coin it reads as a
+    /// comma-separated list and against the market of the first.
+    pub async fn create_expression_alert(&self, public_key: &str, chat_id: ChatId, target: AlertTarget) -> anyhow::Result<AlertTarget> {
+        let expression = expression(&target).ok_or_else(|| anyhow::anyhow!("Not an expression: {target}"))?;
+        let (coin, market, token) = self.expression_markets(&expression).await?;
+
+        self.db.insert_alert(&NewAlert {
+            public_key: public_key.to_string(),
+            chat_id,
+            coin,
+            token: token.clone(),
+            market,
+            target: target.clone(),
+        }).await?;
+        self.invalidate_cross_market().await;
+        if let Err(e) = self.acquire_subscription(&token, &target).await {
+            log::error!("Failed to subscribe to {target}: {e}");
+        }
+        Ok(target)
+    }
+
+    /// The coins `expression` reads and their tokens, each joined by commas,
+    /// and the market of the first coin.
+    async fn expression_markets(&self, expression: &Expression) -> anyhow::Result<(String, MarketType, String)> {
+        let coins = expression.coins();
+        let mut markets = Vec::new();
+        for coin in &coins {
+            markets.push(self.market_token(coin).await?);
+        }
+        let tokens: Vec<&str> = markets.iter().map(|(_, token)| token.as_str()).collect();
+        Ok((coins.join(","), markets[0].0, tokens.join(",")))
+    }
+
+    /// Deletes an alert owned by `chat_id`. Returns `false` if the chat has no
+    /// alert with that id.
+    pub async fn delete_alert(&self, alert_id: i64, chat_id: ChatId) -> Result<bool> {
+        let Some(alert) = self.db.get_alert_for_chat(alert_id, chat_id).await? else {
+            return Ok(false);
+        };
+        self.db.delete_alert(alert_id, chat_id).await?;
+        self.invalidate_cross_market().await;
+        if alert.is_active {
+            self.release_subscription(&alert).await;
+        }
+        Ok(true)
+    }
+
+    /// Changes the target of an alert owned by `chat_id` and re-arms it.
+    /// Returns the stored target, or `None` if the chat has no alert with
+    /// that id.
+    pub async fn edit_alert(&self, alert_id: i64, chat_id: ChatId, target: AlertTarget) -> anyhow::Result<Option<AlertTarget>> {
+        let Some(alert) = self.db.get_alert_for_chat(alert_id, chat_id).await? else {
+            return Ok(None);
+        };
+        if alert.kind == AlertKind::Expression && target.kind != AlertKind::Expression {
+            anyhow::bail!("An expression alert can only be changed to another expression, set a new alert with /setalert");
+        }
+        if target.kind.is_perp_only() && alert.market != MarketType::Perp {
+            anyhow::bail!("Funding, open interest and premium alerts need a perp");
+        }
+        if matches!(alert.rearm, RearmMode::Band(_)) && target.kind != AlertKind::Price {
+            anyhow::bail!("Only price alerts can re-arm on a band, change its re-arm mode with /setrearm first");
+        }
+        let target = self.with_reference_price(&alert.token, target).await?;
+        let feeds = self.feeds_for(&alert.token, &target).await?;
+        if let Some(expression) = expression(&target) {
+            let (coin, market, token) = self.expression_markets(&expression).await?;
+            self.db.update_alert_market(alert_id, chat_id, &coin, market, &token).await?;
+        }
+        self.db.update_alert_target(alert_id, chat_id, &target).await?;
+        self.invalidate_cross_market().await;
+        if alert.is_active && self.feeds_for(&alert.token, &alert.target()).await.ok() != Some(feeds) {
+            if let Err(e) = self.acquire_subscription(&alert.token, &target).await {
+                log::error!("Failed to subscribe to {}: {e}", alert.token);
+            }
+            self.release_subscription(&alert).await;
+        }
+        Ok(Some(target))
+    }
+
+    /// Fills in the current mid price of `token` as the reference of alerts
+    /// that measure a move from the price when they were set, which is also
+    /// the first extreme of a trailing alert.
+    async fn with_reference_price(&self, token: &str, target: AlertTarget) -> anyhow::Result<AlertTarget> {
+        if !matches!(target.kind, AlertKind::FromSet | AlertKind::Trailing) {
+            return Ok(target);
+        }
+        let mids = self.info_client.lock().await.all_mids().await?;
+        let price = mids
+            .get(token)
+            .and_then(|px| px.parse::<f64>().ok())
+            .ok_or_else(|| anyhow::anyhow!("No price available for {token}"))?;
+        Ok(AlertTarget { price, ..target })
+    }
+
+    /// Deletes every alert owned by `chat_id` and returns how many were removed.
+    pub async fn clear_alerts(&self, chat_id: ChatId) -> Result<usize> {
+        let alerts = self.db.get_all_alerts_for_chat(chat_id).await?;
+        self.db.delete_alerts_for_chat(chat_id).await?;
+        self.invalidate_cross_market().await;
+        for alert in alerts.iter().filter(|alert| alert.is_active) {
+            self.release_subscription(alert).await;
+        }
+        Ok(alerts.len())
+    }
+
+    /// The market and token of a coin read by an expression or as the second
+    /// leg of a ratio or basis alert. Coins without a spot pair fall back to
+    /// the perp, so `ETH` reads `ETH-PERP`.
+    async fn market_token(&self, coin: &str) -> anyhow::Result<(MarketType, String)> {
+        match self.assets.resolve(coin).await {
+            Ok(market) => Ok(market),
+            Err(e) if coin.to_uppercase().ends_with("-PERP") => Err(e.into()),
+            Err(e) => Ok(self.assets.resolve(&format!("{coin}-PERP")).await.map_err(|_| e)?),
+        }
+    }
+
+    /// The feeds and tokens an alert on `token` with `target` is evaluated
+    /// on. Ratio, basis and expression alerts follow the asset context of
+    /// every coin they read.
+    async fn feeds_for(&self, token: &str, target: &AlertTarget) -> anyhow::Result<Vec<(Feed, String)>> {
+        if let Some(other_leg) = other_leg(target) {
+            let (_, other_token) = self.market_token(other_leg).await?;
+            return Ok(vec![(Feed::AssetCtx, token.to_string()), (Feed::AssetCtx, other_token)]);
+        }
+        let Some(expression) = expression(target) else {
+            return Ok(vec![(feed_for(target), token.to_string())]);
+        };
+        let mut feeds = Vec::new();
+        for coin in expression.coins() {
+            let (_, token) = self.market_token(coin).await?;
+            feeds.push((Feed::AssetCtx, token));
+        }
+        Ok(feeds)
+    }
+
+    /// Subscribes to the feeds `target` is evaluated on, warming up the
+    /// candle series of indicator alerts first.
+    async fn acquire_subscription(&self, token: &str, target: &AlertTarget) -> anyhow::Result<()> {
+        if let Some(condition) = indicator_condition(target)
+            && let Err(e) = self.indicators.warm_up(token, condition.interval).await
+        {
+            log::error!("Failed to load {} candles for {token}: {e}", condition.interval.as_str());
+        }
+        for (feed, token) in self.feeds_for(token, target).await? {
+            self.subscriptions.acquire(feed, &token).await?;
+        }
+        Ok(())
+    }
+
+    async fn release_subscription(&self, alert: &AlertTable) {
+        let feeds = match self.feeds_for(&alert.token, &alert.target()).await {
+            Ok(feeds) => feeds,
+            Err(e) => {
+                log::error!("Failed to resolve the feeds of alert {}: {e}", alert.id);
+                return;
+            }
+        };
+        for (feed, token) in feeds {
+            if let Err(e) = self.subscriptions.release(feed, &token).await {
+                log::error!("Failed to unsubscribe from {token}: {e}");
+            }
+        }
+    }
+
+    /// Subscribes to the feed of every active alert. Alerts whose feeds
+    /// can't be subscribed to, e.g. because a coin they read was delisted,
+    /// are logged and skipped.
+    pub async fn subscribe_existing_alerts(&self) -> anyhow::Result<()> {
+        for alert in self.db.get_all_alerts().await?.into_iter().filter(|alert| alert.is_active) {
+            if let Err(e) = self.acquire_subscription(&alert.token, &alert.target()).await {
+                log::error!("Failed to subscribe to the feeds of alert {}: {e}", alert.id);
+            }
+        }
+        Ok(())
+    }
+
+    /// Applies a live candle to its series and returns the indicator alerts
+    /// on that series whose condition became true.
+    pub async fn get_triggered_indicator_alerts(&self, candle: &CandleData) -> Result<Vec<AlertTable>> {
+        let Some(update) = self.indicators.apply_candle(candle).await else {
+            return Ok(Vec::new());
+        };
+        let alerts = self.db.get_armed_indicator_alerts(&update.token).await?;
+        let now = Utc::now();
+        Ok(alerts
+            .into_iter()
+            .filter(|alert| alert.is_active_at(now))
+            .filter(|alert| {
+                indicator_condition(&alert.target()).is_some_and(|condition| {
+                    condition.interval == update.interval && condition.is_triggered(&update.previous, &update.current)
+                })
+            })
+            .collect())
+    }
+
+    /// Returns the armed ratio, basis and expression alerts that read `token`
+    /// and are active now. Alerts on other tokens are never loaded.
+    pub async fn get_cross_market_alerts(&self, token: &str) -> Result<CrossMarketAlerts> {
+        let index = {
+            let mut cross_market = self.cross_market.lock().await;
+            match &*cross_market {
+                Some(index) => index.clone(),
+                None => {
+                    let alerts = self.db.get_cross_market_alerts().await?;
+                    cross_market.insert(Arc::new(CrossMarketIndex::build(alerts, &self.assets))).clone()
+                }
+            }
+        };
+        let alert_ids = index.by_token.get(token).map(Vec::as_slice).unwrap_or_default();
+        let now = Utc::now();
+        let alerts = self
+            .db
+            .get_armed_alerts(alert_ids)
+            .await?
+            .into_iter()
+            .filter(|alert| alert.is_active_at(now))
+            .collect();
+        Ok(CrossMarketAlerts { alerts, index })
+    }
+
+    /// Drops the index of cross-market alerts, so the next tick rebuilds it.
+    async fn invalidate_cross_market(&self) {
+        *self.cross_market.lock().await = None;
+    }
+
+    /// Parses a `/setexpression` condition such as
+    /// `HYPE > 40 AND funding(HYPE) < 0` or `price(ETH)/price(BTC) < 0.05`.
+    pub fn parse_expression(expression: &str) -> anyhow::Result<AlertTarget> {
+        let expression = expression.parse::<Expression>()?;
+        Ok(AlertTarget {
+            kind: AlertKind::Expression,
+            expression: Some(expression.to_string()),
+            ..AlertTarget::price(AlertDirection::Above, 0.0)
+        })
+    }
+
+    /// Parses a `/setalert` target. Prices look like `40`, `>40` or `<40`.
+    /// Percentage moves look like `+5%`, `-5%` or `5%` (either way) and
+    /// default to the 24h change; a `/15m` or `/4h` suffix measures the move
+    /// within that window instead, and `/set` measures it from the current
+    /// price. Perps also take `funding>0.01%` (hourly, or `funding>10%apr`),
+    /// `oi+10%/1h` and `premium>20bps`. Order book targets look like
+    /// `spread>50bps`, `biddepth1%<50000`, `askdepth1%<50000` and
+    /// `level>1000`. Candle indicators compare against a number or another
+    /// indicator on the same interval, e.g. `rsi14(1h)<30`,
+    /// `ema12(4h)>ema26(4h)` or `close>bbu20(1h)`. Two markets compare as
+    /// `ratio(BTC-PERP)<0.05` or `basis(HYPE-PERP)>50bps`. Trailing alerts
+    /// look like `trail-10%` (a drop from the high since the alert was set)
+    /// or `trail+10%` (a rise from the low).
+    pub fn parse_target(target: &str) -> anyhow::Result<AlertTarget> {
+        if let Some(trail) = target.strip_prefix("trail") {
+            let target = Self::parse_change_target(&format!("{trail}/set"))
+                .ok()
+                .filter(|target| target.direction != AlertDirection::Cross)
+                .ok_or_else(|| anyhow::anyhow!("Invalid trailing alert: {target} (use e.g. trail-10% or trail+10%)"))?;
+            return Ok(AlertTarget { kind: AlertKind::Trailing, ..target });
+        }
+        for (prefix, kind) in [("ratio(", AlertKind::Ratio), ("basis(", AlertKind::Basis)] {
+            if let Some(pair) = target.strip_prefix(prefix) {
+                return Self::parse_pair_target(kind, pair, target);
+            }
+        }
+        if target.contains('(') {
+            let condition = target.parse::<IndicatorCondition>()?;
+            return Ok(AlertTarget {
+                kind: AlertKind::Indicator,
+                expression: Some(condition.to_string()),
+                ..AlertTarget::price(condition.direction, 0.0)
+            });
+        }
+        if let Some(spread) = target.strip_prefix("spread") {
+            let (direction, bps) = Self::parse_threshold_direction(spread)?;
+            let bps = Self::parse_number(bps.strip_suffix("bps").unwrap_or(bps), target)?;
+            return Ok(AlertTarget::metric(AlertKind::Spread, direction, bps));
+        }
+        for (prefix, kind) in [("biddepth", AlertKind::BidDepth), ("askdepth", AlertKind::AskDepth)] {
+            if let Some(depth) = target.strip_prefix(prefix) {
+                let (band, threshold) = depth
+                    .split_once('%')
+                    .ok_or_else(|| anyhow::anyhow!("Invalid depth: {target} (use e.g. {prefix}1%<50000)"))?;
+                let band_pct = Self::parse_number(band, target)?;
+                let (direction, notional) = Self::parse_threshold_direction(threshold)?;
+                return Ok(AlertTarget {
+                    change_pct: Some(band_pct),
+                    ..AlertTarget::metric(kind, direction, Self::parse_number(notional, target)?)
+                });
+            }
+        }
+        if let Some(level) = target.strip_prefix("level>") {
+            return Ok(AlertTarget::metric(AlertKind::LargeLevel, AlertDirection::Above, Self::parse_number(level, target)?));
+        }
+        if let Some(funding) = target.strip_prefix("funding") {
+            return Self::parse_funding_target(funding);
+        }
+        if let Some(premium) = target.strip_prefix("premium") {
+            return Self::parse_premium_target(premium);
+        }
+        if let Some(open_interest) = target.strip_prefix("oi") {
+            let period = if open_interest.contains('/') { "" } else { "/1h" };
+            let target = Self::parse_change_target(&format!("{open_interest}{period}"))?;
+            let window_minutes = match target.kind {
+                AlertKind::WindowChange => target.window_minutes,
+                AlertKind::DayChange => Some(MAX_WINDOW_MINUTES),
+                _ => anyhow::bail!("Open interest alerts need a window, e.g. oi+10%/1h"),
+            };
+            return Ok(AlertTarget {
+                kind: AlertKind::OpenInterestChange,
+                window_minutes,
+                ..target
+            });
+        }
+        if target.contains('%') {
+            return Self::parse_change_target(target);
+        }
+        let (direction, price) = if let Some(price) = target.strip_prefix('>') {
+            (AlertDirection::Above, price)
+        } else if let Some(price) = target.strip_prefix('<') {
+            (AlertDirection::Below, price)
+        } else {
+            (AlertDirection::Cross, target)
+        };
+        let price = price
+            .parse::<f64>()
+            .map_err(|_| anyhow::anyhow!("Invalid price: {target}"))?;
+        Ok(AlertTarget::price(direction, price))
+    }
+
+    fn parse_change_target(target: &str) -> anyhow::Result<AlertTarget> {
+        let (change, period) = target.split_once('/').unwrap_or((target, "24h"));
+        let (direction, change) = if let Some(change) = change.strip_prefix('+') {
+            (AlertDirection::Above, change)
+        } else if let Some(change) = change.strip_prefix('-') {
+            (AlertDirection::Below, change)
+        } else {
+            (AlertDirection::Cross, change.strip_prefix('±').unwrap_or(change))
+        };
+        let change_pct = change
+            .strip_suffix('%')
+            .and_then(|change| change.parse::<f64>().ok())
+            .filter(|change| *change > 0.0)
+            .ok_or_else(|| anyhow::anyhow!("Invalid percentage: {target}"))?;
+
+        let (kind, window_minutes) = match period {
+            "24h" => (AlertKind::DayChange, None),
+            "set" => (AlertKind::FromSet, None),
+            _ => {
+                let minutes = if let Some(minutes) = period.strip_suffix('m') {
+                    minutes.parse::<i64>().ok()
+                } else if let Some(hours) = period.strip_suffix('h') {
+                    hours.parse::<i64>().ok().map(|hours| hours * 60)
+                } else {
+                    None
+                };
+                let minutes = minutes
+                    .filter(|minutes| (1..=MAX_WINDOW_MINUTES).contains(minutes))
+                    .ok_or_else(|| anyhow::anyhow!("Invalid window: {period} (use e.g. 15m, 4h, 24h or set)"))?;
+                (AlertKind::WindowChange, Some(minutes))
+            }
+        };
+        Ok(AlertTarget {
+            kind,
+            direction,
+            price: 0.0,
+            change_pct: Some(change_pct),
+            window_minutes,
+            expression: None,
+        })
+    }
+
+    /// Parses `BTC-PERP)<0.05` after `ratio(` or `HYPE-PERP)>50bps` after
+    /// `basis(`.
+    fn parse_pair_target(kind: AlertKind, pair: &str, target: &str) -> anyhow::Result<AlertTarget> {
+        let (coin, threshold) = pair
+            .split_once(')')
+            .filter(|(coin, _)| !coin.is_empty())
+            .ok_or_else(|| anyhow::anyhow!("Invalid pair: {target} (use e.g. ratio(BTC-PERP)<0.05 or basis(HYPE-PERP)>50bps)"))?;
+        let (direction, value) = Self::parse_threshold_direction(threshold)?;
+        let value = match kind {
+            AlertKind::Basis => value
+                .strip_suffix("bps")
+                .and_then(|bps| bps.parse::<f64>().ok())
+                .ok_or_else(|| anyhow::anyhow!("Invalid basis: {target} (use e.g. basis(HYPE-PERP)>50bps)"))?,
+            _ => Self::parse_number(value, target)?,
+        };
+        Ok(AlertTarget {
+            expression: Some(coin.to_string()),
+            ..AlertTarget::metric(kind, direction, value)
+        })
+    }
+
+    /// Parses a `/setrearm` mode: `once`, `cooldown 15m` (seconds, minutes,
+    /// hours or days) or `band 1%`.
+    pub fn parse_rearm_mode(mode: &str) -> anyhow::Result<RearmMode> {
+        let mut words = mode.split_whitespace();
+        let rearm = match (words.next(), words.next()) {
+            (Some("once"), None) => RearmMode::OneShot,
+            (Some("cooldown"), Some(duration)) => RearmMode::Cooldown(
+                Self::parse_duration_secs(duration)
+                    .ok_or_else(|| anyhow::anyhow!("Invalid cooldown: {duration} (use e.g. 30s, 15m, 4h or 1d)"))?,
+            ),
+            (Some("band"), Some(band)) => {
+                let band_pct = band
+                    .strip_suffix('%')
+                    .and_then(|band| band.parse::<f64>().ok())
+                    .filter(|band| *band > 0.0)
+                    .ok_or_else(|| anyhow::anyhow!("Invalid band: {band} (use e.g. band 1%)"))?;
+                RearmMode::Band(band_pct)
+            }
+            _ => anyhow::bail!("Invalid re-arm mode: {mode} (use once, cooldown 15m or band 1%)"),
+        };
+        if words.next().is_some() {
+            anyhow::bail!("Invalid re-arm mode: {mode} (use once, cooldown 15m or band 1%)");
+        }
+        Ok(rearm)
+    }
+
+    /// Parses a positive duration such as `30s`, `15m`, `4h` or `7d` into
+    /// seconds.
+    fn parse_duration_secs(duration: &str) -> Option<i64> {
+        let unit_secs = match duration.chars().last()? {
+            's' => 1,
+            'm' => 60,
+            'h' => 3_600,
+            'd' => 86_400,
+            _ => return None,
+        };
+        let amount = duration[..duration.len() - 1].parse::<i64>().ok().filter(|amount| *amount > 0)?;
+        Some(amount * unit_secs)
+    }
+
+    /// Parses a `/setexpiry` time: a duration from now such as `12h` or
+    /// `7d`, a date or date and time in `timezone` such as `2026-12-31` or
+    /// `2026-12-31 18:00`, or `never`.
+    pub fn parse_expiry(expiry: &str, timezone: Tz) -> anyhow::Result<Option<DateTime<Utc>>> {
+        let expiry = expiry.trim();
+        if expiry == "never" {
+            return Ok(None);
+        }
+        if let Some(secs) = Self::parse_duration_secs(expiry) {
+            return Ok(Some(Utc::now() + Duration::seconds(secs)));
+        }
+        let expires_at = NaiveDateTime::parse_from_str(expiry, "%Y-%m-%d %H:%M")
+            .or_else(|_| NaiveDate::parse_from_str(expiry, "%Y-%m-%d").map(|date| date.and_time(NaiveTime::MIN)))
+            .map_err(|_| anyhow::anyhow!("Invalid expiry: {expiry} (use e.g. 12h, 7d, 2026-12-31, 2026-12-31 18:00 or never)"))?;
+        let expires_at = timezone
+            .from_local_datetime(&expires_at)
+            .earliest()
+            .ok_or_else(|| anyhow::anyhow!("{expiry} does not exist in {timezone} (clocks skip it)"))?
+            .with_timezone(&Utc);
+        if expires_at <= Utc::now() {
+            anyhow::bail!("{expiry} is in the past");
+        }
+        Ok(Some(expires_at))
+    }
+
+    /// Parses `>0.01%` or `<-10%apr` after `funding` into an hourly rate.
+    fn parse_funding_target(funding: &str) -> anyhow::Result<AlertTarget> {
+        let (direction, rate) = Self::parse_threshold_direction(funding)?;
+        let (rate, annualised) = match rate.strip_suffix("apr") {
+            Some(rate) => (rate, true),
+            None => (rate, false),
+        };
+        let rate = rate
+            .strip_suffix('%')
+            .and_then(|rate| rate.parse::<f64>().ok())
+            .ok_or_else(|| anyhow::anyhow!("Invalid funding rate: {funding} (use e.g. funding>0.01% or funding>10%apr)"))?;
+        let hourly_rate = if annualised { rate / HOURS_PER_YEAR } else { rate };
+        Ok(AlertTarget::metric(AlertKind::Funding, direction, hourly_rate))
+    }
+
+    /// Parses `>20bps` or `<-20bps` after `premium`.
+    fn parse_premium_target(premium: &str) -> anyhow::Result<AlertTarget> {
+        let (direction, bps) = Self::parse_threshold_direction(premium)?;
+        let bps = bps
+            .strip_suffix("bps")
+            .and_then(|bps| bps.parse::<f64>().ok())
+            .ok_or_else(|| anyhow::anyhow!("Invalid premium: {premium} (use e.g. premium>20bps)"))?;
+        Ok(AlertTarget::metric(AlertKind::Premium, direction, bps))
+    }
+
+    fn parse_number(value: &str, target: &str) -> anyhow::Result<f64> {
+        value
+            .parse::<f64>()
+            .ok()
+            .filter(|value| *value > 0.0)
+            .ok_or_else(|| anyhow::anyhow!("Invalid number in {target}: {value}"))
+    }
+
+    fn parse_threshold_direction(threshold: &str) -> anyhow::Result<(AlertDirection, &str)> {
+        if let Some(value) = threshold.strip_prefix('>') {
+            Ok((AlertDirection::Above, value))
+        } else if let Some(value) = threshold.strip_prefix('<') {
+            Ok((AlertDirection::Below, value))
+        } else {
+            Err(anyhow::anyhow!("Use > or < to set which way the threshold is crossed: {threshold}"))
+        }
+    }
 
-        self.db.insert_alert(public_key, chat_id, coin, &token, price).await
+    /// Fetches the current context of every perp, by token.
+    pub async fn fetch_perp_asset_contexts(&self) -> anyhow::Result<HashMap<String, PerpAssetContext>> {
+        reports::perp_asset_contexts(&self.info_client).await
     }
 
-    async fn get_token(&self, coin: &str) -> anyhow::Result<String> {
-        let spot_meta = self.info_client.lock().await.spot_meta().await?;
-        let universe = spot_meta.universe;
-        let tokens = spot_meta.tokens;
-        let token_index = tokens.iter().find(|t| t.name == coin).unwrap().index;
-        let token = universe.iter().find(|t| t.tokens[0] == token_index).unwrap().name.clone();
-        Ok(token)
+    /// Perp tokens with funding, premium or open interest alerts, whose
+    /// previous values are worth seeding at startup.
+    pub async fn get_perp_metric_tokens(&self) -> Result<Vec<String>> {
+        let mut tokens: Vec<String> = self
+            .db
+            .get_all_alerts()
+            .await?
+            .into_iter()
+            .filter(|alert| alert.is_active && alert.kind.is_perp_only())
+            .map(|alert| alert.token)
+            .collect();
+        tokens.sort();
+        tokens.dedup();
+        Ok(tokens)
     }
 }
@@ -0,0 +1,131 @@
+use crate::db::MarketType;
+use chrono::{DateTime, Duration, Utc};
+use hyperliquid_rust_sdk::{InfoClient, Meta, SpotMeta};
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use tokio::sync::Mutex;
+
+/// A lookup that misses refreshes the registry at most this often, so a
+/// coin listed since the last timed refresh still resolves.
+const MIN_REFRESH_SECS: i64 = 30;
+/// Spot tokens quoted in this are preferred when a bare token name has
+/// several pairs.
+const PREFERRED_QUOTE: &str = "USDC";
+
+/// A coin that is neither a perp nor a spot pair or token on Hyperliquid.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownCoin(pub String);
+
+impl std::fmt::Display for UnknownCoin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Unknown coin: {} (use e.g. HYPE, HYPE/USDC, @107 or BTC-PERP)", self.0)
+    }
+}
+
+impl std::error::Error for UnknownCoin {}
+
+/// Maps the names users type to the market and token the websocket feeds
+/// use, built from `meta()` and `spot_meta()`. Lookups are
+/// case-insensitive and accept a perp as `BTC-PERP`, a spot pair by name
+/// (`@107`) or as `HYPE/USDC`, and a spot token by name (`HYPE`), which
+/// resolves to its USDC pair.
+#[derive(Clone)]
+pub struct AssetRegistry {
+    info_client: Arc<Mutex<InfoClient>>,
+    assets: Arc<RwLock<HashMap<String, (MarketType, String)>>>,
+    // When the registry was last loaded or a refresh was last attempted.
+    refreshed_at: Arc<RwLock<Option<DateTime<Utc>>>>,
+}
+
+impl AssetRegistry {
+    pub fn new(info_client: Arc<Mutex<InfoClient>>) -> Self {
+        Self {
+            info_client,
+            assets: Arc::new(RwLock::new(HashMap::new())),
+            refreshed_at: Arc::new(RwLock::new(None)),
+        }
+    }
+
+    /// Re-fetches the perp and spot metadata.
+    pub async fn refresh(&self) -> anyhow::Result<()> {
+        let (meta, spot_meta) = {
+            let info_client = self.info_client.lock().await;
+            (info_client.meta().await?, info_client.spot_meta().await?)
+        };
+        self.load(&meta, &spot_meta);
+        Ok(())
+    }
+
+    /// Replaces the registry with the markets in `meta` and `spot_meta`.
+    pub fn load(&self, meta: &Meta, spot_meta: &SpotMeta) {
+        let mut assets = HashMap::new();
+        for asset in meta.universe.iter().filter(|asset| !asset.is_delisted) {
+            assets.insert(format!("{}-PERP", asset.name).to_uppercase(), (MarketType::Perp, asset.name.clone()));
+        }
+
+        let pairs: HashMap<u32, &str> = spot_meta
+            .universe
+            .iter()
+            .map(|pair| (10_000 + pair.index as u32, pair.name.as_str()))
+            .collect();
+        for (name, index) in spot_meta.add_pair_and_name_to_index_map(HashMap::new()) {
+            if let Some(pair) = pairs.get(&index) {
+                assets.insert(name.to_uppercase(), (MarketType::Spot, pair.to_string()));
+            }
+        }
+
+        for token in &spot_meta.tokens {
+            let quoted_in = |quote: Option<&str>| {
+                spot_meta
+                    .universe
+                    .iter()
+                    .filter(|pair| pair.tokens[0] == token.index)
+                    .filter(|pair| {
+                        quote.is_none_or(|quote| spot_meta.tokens.iter().any(|t| t.index == pair.tokens[1] && t.name == quote))
+                    })
+                    .min_by_key(|pair| pair.index)
+            };
+            if let Some(pair) = quoted_in(Some(PREFERRED_QUOTE)).or_else(|| quoted_in(None)) {
+                assets
+                    .entry(token.name.to_uppercase())
+                    .or_insert((MarketType::Spot, pair.name.clone()));
+            }
+        }
+
+        *self.assets.write().unwrap() = assets;
+        *self.refreshed_at.write().unwrap() = Some(Utc::now());
+    }
+
+    /// Looks `coin` up in the registry as last loaded.
+    pub fn lookup(&self, coin: &str) -> Result<(MarketType, String), UnknownCoin> {
+        self.assets
+            .read()
+            .unwrap()
+            .get(&coin.trim().to_uppercase())
+            .cloned()
+            .ok_or_else(|| UnknownCoin(coin.to_string()))
+    }
+
+    /// Looks `coin` up, refreshing the registry first if it misses and was
+    /// not refreshed recently.
+    pub async fn resolve(&self, coin: &str) -> Result<(MarketType, String), UnknownCoin> {
+        let unknown = match self.lookup(coin) {
+            Ok(asset) => return Ok(asset),
+            Err(unknown) => unknown,
+        };
+        let stale = self
+            .refreshed_at
+            .read()
+            .unwrap()
+            .is_none_or(|refreshed_at| Utc::now() - refreshed_at >= Duration::seconds(MIN_REFRESH_SECS));
+        if !stale {
+            return Err(unknown);
+        }
+        *self.refreshed_at.write().unwrap() = Some(Utc::now());
+        if let Err(e) = self.refresh().await {
+            log::error!("Failed to refresh asset registry: {e}");
+            return Err(unknown);
+        }
+        self.lookup(coin)
+    }
+}
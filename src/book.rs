@@ -0,0 +1,72 @@
+use hyperliquid_rust_sdk::{BboData, BookLevel, L2BookData};
+
+/// One side of a book as `(px, sz)` levels, best price first.
+pub type Levels = Vec<(f64, f64)>;
+
+fn parse_level(level: &BookLevel) -> Option<(f64, f64)> {
+    Some((level.px.parse().ok()?, level.sz.parse().ok()?))
+}
+
+/// An order book snapshot built from an `L2Book` or `Bbo` update. `Bbo`
+/// updates only carry the top level of each side.
+#[derive(Debug, Clone, Default)]
+pub struct OrderBook {
+    pub bids: Levels,
+    pub asks: Levels,
+}
+
+impl OrderBook {
+    pub fn from_l2_book(data: &L2BookData) -> Self {
+        let side = |index: usize| -> Levels {
+            data.levels
+                .get(index)
+                .map(|levels| levels.iter().filter_map(parse_level).collect())
+                .unwrap_or_default()
+        };
+        Self { bids: side(0), asks: side(1) }
+    }
+
+    pub fn from_bbo(data: &BboData) -> Self {
+        let side = |index: usize| -> Levels {
+            data.bbo
+                .get(index)
+                .and_then(|level| level.as_ref())
+                .and_then(parse_level)
+                .into_iter()
+                .collect()
+        };
+        Self { bids: side(0), asks: side(1) }
+    }
+
+    fn best(&self) -> Option<(f64, f64)> {
+        Some((self.bids.first()?.0, self.asks.first()?.0))
+    }
+
+    pub fn mid_px(&self) -> Option<f64> {
+        self.best().map(|(bid, ask)| (bid + ask) / 2.0)
+    }
+
+    /// Spread between the best bid and ask, in bps of the mid price.
+    pub fn spread_bps(&self) -> Option<f64> {
+        let (bid, ask) = self.best()?;
+        let mid = (bid + ask) / 2.0;
+        Some((ask - bid) / mid * 10_000.0)
+    }
+
+    /// Notional resting on the bids within `band_pct` below the mid price.
+    pub fn bid_depth(&self, band_pct: f64) -> Option<f64> {
+        let floor = self.mid_px()? * (1.0 - band_pct / 100.0);
+        Some(self.bids.iter().filter(|(px, _)| *px >= floor).map(|(px, sz)| px * sz).sum())
+    }
+
+    /// Notional resting on the asks within `band_pct` above the mid price.
+    pub fn ask_depth(&self, band_pct: f64) -> Option<f64> {
+        let ceiling = self.mid_px()? * (1.0 + band_pct / 100.0);
+        Some(self.asks.iter().filter(|(px, _)| *px <= ceiling).map(|(px, sz)| px * sz).sum())
+    }
+
+    /// Size of the largest level on either side.
+    pub fn largest_level(&self) -> Option<f64> {
+        self.bids.iter().chain(&self.asks).map(|(_, sz)| *sz).reduce(f64::max)
+    }
+}
@@ -1,36 +1,133 @@
-use crate::db::{CronAlert, Database};
-use chrono::{DateTime, Utc};
+use crate::assets::AssetRegistry;
+use crate::db::{CronAlert, CronAlertKind, CronDelivery, Database, MisfirePolicy, ReportTemplate};
+use crate::reports::{self, MarketReport, PortfolioReport, MAX_REPORT_COINS};
+use chrono::{DateTime, Duration, NaiveTime, Timelike, Utc, Weekday};
+use chrono_tz::Tz;
 use hyperliquid_rust_sdk::InfoClient;
 use rusqlite::Result;
 use std::sync::Arc;
 use teloxide::types::ChatId;
-use tokio::sync::Mutex;
+use tokio::sync::{Mutex, Notify};
+
+/// A fire more than this late counts as missed, and is handled by the cron
+/// alert's misfire policy.
+pub const MISFIRE_GRACE_SECS: i64 = 60;
+/// Most missed fires a `all` misfire policy catches up with.
+pub const MAX_CATCH_UP_FIRES: usize = 24;
+/// Most missed fires looked at when catching up, so a minutely schedule
+/// after a long downtime does not take long to plan.
+const MAX_SCANNED_FIRES: usize = 10_000;
+/// Failed deliveries are retried until they have had this many attempts.
+pub const MAX_DELIVERY_ATTEMPTS: i64 = 3;
+/// Least time between two attempts of a failed delivery.
+const RETRY_SECS: i64 = 60;
+/// Longest the worker sleeps, even with no cron alert due before then.
+const MAX_IDLE_SECS: i64 = 3_600;
+/// Shortest the worker sleeps, so an alert stuck in the past can't make it
+/// spin.
+const MIN_WAIT_SECS: i64 = 1;
+
+/// What a due cron alert sends now, and when it is next due.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FirePlan {
+    pub fires: Vec<DateTime<Utc>>,
+    /// Missed fires the misfire policy drops.
+    pub skipped: usize,
+    pub next_trigger: DateTime<Utc>,
+}
 
 #[derive(Clone)]
 pub struct CronService {
     db: Database,
     info_client: Arc<Mutex<InfoClient>>,
+    assets: AssetRegistry,
+    // Wakes the worker when a cron alert is added or rescheduled.
+    changed: Arc<Notify>,
 }
 
 impl CronService {
-    pub fn new(db: Database, info_client: Arc<Mutex<InfoClient>>) -> Self {
-        Self { db, info_client }
+    pub fn new(db: Database, info_client: Arc<Mutex<InfoClient>>, assets: AssetRegistry) -> Self {
+        Self {
+            db,
+            info_client,
+            assets,
+            changed: Arc::new(Notify::new()),
+        }
     }
 
+    /// Schedules a report on `coins`, a comma-separated watchlist such as
+    /// `HYPE,BTC-PERP`.
     pub async fn create_cron_alert(
         &self,
         chat_id: ChatId,
-        coin: &str,
+        coins: &str,
         cron_schedule: &str,
-    ) -> Result<()> {
-        // Insert into database
-        let token = self.get_token(coin).await.unwrap();
+        template: ReportTemplate,
+    ) -> anyhow::Result<()> {
+        let coins: Vec<&str> = coins.split(',').map(str::trim).filter(|coin| !coin.is_empty()).collect();
+        if coins.is_empty() || coins.len() > MAX_REPORT_COINS {
+            anyhow::bail!("A cron alert covers 1 to {MAX_REPORT_COINS} coins");
+        }
+        let mut tokens = Vec::with_capacity(coins.len());
+        for coin in &coins {
+            tokens.push(self.assets.resolve(coin).await?.1);
+        }
+        let timezone = self.db.get_chat_timezone(chat_id).await?;
+        let next_trigger = Self::next_trigger(cron_schedule, timezone, Utc::now())?;
         self.db
-            .insert_cron_alert(chat_id, coin, &token, cron_schedule)
+            .insert_cron_alert(chat_id, &coins.join(","), &tokens.join(","), cron_schedule, template, next_trigger)
             .await?;
+        self.changed.notify_one();
+        Ok(())
+    }
+
+    /// Creates a cron alert that sends a portfolio summary of `address`.
+    pub async fn create_portfolio_alert(&self, chat_id: ChatId, address: &str, cron_schedule: &str) -> anyhow::Result<()> {
+        let timezone = self.db.get_chat_timezone(chat_id).await?;
+        let next_trigger = Self::next_trigger(cron_schedule, timezone, Utc::now())?;
+        self.db.insert_portfolio_cron_alert(chat_id, address, cron_schedule, next_trigger).await?;
+        self.changed.notify_one();
+        Ok(())
+    }
+
+    /// The timezone `chat_id` evaluates schedules and shows times in.
+    pub async fn timezone(&self, chat_id: ChatId) -> Result<Tz> {
+        self.db.get_chat_timezone(chat_id).await
+    }
+
+    /// Sets the timezone of `chat_id` and reschedules its cron alerts in it.
+    pub async fn set_timezone(&self, chat_id: ChatId, timezone: Tz) -> anyhow::Result<()> {
+        self.db.set_chat_timezone(chat_id, timezone).await?;
+        let now = Utc::now();
+        for cron_alert in self.db.get_cron_alerts_for_chat(chat_id).await? {
+            let next_trigger = Self::next_trigger(&cron_alert.cron_schedule, timezone, now)?;
+            self.db.update_cron_alert_next_trigger(cron_alert.id, next_trigger).await?;
+        }
+        self.changed.notify_one();
         Ok(())
     }
 
+    /// Sets what a cron alert of `chat_id` does about missed fires. Returns
+    /// `false` if the chat has no cron alert with that id.
+    pub async fn set_misfire_policy(&self, alert_id: i64, chat_id: ChatId, misfire: MisfirePolicy) -> Result<bool> {
+        Ok(self.db.set_cron_alert_misfire(alert_id, chat_id, misfire).await? > 0)
+    }
+
+    /// Resolves when a cron alert was added or rescheduled, so the worker
+    /// can stop sleeping towards a later trigger.
+    pub async fn changed(&self) {
+        self.changed.notified().await
+    }
+
+    /// The first time after `after` that `cron_schedule` fires on the wall
+    /// clock of `timezone`. Across a DST change, times that occur twice fire
+    /// once, at the first, and times that are skipped do not fire that day.
+    pub fn next_trigger(cron_schedule: &str, timezone: Tz, after: DateTime<Utc>) -> anyhow::Result<DateTime<Utc>> {
+        let next_trigger = cron_parser::parse(cron_schedule.trim(), &after.with_timezone(&timezone))
+            .map_err(|e| anyhow::anyhow!("Invalid schedule: {cron_schedule} ({e})"))?;
+        Ok(next_trigger.with_timezone(&Utc))
+    }
+
     pub async fn get_all_cron_alerts(&self) -> Result<Vec<CronAlert>> {
         self.db.get_all_cron_alerts().await
     }
@@ -43,79 +140,217 @@ impl CronService {
         self.db.deactivate_cron_alert(alert_id).await
     }
 
-    pub async fn delete_cron_alert(&self, alert_id: i64) -> Result<()> {
-        self.db.delete_cron_alert(alert_id).await
+    /// Deletes a cron alert owned by `chat_id`. Returns `false` if the chat
+    /// has no cron alert with that id.
+    pub async fn delete_cron_alert(&self, alert_id: i64, chat_id: ChatId) -> Result<bool> {
+        Ok(self.db.delete_cron_alert(alert_id, chat_id).await? > 0)
     }
 
-    pub async fn get_triggered_cron_alerts(&self) -> Result<Vec<CronAlert>> {
-        self.db.get_next_trigger_cron_alerts().await
+    /// Decides which of the fires due since `cron_alert.next_trigger` to
+    /// send at `now`. Fires within `MISFIRE_GRACE_SECS` are on time and
+    /// always sent; older ones are missed and handled by its misfire policy.
+    pub fn plan_fires(cron_alert: &CronAlert, timezone: Tz, now: DateTime<Utc>) -> anyhow::Result<FirePlan> {
+        let schedule = &cron_alert.cron_schedule;
+        let mut due = Vec::new();
+        let mut next_trigger = match cron_alert.next_trigger {
+            Some(next_trigger) => next_trigger,
+            None => Self::next_trigger(schedule, timezone, now)?,
+        };
+        while next_trigger <= now && due.len() < MAX_SCANNED_FIRES {
+            due.push(next_trigger);
+            next_trigger = Self::next_trigger(schedule, timezone, next_trigger)?;
+        }
+        if next_trigger <= now {
+            next_trigger = Self::next_trigger(schedule, timezone, now)?;
+        }
+
+        let grace = Duration::seconds(MISFIRE_GRACE_SECS);
+        let fires: Vec<DateTime<Utc>> = match cron_alert.misfire {
+            MisfirePolicy::FireOnce => due.last().copied().into_iter().collect(),
+            MisfirePolicy::FireAll => due[due.len().saturating_sub(MAX_CATCH_UP_FIRES)..].to_vec(),
+            MisfirePolicy::Skip => due.iter().copied().filter(|at| now - *at <= grace).collect(),
+        };
+        Ok(FirePlan {
+            skipped: due.len() - fires.len(),
+            fires,
+            next_trigger,
+        })
     }
 
-    pub async fn mark_cron_alert_triggered(
-        &self,
-        alert_id: i64,
-        next_trigger: DateTime<Utc>,
-    ) -> Result<()> {
-        self.db
-            .update_cron_alert_last_triggered(alert_id, next_trigger)
-            .await
-    }
-
-    pub async fn get_price(&self, token: &str) -> anyhow::Result<f64> {
-        let all_mids = self.info_client.lock().await.all_mids().await?;
-        let price = all_mids.get(token).unwrap().parse::<f64>().unwrap();
-
-        Ok(price)
-    }
-
-    async fn get_token(&self, coin: &str) -> anyhow::Result<String> {
-        let spot_meta = self.info_client.lock().await.spot_meta().await?;
-        let universe = spot_meta.universe;
-        let tokens = spot_meta.tokens;
-        let token_index = tokens.iter().find(|t| t.name == coin).unwrap().index;
-        let token = universe
-            .iter()
-            .find(|t| t.tokens[0] == token_index)
-            .unwrap()
-            .name
-            .clone();
-        println!("Token: {token}");
-        Ok(token)
-    }
-
-    pub async fn create_schedule(&self, schedule: &str, time: &str) -> anyhow::Result<String> {
-        // time format is HH:MM
-        let time = time.split(":").collect::<Vec<&str>>();
-        let hour = time[0].parse::<i32>().unwrap();
-        let minute = time[1].parse::<i32>().unwrap();
-
-        match schedule {
-            "daily" => {
-                let cron_schedule = format!("{} {} * * *", minute, hour);
-                Ok(cron_schedule)
+    /// Claims the fires of every due cron alert, moving each to its next
+    /// trigger, and failed deliveries last tried at least `RETRY_SECS` ago.
+    /// Each returned delivery has to be completed or failed once sent or
+    /// not. Alerts whose schedule can't be planned are deactivated, as they
+    /// would otherwise stay due forever.
+    pub async fn claim_due(&self) -> anyhow::Result<Vec<(CronAlert, CronDelivery)>> {
+        let now = Utc::now();
+        let mut claimed = Vec::new();
+        for cron_alert in self.db.get_next_trigger_cron_alerts(now).await? {
+            let Some(due_at) = cron_alert.next_trigger else {
+                continue;
+            };
+            let timezone = self.db.get_chat_timezone(ChatId(cron_alert.chat_id)).await?;
+            let plan = match Self::plan_fires(&cron_alert, timezone, now) {
+                Ok(plan) => plan,
+                Err(e) => {
+                    log::error!("Failed to schedule cron alert {}, deactivating it: {e}", cron_alert.id);
+                    self.db.deactivate_cron_alert(cron_alert.id).await?;
+                    continue;
+                }
+            };
+            if plan.skipped > 0 {
+                log::info!("Skipping {} missed fires of cron alert {} ({} policy)", plan.skipped, cron_alert.id, cron_alert.misfire);
+            }
+            for delivery in self.db.claim_cron_deliveries(cron_alert.id, due_at, &plan.fires, plan.next_trigger).await? {
+                claimed.push((cron_alert.clone(), delivery));
             }
-            "monday" | "tuesday" | "wednesday" | "thursday" | "friday" | "saturday" | "sunday" => {
-                let day_map = [
-                    ("sunday", "0"),
-                    ("monday", "1"),
-                    ("tuesday", "2"),
-                    ("wednesday", "3"),
-                    ("thursday", "4"),
-                    ("friday", "5"),
-                    ("saturday", "6"),
-                ];
-                let schedule_lower = schedule.to_lowercase();
-
-                let schedule_num = day_map
-                    .iter()
-                    .find(|(day, _)| *day == schedule_lower.as_str())
-                    .map(|(_, num)| num)
-                    .expect("Invalid schedule");
-                let cron_schedule = format!("{} {} * * {}", minute, hour, schedule_num);
-                Ok(cron_schedule)
+        }
+        let retry_before = now - Duration::seconds(RETRY_SECS);
+        for delivery in self.db.claim_failed_cron_deliveries(MAX_DELIVERY_ATTEMPTS, retry_before).await? {
+            if let Some(cron_alert) = self.db.get_cron_alert(delivery.cron_alert_id).await? {
+                claimed.push((cron_alert, delivery));
             }
+        }
+        Ok(claimed)
+    }
+
+    /// Builds the message for a claimed delivery, noting when it was due if
+    /// it is late.
+    pub async fn message(&self, cron_alert: &CronAlert, delivery: &CronDelivery) -> anyhow::Result<String> {
+        let report = match cron_alert.kind {
+            CronAlertKind::Market => self.report(cron_alert).await?.to_string(),
+            CronAlertKind::Portfolio => self.portfolio_report(cron_alert).await?.to_string(),
+        };
+        if Utc::now() - delivery.scheduled_for <= Duration::seconds(MISFIRE_GRACE_SECS) {
+            return Ok(report);
+        }
+        let timezone = self.db.get_chat_timezone(ChatId(cron_alert.chat_id)).await?;
+        let scheduled_for = delivery.scheduled_for.with_timezone(&timezone);
+        Ok(format!("{report}\n(late, was due {})", scheduled_for.format("%Y-%m-%d %H:%M %Z")))
+    }
+
+    pub async fn complete_delivery(&self, delivery_id: i64) -> Result<()> {
+        self.db.complete_cron_delivery(delivery_id).await
+    }
+
+    pub async fn fail_delivery(&self, delivery_id: i64, error: &str) -> Result<()> {
+        self.db.fail_cron_delivery(delivery_id, error).await
+    }
+
+    /// Abandons deliveries a previous run claimed but never completed. They
+    /// may have been sent, so they are not retried. Returns how many there
+    /// were.
+    pub async fn abandon_interrupted_deliveries(&self) -> Result<usize> {
+        self.db.abandon_claimed_cron_deliveries().await
+    }
+
+    /// How long the worker can sleep before a cron alert is due or a failed
+    /// delivery should be retried.
+    pub async fn until_next_trigger(&self) -> Result<std::time::Duration> {
+        let mut wait = Duration::seconds(MAX_IDLE_SECS);
+        if let Some(next_trigger) = self.db.get_earliest_cron_trigger().await? {
+            wait = wait.min(next_trigger - Utc::now());
+        }
+        if self.db.has_failed_cron_deliveries(MAX_DELIVERY_ATTEMPTS).await? {
+            wait = wait.min(Duration::seconds(RETRY_SECS));
+        }
+        Ok(wait.max(Duration::seconds(MIN_WAIT_SECS)).to_std().unwrap_or_default())
+    }
+
+    /// Builds the report `cron_alert` sends, from current market data.
+    pub async fn report(&self, cron_alert: &CronAlert) -> anyhow::Result<MarketReport> {
+        let mut markets = Vec::new();
+        for coin in cron_alert.coins() {
+            let (market, token) = self.assets.resolve(coin).await?;
+            markets.push((coin.to_string(), market, token));
+        }
+        let stats = reports::market_stats(&self.info_client, &markets, cron_alert.template).await?;
+        Ok(MarketReport {
+            template: cron_alert.template,
+            stats,
+        })
+    }
+
+    /// Fetches the portfolio summary of a portfolio cron alert's address,
+    /// with PnL and fees since midnight in the chat's timezone.
+    pub async fn portfolio_report(&self, cron_alert: &CronAlert) -> anyhow::Result<PortfolioReport> {
+        let timezone = self.db.get_chat_timezone(ChatId(cron_alert.chat_id)).await?;
+        let now = Utc::now();
+        let midnight = now.with_timezone(&timezone).date_naive().and_time(NaiveTime::MIN);
+        let since = midnight
+            .and_local_timezone(timezone)
+            .earliest()
+            .map_or(now - Duration::hours(24), |since| since.with_timezone(&Utc));
+        let address = cron_alert
+            .address
+            .as_deref()
+            .ok_or_else(|| anyhow::anyhow!("Cron alert {} has no address", cron_alert.id))?;
+        reports::portfolio_report(&self.info_client, address, since).await
+    }
+
+    /// Parses a `/setcronalert` schedule into a five-field cron expression,
+    /// evaluated in the chat's timezone. Accepts `hourly` (optionally at a
+    /// minute, `hourly :15`), `every 15m` or `every 4h`, `daily 08:00`, a day
+    /// name, `weekdays` or `weekends` followed by a time, `monthly <day>
+    /// <time>` and raw cron expressions such as `0 9 * * 1-5`.
+    pub fn parse_schedule(schedule: &str) -> anyhow::Result<String> {
+        let schedule = schedule.trim().to_lowercase();
+        let words: Vec<&str> = schedule.split_whitespace().collect();
+        let cron = match words.as_slice() {
+            ["hourly"] => "0 * * * *".to_string(),
+            ["hourly", minute] => {
+                let minute = minute
+                    .trim_start_matches(':')
+                    .parse::<u32>()
+                    .ok()
+                    .filter(|minute| *minute < 60)
+                    .ok_or_else(|| anyhow::anyhow!("Invalid minute: {minute} (use e.g. hourly :15)"))?;
+                format!("{minute} * * * *")
+            }
+            ["every", interval] => Self::parse_interval(interval)?,
+            ["daily", time] => Self::at_time(time, "*", "*")?,
+            ["weekdays", time] => Self::at_time(time, "*", "1-5")?,
+            ["weekends", time] => Self::at_time(time, "*", "0,6")?,
+            ["monthly", day, time] => {
+                let day = day
+                    .parse::<u32>()
+                    .ok()
+                    .filter(|day| (1..=31).contains(day))
+                    .ok_or_else(|| anyhow::anyhow!("Invalid day of month: {day} (use 1 to 31)"))?;
+                Self::at_time(time, &day.to_string(), "*")?
+            }
+            [day, time] if day.parse::<Weekday>().is_ok() => {
+                let day = day.parse::<Weekday>().unwrap().num_days_from_sunday();
+                Self::at_time(time, "*", &day.to_string())?
+            }
+            fields if fields.len() == 5 => fields.join(" "),
+            _ => anyhow::bail!(
+                "Invalid schedule: {schedule} (use e.g. hourly, every 4h, daily 08:00, monday 08:00, weekdays 09:30, monthly 1 09:00 or a cron expression such as 0 9 * * 1-5)"
+            ),
+        };
+        cron_parser::parse(&cron, &Utc::now()).map_err(|e| anyhow::anyhow!("Invalid schedule: {schedule} ({e})"))?;
+        Ok(cron)
+    }
+
+    /// The cron expression firing at `time` (`HH:MM`) on the given day of
+    /// month and day of week fields.
+    fn at_time(time: &str, day_of_month: &str, day_of_week: &str) -> anyhow::Result<String> {
+        let time = NaiveTime::parse_from_str(time, "%H:%M")
+            .map_err(|_| anyhow::anyhow!("Invalid time: {time} (use HH:MM, e.g. 08:00)"))?;
+        Ok(format!("{} {} {day_of_month} * {day_of_week}", time.minute(), time.hour()))
+    }
 
-            _ => Err(anyhow::anyhow!("Invalid schedule: {schedule}")),
+    /// Parses `15m` or `4h` after `every`. Intervals have to divide the hour
+    /// or the day, since cron steps restart at each one.
+    fn parse_interval(interval: &str) -> anyhow::Result<String> {
+        let invalid = || anyhow::anyhow!("Invalid interval: {interval} (use minutes that divide 60, e.g. 15m, or hours that divide 24, e.g. 4h)");
+        let divides = |amount: &str, period: u32| amount.parse::<u32>().ok().filter(|amount| (1..period).contains(amount) && period.is_multiple_of(*amount));
+        if let Some(minutes) = interval.strip_suffix('m').and_then(|minutes| divides(minutes, 60)) {
+            Ok(format!("*/{minutes} * * * *"))
+        } else if let Some(hours) = interval.strip_suffix('h').and_then(|hours| divides(hours, 24)) {
+            Ok(format!("0 */{hours} * * *"))
+        } else {
+            Err(invalid())
         }
     }
 }
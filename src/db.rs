@@ -1,9 +1,10 @@
-use rusqlite::{Connection, Result, params};
-use chrono::{DateTime, Utc};
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
+use rusqlite::{Connection, Result, Row, params, params_from_iter};
+use chrono::{DateTime, Datelike, NaiveTime, Utc, Weekday};
+use chrono_tz::Tz;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 use teloxide::types::ChatId;
-use cron_parser::parse;
 
 #[derive(Debug)]
 pub struct AlertTable {
@@ -12,14 +13,759 @@ pub struct AlertTable {
     pub chat_id: i64,
     pub coin: String,
     pub token: String,
+    /// The threshold of the alert. Expression alerts keep theirs in
+    /// `expression` and store no price.
     pub price: f64,
     pub alerted: bool,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
     pub cooldown_until: DateTime<Utc>,
+    pub direction: AlertDirection,
+    pub market: MarketType,
+    pub kind: AlertKind,
+    pub change_pct: Option<f64>,
+    pub window_minutes: Option<i64>,
+    pub expression: Option<String>,
+    pub rearm: RearmMode,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub active_window: Option<ActiveWindow>,
+    /// Cleared when the alert expires. Inactive alerts are kept for listing
+    /// but hold no subscriptions and never fire.
+    pub is_active: bool,
 }
 
-#[derive(Debug)]
+const WEEKDAYS: [Weekday; 5] = [Weekday::Mon, Weekday::Tue, Weekday::Wed, Weekday::Thu, Weekday::Fri];
+const WEEKENDS: [Weekday; 2] = [Weekday::Sat, Weekday::Sun];
+
+/// The hours of the day and days of the week an alert may fire in, in the
+/// timezone the window was set in.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ActiveWindow {
+    /// Start and end of the daily window. A window that ends before it
+    /// starts runs overnight.
+    pub hours: Option<(NaiveTime, NaiveTime)>,
+    /// Empty means every day.
+    pub days: Vec<Weekday>,
+    pub timezone: Tz,
+}
+
+impl ActiveWindow {
+    pub fn contains(&self, at: DateTime<Utc>) -> bool {
+        let at = at.with_timezone(&self.timezone);
+        if !self.days.is_empty() && !self.days.contains(&at.weekday()) {
+            return false;
+        }
+        let Some((start, end)) = self.hours else {
+            return true;
+        };
+        let time = at.time();
+        if start <= end {
+            start <= time && time < end
+        } else {
+            time >= start || time < end
+        }
+    }
+}
+
+impl std::str::FromStr for ActiveWindow {
+    type Err = anyhow::Error;
+
+    /// Parses `08:00-22:00`, `weekdays`, `weekends`, `sat,sun` or an hour
+    /// range followed by days, e.g. `08:00-22:00 weekdays`, optionally
+    /// followed by a timezone, e.g. `08:00-22:00 weekdays Europe/London`.
+    /// Windows without one are in UTC.
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let mut window = ActiveWindow { hours: None, days: Vec::new(), timezone: Tz::UTC };
+        let mut parts: Vec<&str> = s.split_whitespace().collect();
+        if let Some(timezone) = parts.last().and_then(|part| part.parse::<Tz>().ok()) {
+            window.timezone = timezone;
+            parts.pop();
+        }
+        for part in parts {
+            if let Some((start, end)) = part.split_once('-').filter(|_| window.hours.is_none() && part.contains(':')) {
+                let parse = |time: &str| {
+                    NaiveTime::parse_from_str(time, "%H:%M").map_err(|_| anyhow::anyhow!("Invalid time: {time} (use e.g. 08:00-22:00)"))
+                };
+                window.hours = Some((parse(start)?, parse(end)?));
+            } else if window.days.is_empty() {
+                window.days = match part {
+                    "weekdays" => WEEKDAYS.to_vec(),
+                    "weekends" => WEEKENDS.to_vec(),
+                    days => days
+                        .split(',')
+                        .map(|day| day.parse::<Weekday>().map_err(|_| anyhow::anyhow!("Invalid day: {day} (use e.g. weekdays, weekends or mon,wed,fri)")))
+                        .collect::<anyhow::Result<Vec<_>>>()?,
+                };
+            } else {
+                anyhow::bail!("Invalid window: {s} (use e.g. 08:00-22:00 weekdays)");
+            }
+        }
+        if window.hours.is_none() && window.days.is_empty() {
+            anyhow::bail!("Invalid window: {s} (use e.g. 08:00-22:00 weekdays)");
+        }
+        Ok(window)
+    }
+}
+
+impl std::fmt::Display for ActiveWindow {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut parts = Vec::new();
+        if let Some((start, end)) = self.hours {
+            parts.push(format!("{}-{}", start.format("%H:%M"), end.format("%H:%M")));
+        }
+        if self.days == WEEKDAYS {
+            parts.push("weekdays".to_string());
+        } else if self.days == WEEKENDS {
+            parts.push("weekends".to_string());
+        } else if !self.days.is_empty() {
+            let days: Vec<String> = self.days.iter().map(|day| day.to_string().to_lowercase()).collect();
+            parts.push(days.join(","));
+        }
+        if self.timezone != Tz::UTC {
+            parts.push(self.timezone.name().to_string());
+        }
+        write!(f, "{}", parts.join(" "))
+    }
+}
+
+impl ToSql for ActiveWindow {
+    fn to_sql(&self) -> Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.to_string()))
+    }
+}
+
+impl FromSql for ActiveWindow {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        value.as_str()?.parse().map_err(|e: anyhow::Error| FromSqlError::Other(e.into()))
+    }
+}
+
+/// Cooldown of alerts that were never given a re-arm mode.
+pub const DEFAULT_COOLDOWN_SECS: i64 = 60;
+
+/// How an alert is re-armed after it fires.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RearmMode {
+    /// Stays disabled after firing until it is edited.
+    OneShot,
+    /// Re-armed after this many seconds.
+    Cooldown(i64),
+    /// Re-armed once the price is this many percent away from the target.
+    /// Only price alerts can use it.
+    Band(f64),
+}
+
+impl RearmMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RearmMode::OneShot => "once",
+            RearmMode::Cooldown(_) => "cooldown",
+            RearmMode::Band(_) => "band",
+        }
+    }
+
+    /// The value stored next to the mode: seconds for cooldowns and a
+    /// percentage for bands.
+    pub fn value(&self) -> Option<f64> {
+        match self {
+            RearmMode::OneShot => None,
+            RearmMode::Cooldown(seconds) => Some(*seconds as f64),
+            RearmMode::Band(band_pct) => Some(*band_pct),
+        }
+    }
+
+    fn from_columns(mode: &str, value: Option<f64>) -> anyhow::Result<Self> {
+        match (mode, value) {
+            ("once", _) => Ok(RearmMode::OneShot),
+            ("cooldown", Some(seconds)) => Ok(RearmMode::Cooldown(seconds as i64)),
+            ("cooldown", None) => Ok(RearmMode::Cooldown(DEFAULT_COOLDOWN_SECS)),
+            ("band", Some(band_pct)) => Ok(RearmMode::Band(band_pct)),
+            _ => Err(anyhow::anyhow!("Invalid re-arm mode: {mode}")),
+        }
+    }
+}
+
+impl std::fmt::Display for RearmMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RearmMode::OneShot => write!(f, "one-shot"),
+            RearmMode::Cooldown(seconds) => {
+                let (amount, unit) = [(86_400, "d"), (3_600, "h"), (60, "m")]
+                    .into_iter()
+                    .find(|(unit_secs, _)| seconds % unit_secs == 0)
+                    .map(|(unit_secs, unit)| (seconds / unit_secs, unit))
+                    .unwrap_or((*seconds, "s"));
+                write!(f, "cooldown {amount}{unit}")
+            }
+            RearmMode::Band(band_pct) => write!(f, "re-arms {band_pct}% from target"),
+        }
+    }
+}
+
+/// Funding is paid hourly, so annualised rates are the hourly rate times this.
+pub const HOURS_PER_YEAR: f64 = 24.0 * 365.0;
+
+/// What an alert measures. Price alerts compare the mark price against
+/// `price`; the change kinds compare a percentage move against `change_pct`,
+/// with `direction` picking a rise, a drop or either.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlertKind {
+    Price,
+    /// Change against the previous day's price.
+    DayChange,
+    /// Change within the last `window_minutes`.
+    WindowChange,
+    /// Change from `price`, the mark price when the alert was set.
+    FromSet,
+    /// Perp funding crossing `price`, an hourly rate in percent.
+    Funding,
+    /// Perp open interest change within the last `window_minutes`.
+    OpenInterestChange,
+    /// Perp mark/oracle premium crossing `price`, in bps.
+    Premium,
+    /// Best bid/ask spread crossing `price`, in bps.
+    Spread,
+    /// Bid notional within `change_pct` below the mid crossing `price`.
+    BidDepth,
+    /// Ask notional within `change_pct` above the mid crossing `price`.
+    AskDepth,
+    /// A single book level of at least `price` in size appearing.
+    LargeLevel,
+    /// A candle indicator condition, stored in `expression`.
+    Indicator,
+    /// A composite condition over one or more markets, stored in
+    /// `expression`. `coin` and `token` name its first market.
+    Expression,
+    /// The mark of the alert's coin divided by the mark of the coin in
+    /// `expression`, crossing `price`.
+    Ratio,
+    /// The premium of the alert's coin over the coin in `expression`, in
+    /// bps of the latter, crossing `price`.
+    Basis,
+    /// A retrace of `change_pct` from `price`, the extreme reached since the
+    /// alert was set: the high for a drop (`Below`) and the low for a rise
+    /// (`Above`). `price` follows the mark as it makes new extremes.
+    Trailing,
+}
+
+impl AlertKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AlertKind::Price => "price",
+            AlertKind::DayChange => "day_change",
+            AlertKind::WindowChange => "window_change",
+            AlertKind::FromSet => "from_set",
+            AlertKind::Funding => "funding",
+            AlertKind::OpenInterestChange => "open_interest_change",
+            AlertKind::Premium => "premium",
+            AlertKind::Spread => "spread",
+            AlertKind::BidDepth => "bid_depth",
+            AlertKind::AskDepth => "ask_depth",
+            AlertKind::LargeLevel => "large_level",
+            AlertKind::Indicator => "indicator",
+            AlertKind::Expression => "expression",
+            AlertKind::Ratio => "ratio",
+            AlertKind::Basis => "basis",
+            AlertKind::Trailing => "trailing",
+        }
+    }
+
+    /// Whether the alert is evaluated on order book updates rather than
+    /// price updates.
+    pub fn is_book(&self) -> bool {
+        matches!(self, AlertKind::Spread | AlertKind::BidDepth | AlertKind::AskDepth | AlertKind::LargeLevel)
+    }
+
+    /// Whether the alert reads values only perpetuals have.
+    pub fn is_perp_only(&self) -> bool {
+        matches!(self, AlertKind::Funding | AlertKind::OpenInterestChange | AlertKind::Premium)
+    }
+}
+
+impl std::str::FromStr for AlertKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "price" => Ok(AlertKind::Price),
+            "day_change" => Ok(AlertKind::DayChange),
+            "window_change" => Ok(AlertKind::WindowChange),
+            "from_set" => Ok(AlertKind::FromSet),
+            "funding" => Ok(AlertKind::Funding),
+            "open_interest_change" => Ok(AlertKind::OpenInterestChange),
+            "premium" => Ok(AlertKind::Premium),
+            "spread" => Ok(AlertKind::Spread),
+            "bid_depth" => Ok(AlertKind::BidDepth),
+            "ask_depth" => Ok(AlertKind::AskDepth),
+            "large_level" => Ok(AlertKind::LargeLevel),
+            "indicator" => Ok(AlertKind::Indicator),
+            "expression" => Ok(AlertKind::Expression),
+            "ratio" => Ok(AlertKind::Ratio),
+            "basis" => Ok(AlertKind::Basis),
+            "trailing" => Ok(AlertKind::Trailing),
+            _ => Err(anyhow::anyhow!("Invalid alert kind: {s}")),
+        }
+    }
+}
+
+impl ToSql for AlertKind {
+    fn to_sql(&self) -> Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+
+impl FromSql for AlertKind {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        value.as_str()?.parse().map_err(|e: anyhow::Error| FromSqlError::Other(e.into()))
+    }
+}
+
+/// Whether an alert watches a spot pair or a perpetual.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MarketType {
+    Spot,
+    Perp,
+}
+
+impl MarketType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MarketType::Spot => "spot",
+            MarketType::Perp => "perp",
+        }
+    }
+}
+
+impl std::str::FromStr for MarketType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "spot" => Ok(MarketType::Spot),
+            "perp" => Ok(MarketType::Perp),
+            _ => Err(anyhow::anyhow!("Invalid market type: {s}")),
+        }
+    }
+}
+
+impl ToSql for MarketType {
+    fn to_sql(&self) -> Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+
+impl FromSql for MarketType {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        value.as_str()?.parse().map_err(|e: anyhow::Error| FromSqlError::Other(e.into()))
+    }
+}
+
+/// What a row of the listings snapshot describes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ListingKind {
+    Perp,
+    SpotPair,
+    SpotToken,
+}
+
+impl ListingKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ListingKind::Perp => "perp",
+            ListingKind::SpotPair => "spot_pair",
+            ListingKind::SpotToken => "spot_token",
+        }
+    }
+}
+
+impl std::str::FromStr for ListingKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "perp" => Ok(ListingKind::Perp),
+            "spot_pair" => Ok(ListingKind::SpotPair),
+            "spot_token" => Ok(ListingKind::SpotToken),
+            _ => Err(anyhow::anyhow!("Invalid listing kind: {s}")),
+        }
+    }
+}
+
+impl std::fmt::Display for ListingKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ListingKind::Perp => write!(f, "perp"),
+            ListingKind::SpotPair => write!(f, "spot pair"),
+            ListingKind::SpotToken => write!(f, "spot token"),
+        }
+    }
+}
+
+impl ToSql for ListingKind {
+    fn to_sql(&self) -> Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+
+impl FromSql for ListingKind {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        value.as_str()?.parse().map_err(|e: anyhow::Error| FromSqlError::Other(e.into()))
+    }
+}
+
+/// What a cron alert sends when it fires. Defaults to the price, which is
+/// all cron alerts sent before templates existed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReportTemplate {
+    /// The price of each coin, one line each.
+    #[default]
+    Price,
+    /// Price and 24h change of each coin.
+    Summary,
+    /// Price, 24h change, high/low and volume of each coin, plus funding and
+    /// open interest for perps.
+    Full,
+}
+
+impl ReportTemplate {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReportTemplate::Price => "price",
+            ReportTemplate::Summary => "summary",
+            ReportTemplate::Full => "full",
+        }
+    }
+}
+
+impl std::str::FromStr for ReportTemplate {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "price" => Ok(ReportTemplate::Price),
+            "summary" => Ok(ReportTemplate::Summary),
+            "full" => Ok(ReportTemplate::Full),
+            _ => Err(anyhow::anyhow!("Invalid report template: {s} (use price, summary or full)")),
+        }
+    }
+}
+
+impl std::fmt::Display for ReportTemplate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl ToSql for ReportTemplate {
+    fn to_sql(&self) -> Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+
+impl FromSql for ReportTemplate {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        value.as_str()?.parse().map_err(|e: anyhow::Error| FromSqlError::Other(e.into()))
+    }
+}
+
+/// What a cron alert reports on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CronAlertKind {
+    /// A watchlist of coins, laid out by the alert's template.
+    Market,
+    /// The account of the alert's `address`.
+    Portfolio,
+}
+
+impl CronAlertKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CronAlertKind::Market => "market",
+            CronAlertKind::Portfolio => "portfolio",
+        }
+    }
+}
+
+impl std::str::FromStr for CronAlertKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "market" => Ok(CronAlertKind::Market),
+            "portfolio" => Ok(CronAlertKind::Portfolio),
+            _ => Err(anyhow::anyhow!("Invalid cron alert kind: {s}")),
+        }
+    }
+}
+
+impl ToSql for CronAlertKind {
+    fn to_sql(&self) -> Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+
+impl FromSql for CronAlertKind {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        value.as_str()?.parse().map_err(|e: anyhow::Error| FromSqlError::Other(e.into()))
+    }
+}
+
+/// What a cron alert does about fires it missed, e.g. while the bot was
+/// down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MisfirePolicy {
+    /// Catch up with a single report.
+    FireOnce,
+    /// Catch up with a report for every missed fire.
+    FireAll,
+    /// Drop missed fires and wait for the next one.
+    Skip,
+}
+
+impl MisfirePolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MisfirePolicy::FireOnce => "once",
+            MisfirePolicy::FireAll => "all",
+            MisfirePolicy::Skip => "skip",
+        }
+    }
+}
+
+impl std::str::FromStr for MisfirePolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "once" => Ok(MisfirePolicy::FireOnce),
+            "all" => Ok(MisfirePolicy::FireAll),
+            "skip" => Ok(MisfirePolicy::Skip),
+            _ => Err(anyhow::anyhow!("Invalid misfire policy: {s} (use once, all or skip)")),
+        }
+    }
+}
+
+impl std::fmt::Display for MisfirePolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl ToSql for MisfirePolicy {
+    fn to_sql(&self) -> Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+
+impl FromSql for MisfirePolicy {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        value.as_str()?.parse().map_err(|e: anyhow::Error| FromSqlError::Other(e.into()))
+    }
+}
+
+/// Where a cron alert delivery is. A delivery is `Claimed` before its report
+/// is sent and `Sent` after, so one left `Claimed` by a crash may or may not
+/// have reached the chat.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeliveryStatus {
+    Claimed,
+    Sent,
+    /// Sending failed, so it is retried.
+    Failed,
+    /// Claimed when the bot stopped. It is not retried, to never send twice.
+    Abandoned,
+}
+
+impl DeliveryStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryStatus::Claimed => "claimed",
+            DeliveryStatus::Sent => "sent",
+            DeliveryStatus::Failed => "failed",
+            DeliveryStatus::Abandoned => "abandoned",
+        }
+    }
+}
+
+impl std::str::FromStr for DeliveryStatus {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "claimed" => Ok(DeliveryStatus::Claimed),
+            "sent" => Ok(DeliveryStatus::Sent),
+            "failed" => Ok(DeliveryStatus::Failed),
+            "abandoned" => Ok(DeliveryStatus::Abandoned),
+            _ => Err(anyhow::anyhow!("Invalid delivery status: {s}")),
+        }
+    }
+}
+
+impl ToSql for DeliveryStatus {
+    fn to_sql(&self) -> Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+
+impl FromSql for DeliveryStatus {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        value.as_str()?.parse().map_err(|e: anyhow::Error| FromSqlError::Other(e.into()))
+    }
+}
+
+/// One scheduled fire of a cron alert. There is at most one per alert and
+/// `scheduled_for`, which is what keeps a fire from being sent twice.
+#[derive(Debug, Clone)]
+pub struct CronDelivery {
+    pub id: i64,
+    pub cron_alert_id: i64,
+    pub scheduled_for: DateTime<Utc>,
+    pub status: DeliveryStatus,
+    pub attempts: i64,
+    pub error: Option<String>,
+    pub claimed_at: DateTime<Utc>,
+    pub completed_at: Option<DateTime<Utc>>,
+}
+
+/// A perp, spot pair or spot token listed on Hyperliquid. `name` is what
+/// the exchange calls it, e.g. `BTC` or `@107`, and `label` how it is shown,
+/// e.g. `BTC-PERP` or `HYPE/USDC (@107)`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MarketListing {
+    pub kind: ListingKind,
+    pub name: String,
+    pub label: String,
+}
+
+/// Which way the price has to move through an alert's target for it to fire.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlertDirection {
+    Above,
+    Below,
+    Cross,
+}
+
+impl AlertDirection {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AlertDirection::Above => "above",
+            AlertDirection::Below => "below",
+            AlertDirection::Cross => "cross",
+        }
+    }
+
+    /// Whether moving from `previous_px` to `current_px` satisfies this
+    /// direction for `target`. Cross alerts also fire while the price sits
+    /// within 0.1% of the target.
+    pub fn is_triggered(&self, previous_px: f64, current_px: f64, target: f64) -> bool {
+        let crossed_up = previous_px < target && current_px >= target;
+        let crossed_down = previous_px > target && current_px <= target;
+        match self {
+            AlertDirection::Above => crossed_up,
+            AlertDirection::Below => crossed_down,
+            AlertDirection::Cross => {
+                crossed_up || crossed_down || (current_px * 0.999..=current_px * 1.001).contains(&target)
+            }
+        }
+    }
+}
+
+impl std::str::FromStr for AlertDirection {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "above" => Ok(AlertDirection::Above),
+            "below" => Ok(AlertDirection::Below),
+            "cross" => Ok(AlertDirection::Cross),
+            _ => Err(anyhow::anyhow!("Invalid alert direction: {s}")),
+        }
+    }
+}
+
+impl ToSql for AlertDirection {
+    fn to_sql(&self) -> Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+
+impl FromSql for AlertDirection {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        value.as_str()?.parse().map_err(|e: anyhow::Error| FromSqlError::Other(e.into()))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct WatchedAddress {
+    pub id: i64,
+    pub chat_id: i64,
+    pub address: String,
+    pub liquidation_threshold: f64,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub tpsl_threshold: f64,
+}
+
+/// A chat's threshold for large trades on one token.
+#[derive(Debug, Clone)]
+pub struct WhaleAlert {
+    pub id: i64,
+    pub chat_id: i64,
+    pub coin: String,
+    pub token: String,
+    pub market: MarketType,
+    pub min_notional: f64,
+    pub burst_seconds: Option<i64>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// The user-supplied fields of an alert that is about to be inserted.
+#[derive(Debug, Clone)]
+pub struct NewAlert {
+    pub public_key: String,
+    pub chat_id: ChatId,
+    pub coin: String,
+    pub token: String,
+    pub market: MarketType,
+    pub target: AlertTarget,
+}
+
+/// The condition an alert waits for, as parsed from a `/setalert` target.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AlertTarget {
+    pub kind: AlertKind,
+    pub direction: AlertDirection,
+    pub price: f64,
+    pub change_pct: Option<f64>,
+    pub window_minutes: Option<i64>,
+    pub expression: Option<String>,
+}
+
+impl AlertTarget {
+    pub fn price(direction: AlertDirection, price: f64) -> Self {
+        Self {
+            kind: AlertKind::Price,
+            direction,
+            price,
+            change_pct: None,
+            window_minutes: None,
+            expression: None,
+        }
+    }
+
+    /// A target on a perp value such as funding or premium, stored in `price`.
+    pub fn metric(kind: AlertKind, direction: AlertDirection, threshold: f64) -> Self {
+        Self { kind, ..Self::price(direction, threshold) }
+    }
+
+    /// The value of the `price` column, which expressions leave empty.
+    fn stored_price(&self) -> Option<f64> {
+        (self.kind != AlertKind::Expression).then_some(self.price)
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct CronAlert {
     pub id: i64,
     pub chat_id: i64,
@@ -31,34 +777,327 @@ pub struct CronAlert {
     pub updated_at: DateTime<Utc>,
     pub last_triggered: Option<DateTime<Utc>>,
     pub next_trigger: Option<DateTime<Utc>>,
+    pub template: ReportTemplate,
+    pub misfire: MisfirePolicy,
+    pub kind: CronAlertKind,
+    /// The account a portfolio alert reports on.
+    pub address: Option<String>,
 }
 
-impl std::fmt::Display for AlertTable {
+impl CronAlert {
+    /// The coins the alert reports on, as the chat typed them. `coin` holds
+    /// a comma-separated watchlist.
+    pub fn coins(&self) -> Vec<&str> {
+        self.coin.split(',').map(str::trim).filter(|coin| !coin.is_empty()).collect()
+    }
+}
+
+impl AlertTable {
+    pub fn target(&self) -> AlertTarget {
+        AlertTarget {
+            kind: self.kind,
+            direction: self.direction,
+            price: self.price,
+            change_pct: self.change_pct,
+            window_minutes: self.window_minutes,
+            expression: self.expression.clone(),
+        }
+    }
+}
+
+impl std::fmt::Display for AlertTarget {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let sign = match self.direction {
+            AlertDirection::Above => "+",
+            AlertDirection::Below => "-",
+            AlertDirection::Cross => "±",
+        };
+        let change_pct = self.change_pct.unwrap_or_default();
+        match self.kind {
+            AlertKind::Price => {
+                let direction = match self.direction {
+                    AlertDirection::Above => "above",
+                    AlertDirection::Below => "below",
+                    AlertDirection::Cross => "at",
+                };
+                write!(f, "{direction} ${:.2}", self.price)
+            }
+            AlertKind::DayChange => write!(f, "moves {sign}{change_pct}% over 24h"),
+            AlertKind::WindowChange => {
+                write!(f, "moves {sign}{change_pct}% within {}m", self.window_minutes.unwrap_or_default())
+            }
+            AlertKind::FromSet => write!(f, "moves {sign}{change_pct}% from ${:.2}", self.price),
+            AlertKind::Trailing => match self.direction {
+                AlertDirection::Above => write!(f, "rises {change_pct}% from its low of ${:.2}", self.price),
+                _ => write!(f, "drops {change_pct}% from its high of ${:.2}", self.price),
+            },
+            AlertKind::Funding => write!(
+                f,
+                "funding {} {}%/h ({:.2}% APR)",
+                self.direction.as_str(),
+                self.price,
+                self.price * HOURS_PER_YEAR
+            ),
+            AlertKind::OpenInterestChange => {
+                write!(f, "open interest {sign}{change_pct}% within {}m", self.window_minutes.unwrap_or_default())
+            }
+            AlertKind::Premium => write!(f, "premium {} {} bps", self.direction.as_str(), self.price),
+            AlertKind::Spread => write!(f, "spread {} {} bps", self.direction.as_str(), self.price),
+            AlertKind::BidDepth => {
+                write!(f, "bid depth within {change_pct}% {} ${:.0}", self.direction.as_str(), self.price)
+            }
+            AlertKind::AskDepth => {
+                write!(f, "ask depth within {change_pct}% {} ${:.0}", self.direction.as_str(), self.price)
+            }
+            AlertKind::LargeLevel => write!(f, "book level of size {} or more", self.price),
+            AlertKind::Indicator | AlertKind::Expression => write!(f, "{}", self.expression.as_deref().unwrap_or_default()),
+            AlertKind::Ratio => write!(
+                f,
+                "ratio to {} {} {}",
+                self.expression.as_deref().unwrap_or_default(),
+                self.direction.as_str(),
+                self.price
+            ),
+            AlertKind::Basis => write!(
+                f,
+                "basis to {} {} {} bps",
+                self.expression.as_deref().unwrap_or_default(),
+                self.direction.as_str(),
+                self.price
+            ),
+        }
+    }
+}
+
+impl AlertTable {
+    /// Whether the alert may fire at `at`: it has not expired and `at` falls
+    /// in its active window.
+    pub fn is_active_at(&self, at: DateTime<Utc>) -> bool {
+        self.expires_at.is_none_or(|expires_at| at < expires_at)
+            && self.active_window.as_ref().is_none_or(|window| window.contains(at))
+    }
+
+    /// Whether the alert is armed, and if not, what re-arms it, with times
+    /// in `timezone`.
+    pub fn state(&self, timezone: Tz) -> String {
+        if !self.is_active {
+            return format!("expired, re-activate it with /setexpiry {} <when>", self.id);
+        }
+        if !self.alerted {
+            return "armed".to_string();
+        }
+        match self.rearm {
+            RearmMode::OneShot => "fired, edit it to re-arm".to_string(),
+            RearmMode::Cooldown(_) => format!("cooling down until {}", self.cooldown_until.with_timezone(&timezone).format("%H:%M:%S %Z")),
+            RearmMode::Band(_) => "fired, waiting for price to leave the band".to_string(),
+        }
+    }
+}
+
+/// Formats a timestamp in `timezone` for alert listings.
+fn local_time(at: DateTime<Utc>, timezone: Tz) -> impl std::fmt::Display {
+    at.with_timezone(&timezone).format("%Y-%m-%d %H:%M:%S %Z")
+}
+
+/// Displays an alert with its times in a chat's timezone.
+pub struct Localized<'a, T> {
+    value: &'a T,
+    timezone: Tz,
+}
+
+impl AlertTable {
+    pub fn localized(&self, timezone: Tz) -> Localized<'_, Self> {
+        Localized { value: self, timezone }
+    }
+}
+
+impl CronAlert {
+    pub fn localized(&self, timezone: Tz) -> Localized<'_, Self> {
+        Localized { value: self, timezone }
+    }
+}
+
+impl std::fmt::Display for Localized<'_, AlertTable> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let alert = self.value;
+        write!(f, "🔔 #{} ", alert.id)?;
+        if alert.kind != AlertKind::Expression {
+            write!(f, "{} ", alert.coin)?;
+        }
+        write!(f, "{} [{}, {}]", alert.target(), alert.rearm, alert.state(self.timezone))?;
+        if let Some(window) = &alert.active_window {
+            write!(f, " (active {window}")?;
+            if window.timezone == Tz::UTC {
+                write!(f, " UTC")?;
+            }
+            write!(f, ")")?;
+        }
+        if let Some(expires_at) = alert.expires_at {
+            write!(f, " (expires {})", local_time(expires_at, self.timezone))?;
+        }
+        write!(f, " (created {})", local_time(alert.created_at, self.timezone))
+    }
+}
+
+impl std::fmt::Display for Localized<'_, CronAlert> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let cron_alert = self.value;
+        write!(f, "⏰ #{} ", cron_alert.id)?;
+        match cron_alert.kind {
+            CronAlertKind::Market => write!(f, "{} {} (report: {})", cron_alert.coin, cron_alert.token, cron_alert.template)?,
+            CronAlertKind::Portfolio => write!(f, "portfolio of {}", cron_alert.address.as_deref().unwrap_or_default())?,
+        }
         write!(
             f,
-            "🔔 {} at ${:.2} (created {})",
-            self.coin,
-            self.price,
-            self.created_at.format("%Y-%m-%d %H:%M:%S")
-        )
+            " (schedule: {}) (misfire: {}) (created {})",
+            cron_alert.cron_schedule,
+            cron_alert.misfire,
+            local_time(cron_alert.created_at, self.timezone)
+        )?;
+        if let Some(next_trigger) = cron_alert.next_trigger {
+            write!(f, " (next trigger: {})", local_time(next_trigger, self.timezone))?;
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for AlertTable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.localized(Tz::UTC).fmt(f)
     }
 }
 
 impl std::fmt::Display for CronAlert {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.localized(Tz::UTC).fmt(f)
+    }
+}
+
+impl std::fmt::Display for WatchedAddress {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "⏰ {} {} (schedule: {}) (created {}) (next trigger: {})",
-            self.coin,
-            self.token,
-            self.cron_schedule,
-            self.created_at.format("%Y-%m-%d %H:%M:%S"),
-            self.next_trigger.unwrap().format("%Y-%m-%d %H:%M:%S")
+            "👛 {} (liquidation warning within {}%) (TP/SL warning within {}%) (since {})",
+            self.address,
+            self.liquidation_threshold,
+            self.tpsl_threshold,
+            self.created_at.format("%Y-%m-%d %H:%M:%S")
         )
     }
 }
 
+impl std::fmt::Display for WhaleAlert {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "🐋 #{} {} trades over ${:.0}", self.id, self.coin, self.min_notional)?;
+        if let Some(burst_seconds) = self.burst_seconds {
+            write!(f, " (or same-side bursts within {burst_seconds}s)")?;
+        }
+        write!(f, " (created {})", self.created_at.format("%Y-%m-%d %H:%M:%S"))
+    }
+}
+
+fn whale_alert_from_row(row: &Row) -> Result<WhaleAlert> {
+    Ok(WhaleAlert {
+        id: row.get(0)?,
+        chat_id: row.get(1)?,
+        coin: row.get(2)?,
+        token: row.get(3)?,
+        market: row.get(4)?,
+        min_notional: row.get(5)?,
+        burst_seconds: row.get(6)?,
+        created_at: row.get::<_, DateTime<Utc>>(7)?,
+        updated_at: row.get::<_, DateTime<Utc>>(8)?,
+    })
+}
+
+fn watched_address_from_row(row: &Row) -> Result<WatchedAddress> {
+    Ok(WatchedAddress {
+        id: row.get(0)?,
+        chat_id: row.get(1)?,
+        address: row.get(2)?,
+        liquidation_threshold: row.get(3)?,
+        created_at: row.get::<_, DateTime<Utc>>(4)?,
+        updated_at: row.get::<_, DateTime<Utc>>(5)?,
+        tpsl_threshold: row.get(6)?,
+    })
+}
+
+fn cron_alert_from_row(row: &Row) -> Result<CronAlert> {
+    Ok(CronAlert {
+        id: row.get(0)?,
+        chat_id: row.get(1)?,
+        coin: row.get(2)?,
+        token: row.get(3)?,
+        cron_schedule: row.get(4)?,
+        is_active: row.get(5)?,
+        created_at: row.get::<_, DateTime<Utc>>(6)?,
+        updated_at: row.get::<_, DateTime<Utc>>(7)?,
+        last_triggered: row.get::<_, Option<DateTime<Utc>>>(8)?,
+        next_trigger: row.get::<_, Option<DateTime<Utc>>>(9)?,
+        template: row.get(10)?,
+        misfire: row.get(11)?,
+        kind: row.get(12)?,
+        address: row.get(13)?,
+    })
+}
+
+fn cron_delivery_from_row(row: &Row) -> Result<CronDelivery> {
+    Ok(CronDelivery {
+        id: row.get(0)?,
+        cron_alert_id: row.get(1)?,
+        scheduled_for: row.get(2)?,
+        status: row.get(3)?,
+        attempts: row.get(4)?,
+        error: row.get(5)?,
+        claimed_at: row.get(6)?,
+        completed_at: row.get(7)?,
+    })
+}
+
+fn market_listing_from_row(row: &Row) -> Result<MarketListing> {
+    Ok(MarketListing {
+        kind: row.get(0)?,
+        name: row.get(1)?,
+        label: row.get(2)?,
+    })
+}
+
+fn alert_from_row(row: &Row) -> Result<AlertTable> {
+    Ok(AlertTable {
+        id: row.get(0)?,
+        public_key: row.get(1)?,
+        chat_id: row.get(2)?,
+        coin: row.get(3)?,
+        token: row.get(4)?,
+        price: row.get::<_, Option<f64>>(5)?.unwrap_or_default(),
+        alerted: row.get(6)?,
+        created_at: row.get::<_, DateTime<Utc>>(7)?,
+        updated_at: row.get::<_, DateTime<Utc>>(8)?,
+        cooldown_until: row.get::<_, DateTime<Utc>>(9).unwrap_or(DateTime::<Utc>::from_timestamp(0, 0).unwrap()),
+        direction: row.get(10)?,
+        market: row.get(11)?,
+        kind: row.get(12)?,
+        change_pct: row.get(13)?,
+        window_minutes: row.get(14)?,
+        expression: row.get(15)?,
+        rearm: RearmMode::from_columns(&row.get::<_, String>(16)?, row.get(17)?)
+            .map_err(|e| rusqlite::Error::FromSqlConversionFailure(16, rusqlite::types::Type::Text, e.into()))?,
+        expires_at: row.get(18)?,
+        active_window: row.get(19)?,
+        is_active: row.get(20)?,
+    })
+}
+
+/// SQLite has no `ADD COLUMN IF NOT EXISTS`, so columns added after a table
+/// was first created are migrated in here.
+fn add_column_if_missing(conn: &Connection, table: &str, column: &str, definition: &str) -> Result<()> {
+    let mut stmt = conn.prepare(&format!("SELECT 1 FROM pragma_table_info('{table}') WHERE name = ?"))?;
+    if !stmt.exists([column])? {
+        conn.execute(&format!("ALTER TABLE {table} ADD COLUMN {column} {definition}"), ())?;
+    }
+    Ok(())
+}
+
 #[derive(Clone)]
 pub struct Database {
     conn: Arc<Mutex<Connection>>,
@@ -85,9 +1124,39 @@ impl Database {
             alerted BOOLEAN DEFAULT FALSE,
             created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
             updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
-            cooldown_until TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+            cooldown_until TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+            direction TEXT DEFAULT 'cross',
+            market TEXT DEFAULT 'spot',
+            kind TEXT DEFAULT 'price',
+            change_pct REAL,
+            window_minutes INTEGER,
+            expression TEXT,
+            rearm_mode TEXT DEFAULT 'cooldown',
+            rearm_value REAL DEFAULT 60,
+            expires_at TIMESTAMP,
+            active_window TEXT,
+            is_active BOOLEAN DEFAULT TRUE
         )
         "#, ())?;
+        add_column_if_missing(&conn_guard, "alerts", "direction", "TEXT DEFAULT 'cross'")?;
+        add_column_if_missing(&conn_guard, "alerts", "market", "TEXT DEFAULT 'spot'")?;
+        add_column_if_missing(&conn_guard, "alerts", "kind", "TEXT DEFAULT 'price'")?;
+        add_column_if_missing(&conn_guard, "alerts", "change_pct", "REAL")?;
+        add_column_if_missing(&conn_guard, "alerts", "window_minutes", "INTEGER")?;
+        add_column_if_missing(&conn_guard, "alerts", "expression", "TEXT")?;
+        add_column_if_missing(&conn_guard, "alerts", "rearm_mode", "TEXT DEFAULT 'cooldown'")?;
+        add_column_if_missing(&conn_guard, "alerts", "rearm_value", "REAL DEFAULT 60")?;
+        add_column_if_missing(&conn_guard, "alerts", "expires_at", "TIMESTAMP")?;
+        add_column_if_missing(&conn_guard, "alerts", "active_window", "TEXT")?;
+        add_column_if_missing(&conn_guard, "alerts", "is_active", "BOOLEAN DEFAULT TRUE")?;
+        conn_guard.execute("UPDATE alerts SET price = NULL WHERE kind = 'expression' AND price IS NOT NULL", ())?;
+
+        conn_guard.execute(r#"
+        CREATE INDEX IF NOT EXISTS idx_alerts_token_price ON alerts (token, price)
+        "#, ())?;
+        conn_guard.execute(r#"
+        CREATE INDEX IF NOT EXISTS idx_alerts_token_kind ON alerts (token, kind)
+        "#, ())?;
         
         conn_guard.execute(r#"
         CREATE TABLE IF NOT EXISTS cron_alerts (
@@ -100,18 +1169,106 @@ impl Database {
             created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
             updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
             last_triggered TIMESTAMP,
-            next_trigger TIMESTAMP
+            next_trigger TIMESTAMP,
+            template TEXT DEFAULT 'price',
+            misfire TEXT DEFAULT 'once',
+            kind TEXT DEFAULT 'market',
+            address TEXT
+        )
+        "#, ())?;
+        add_column_if_missing(&conn_guard, "cron_alerts", "template", "TEXT DEFAULT 'price'")?;
+        add_column_if_missing(&conn_guard, "cron_alerts", "misfire", "TEXT DEFAULT 'once'")?;
+        add_column_if_missing(&conn_guard, "cron_alerts", "kind", "TEXT DEFAULT 'market'")?;
+        add_column_if_missing(&conn_guard, "cron_alerts", "address", "TEXT")?;
+        conn_guard.execute("UPDATE cron_alerts SET address = coin, coin = '' WHERE kind = 'portfolio' AND address IS NULL", ())?;
+
+        conn_guard.execute(r#"
+        CREATE TABLE IF NOT EXISTS cron_deliveries (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            cron_alert_id INTEGER NOT NULL,
+            scheduled_for TIMESTAMP NOT NULL,
+            status TEXT NOT NULL,
+            attempts INTEGER NOT NULL DEFAULT 0,
+            error TEXT,
+            claimed_at TIMESTAMP NOT NULL,
+            completed_at TIMESTAMP,
+            UNIQUE (cron_alert_id, scheduled_for)
+        )
+        "#, ())?;
+
+        conn_guard.execute(r#"
+        CREATE TABLE IF NOT EXISTS watched_addresses (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            chat_id INTEGER,
+            address TEXT,
+            liquidation_threshold REAL DEFAULT 10.0,
+            created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+            updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+            tpsl_threshold REAL DEFAULT 1.0,
+            UNIQUE (chat_id, address)
+        )
+        "#, ())?;
+        add_column_if_missing(&conn_guard, "watched_addresses", "tpsl_threshold", "REAL DEFAULT 1.0")?;
+
+        conn_guard.execute(r#"
+        CREATE TABLE IF NOT EXISTS whale_alerts (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            chat_id INTEGER,
+            coin TEXT,
+            token TEXT,
+            market TEXT DEFAULT 'spot',
+            min_notional REAL,
+            burst_seconds INTEGER,
+            created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+            updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+            UNIQUE (chat_id, token)
+        )
+        "#, ())?;
+
+        conn_guard.execute(r#"
+        CREATE TABLE IF NOT EXISTS chat_settings (
+            chat_id INTEGER PRIMARY KEY,
+            account_events TEXT,
+            created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+            updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+            listings BOOLEAN DEFAULT FALSE,
+            timezone TEXT
+        )
+        "#, ())?;
+        add_column_if_missing(&conn_guard, "chat_settings", "listings", "BOOLEAN DEFAULT FALSE")?;
+        add_column_if_missing(&conn_guard, "chat_settings", "timezone", "TEXT")?;
+
+        conn_guard.execute(r#"
+        CREATE TABLE IF NOT EXISTS market_listings (
+            kind TEXT,
+            name TEXT,
+            label TEXT,
+            created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+            PRIMARY KEY (kind, name)
         )
         "#, ())?;
         Ok(())
     }
 
-    pub async fn insert_alert(&self, public_key: &str, chat_id: ChatId, coin: &str, token: &str, price: f64) -> Result<()> {
+    pub async fn insert_alert(&self, alert: &NewAlert) -> Result<()> {
         let conn_guard = self.conn.lock().await;
         conn_guard.execute(r#"
-        INSERT INTO alerts (public_key, chat_id, coin, token, price, alerted, created_at, updated_at, cooldown_until) 
-        VALUES (?, ?, ?, ?, ?, ?, CURRENT_TIMESTAMP, CURRENT_TIMESTAMP, CURRENT_TIMESTAMP)
-        "#, (public_key, chat_id.0, coin, token, price, false))?;
+        INSERT INTO alerts (public_key, chat_id, coin, token, price, alerted, created_at, updated_at, cooldown_until, direction, market, kind, change_pct, window_minutes, expression) 
+        VALUES (?, ?, ?, ?, ?, ?, CURRENT_TIMESTAMP, CURRENT_TIMESTAMP, CURRENT_TIMESTAMP, ?, ?, ?, ?, ?, ?)
+        "#, params![
+            alert.public_key,
+            alert.chat_id.0,
+            alert.coin,
+            alert.token,
+            alert.target.stored_price(),
+            false,
+            alert.target.direction,
+            alert.market,
+            alert.target.kind,
+            alert.target.change_pct,
+            alert.target.window_minutes,
+            alert.target.expression
+        ])?;
         Ok(())
     }
 
@@ -119,7 +1276,7 @@ impl Database {
         let conn_guard = self.conn.lock().await;
         let mut stmt = conn_guard.prepare("SELECT DISTINCT token FROM alerts")?;
         let tokens = stmt.query_map([], |row| {
-            Ok(row.get(0)?)
+            row.get(0)
         })?.collect::<Result<Vec<String>>>()?;
         Ok(tokens)
     }
@@ -127,70 +1284,231 @@ impl Database {
     pub async fn get_all_alerts(&self) -> Result<Vec<AlertTable>> {
         let conn_guard = self.conn.lock().await;
         let mut stmt = conn_guard.prepare("SELECT * FROM alerts")?;
-        let alerts = stmt.query_map([], |row| {
-            Ok(AlertTable {
-                id: row.get(0)?,
-                public_key: row.get(1)?,
-                chat_id: row.get(2)?,
-                coin: row.get(3)?,
-                token: row.get(4)?,
-                price: row.get(5)?,
-                alerted: row.get(6)?,
-                created_at: row.get::<_, DateTime<Utc>>(7)?,
-                updated_at: row.get::<_, DateTime<Utc>>(8)?,
-                cooldown_until: row.get::<_, DateTime<Utc>>(9).unwrap_or(DateTime::<Utc>::from_timestamp(0, 0).unwrap()),
-            })
-        })?.collect::<Result<Vec<AlertTable>>>()?;
+        let alerts = stmt.query_map([], alert_from_row)?.collect::<Result<Vec<AlertTable>>>()?;
         Ok(alerts)
     }
 
     pub async fn get_all_alerts_for_chat(&self, chat_id: ChatId) -> Result<Vec<AlertTable>> {
         let conn_guard = self.conn.lock().await;
         let mut stmt = conn_guard.prepare("SELECT * FROM alerts WHERE chat_id = ?")?;
-        let alerts = stmt.query_map([chat_id.0], |row| {
-            Ok(AlertTable {
-                id: row.get(0)?,
-                public_key: row.get(1)?,
-                chat_id: row.get(2)?,
-                coin: row.get(3)?,
-                token: row.get(4)?,
-                price: row.get(5)?,
-                alerted: row.get(6)?,
-                created_at: row.get::<_, DateTime<Utc>>(7)?,
-                updated_at: row.get::<_, DateTime<Utc>>(8)?,
-                cooldown_until: row.get::<_, DateTime<Utc>>(9).unwrap_or(DateTime::<Utc>::from_timestamp(0, 0).unwrap()),
-            })
-        })?.collect::<Result<Vec<AlertTable>>>()?;
+        let alerts = stmt.query_map([chat_id.0], alert_from_row)?.collect::<Result<Vec<AlertTable>>>()?;
+        Ok(alerts)
+    }
+
+    pub async fn get_triggered_alerts(&self, market: MarketType, token: &str, lower_price: f64, upper_price: f64) -> Result<Vec<AlertTable>> {
+        let conn_guard = self.conn.lock().await;
+        let mut stmt = conn_guard.prepare("SELECT * FROM alerts WHERE alerted = false AND is_active = true AND kind = 'price' AND token = ? AND market = ? AND price BETWEEN ? AND ?")?;
+        let alerts = stmt.query_map(params![token, market, lower_price, upper_price], alert_from_row)?.collect::<Result<Vec<AlertTable>>>()?;
         Ok(alerts)
     }
 
-    pub async fn get_triggered_alerts(&self, lower_price: f64, upper_price: f64) -> Result<Vec<AlertTable>> {
-        let conn_guard = self.conn.lock().await;
-        let mut stmt = conn_guard.prepare("SELECT * FROM alerts WHERE alerted = false AND price BETWEEN ? AND ?")?;
-        let alerts = stmt.query_map([lower_price, upper_price], |row| {
-            Ok(AlertTable {
-                id: row.get(0)?,
-                public_key: row.get(1)?,
-                chat_id: row.get(2)?,
-                coin: row.get(3)?,
-                token: row.get(4)?,
-                price: row.get(5)?,
-                alerted: row.get(6)?,
-                created_at: row.get::<_, DateTime<Utc>>(7)?,
-                updated_at: row.get::<_, DateTime<Utc>>(8)?,
-                cooldown_until: row.get::<_, DateTime<Utc>>(9).unwrap_or(DateTime::<Utc>::from_timestamp(0, 0).unwrap()),
-            })
-        })?.collect::<Result<Vec<AlertTable>>>()?;
+    /// Returns the armed alerts on `token` that are evaluated on price updates
+    /// but don't target a price. These can't be narrowed down by price, so
+    /// they are evaluated by the caller.
+    pub async fn get_armed_non_price_alerts(&self, market: MarketType, token: &str) -> Result<Vec<AlertTable>> {
+        let conn_guard = self.conn.lock().await;
+        let mut stmt = conn_guard.prepare(r#"
+            SELECT * FROM alerts
+            WHERE alerted = false AND is_active = true AND token = ? AND market = ?
+            AND kind NOT IN ('price', 'spread', 'bid_depth', 'ask_depth', 'large_level', 'indicator', 'expression', 'ratio', 'basis')
+            "#)?;
+        let alerts = stmt.query_map(params![token, market], alert_from_row)?.collect::<Result<Vec<AlertTable>>>()?;
         Ok(alerts)
     }
 
+    /// Returns the armed order book alerts on `token`.
+    pub async fn get_armed_book_alerts(&self, token: &str) -> Result<Vec<AlertTable>> {
+        let conn_guard = self.conn.lock().await;
+        let mut stmt = conn_guard.prepare(r#"
+            SELECT * FROM alerts
+            WHERE alerted = false AND is_active = true AND token = ? AND kind IN ('spread', 'bid_depth', 'ask_depth', 'large_level')
+            "#)?;
+        let alerts = stmt.query_map([token], alert_from_row)?.collect::<Result<Vec<AlertTable>>>()?;
+        Ok(alerts)
+    }
+
+    /// Returns the armed indicator alerts on `token`.
+    pub async fn get_armed_indicator_alerts(&self, token: &str) -> Result<Vec<AlertTable>> {
+        let conn_guard = self.conn.lock().await;
+        let mut stmt = conn_guard.prepare("SELECT * FROM alerts WHERE alerted = false AND is_active = true AND token = ? AND kind = 'indicator'")?;
+        let alerts = stmt.query_map([token], alert_from_row)?.collect::<Result<Vec<AlertTable>>>()?;
+        Ok(alerts)
+    }
+
+    /// Returns every alert that reads more than one market: expression, ratio
+    /// and basis alerts, armed or not.
+    pub async fn get_cross_market_alerts(&self) -> Result<Vec<AlertTable>> {
+        let conn_guard = self.conn.lock().await;
+        let mut stmt = conn_guard.prepare("SELECT * FROM alerts WHERE kind IN ('expression', 'ratio', 'basis')")?;
+        let alerts = stmt.query_map([], alert_from_row)?.collect::<Result<Vec<AlertTable>>>()?;
+        Ok(alerts)
+    }
+
+    /// Returns the armed alerts among `alert_ids`.
+    pub async fn get_armed_alerts(&self, alert_ids: &[i64]) -> Result<Vec<AlertTable>> {
+        if alert_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let placeholders = vec!["?"; alert_ids.len()].join(", ");
+        let conn_guard = self.conn.lock().await;
+        let mut stmt = conn_guard.prepare(&format!("SELECT * FROM alerts WHERE alerted = false AND is_active = true AND id IN ({placeholders})"))?;
+        let alerts = stmt.query_map(params_from_iter(alert_ids), alert_from_row)?.collect::<Result<Vec<AlertTable>>>()?;
+        Ok(alerts)
+    }
+
+    pub async fn get_alert_for_chat(&self, alert_id: i64, chat_id: ChatId) -> Result<Option<AlertTable>> {
+        let conn_guard = self.conn.lock().await;
+        let mut stmt = conn_guard.prepare("SELECT * FROM alerts WHERE id = ? AND chat_id = ?")?;
+        let alert = stmt.query_map(params![alert_id, chat_id.0], alert_from_row)?.next().transpose()?;
+        Ok(alert)
+    }
+
+    pub async fn delete_alert(&self, alert_id: i64, chat_id: ChatId) -> Result<usize> {
+        let conn_guard = self.conn.lock().await;
+        let mut stmt = conn_guard.prepare("DELETE FROM alerts WHERE id = ? AND chat_id = ?")?;
+        let result = stmt.execute(params![alert_id, chat_id.0])?;
+        Ok(result)
+    }
+
+    pub async fn delete_alerts_for_chat(&self, chat_id: ChatId) -> Result<usize> {
+        let conn_guard = self.conn.lock().await;
+        let mut stmt = conn_guard.prepare("DELETE FROM alerts WHERE chat_id = ?")?;
+        let result = stmt.execute([chat_id.0])?;
+        Ok(result)
+    }
+
+    /// Moves an alert owned by `chat_id` to another coin, as when an
+    /// expression alert is changed to read other markets.
+    pub async fn update_alert_market(&self, alert_id: i64, chat_id: ChatId, coin: &str, market: MarketType, token: &str) -> Result<usize> {
+        let conn_guard = self.conn.lock().await;
+        let mut stmt = conn_guard.prepare("UPDATE alerts SET coin = ?, market = ?, token = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ? AND chat_id = ?")?;
+        let result = stmt.execute(params![coin, market, token, alert_id, chat_id.0])?;
+        Ok(result)
+    }
+
+    pub async fn update_alert_target(&self, alert_id: i64, chat_id: ChatId, target: &AlertTarget) -> Result<usize> {
+        let conn_guard = self.conn.lock().await;
+        let mut stmt = conn_guard.prepare(r#"
+            UPDATE alerts SET
+            price = ?,
+            direction = ?,
+            kind = ?,
+            change_pct = ?,
+            window_minutes = ?,
+            expression = ?,
+            alerted = false,
+            updated_at = CURRENT_TIMESTAMP
+            WHERE id = ? AND chat_id = ?
+            "#)?;
+        let result = stmt.execute(params![
+            target.stored_price(),
+            target.direction,
+            target.kind,
+            target.change_pct,
+            target.window_minutes,
+            target.expression,
+            alert_id,
+            chat_id.0
+        ])?;
+        Ok(result)
+    }
+
+    pub async fn set_alert_expiry(&self, alert_id: i64, chat_id: ChatId, expires_at: Option<DateTime<Utc>>) -> Result<usize> {
+        let conn_guard = self.conn.lock().await;
+        let mut stmt = conn_guard.prepare("UPDATE alerts SET expires_at = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ? AND chat_id = ?")?;
+        let result = stmt.execute(params![expires_at, alert_id, chat_id.0])?;
+        Ok(result)
+    }
+
+    pub async fn set_alert_window(&self, alert_id: i64, chat_id: ChatId, active_window: Option<&ActiveWindow>) -> Result<usize> {
+        let conn_guard = self.conn.lock().await;
+        let mut stmt = conn_guard.prepare("UPDATE alerts SET active_window = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ? AND chat_id = ?")?;
+        let result = stmt.execute(params![active_window, alert_id, chat_id.0])?;
+        Ok(result)
+    }
+
+    /// Returns the active alerts whose expiry is at or before `now`.
+    pub async fn get_expired_alerts(&self, now: DateTime<Utc>) -> Result<Vec<AlertTable>> {
+        let conn_guard = self.conn.lock().await;
+        let mut stmt = conn_guard.prepare("SELECT * FROM alerts WHERE is_active = true AND expires_at IS NOT NULL AND expires_at <= ?")?;
+        let alerts = stmt.query_map([now], alert_from_row)?.collect::<Result<Vec<AlertTable>>>()?;
+        Ok(alerts)
+    }
+
+    /// Deactivates an expired alert, or re-activates and re-arms one.
+    pub async fn set_alert_active(&self, alert_id: i64, is_active: bool) -> Result<usize> {
+        let conn_guard = self.conn.lock().await;
+        let mut stmt = conn_guard.prepare(r#"
+            UPDATE alerts SET
+            is_active = ?,
+            alerted = alerted AND NOT ?,
+            updated_at = CURRENT_TIMESTAMP
+            WHERE id = ?
+            "#)?;
+        let result = stmt.execute(params![is_active, is_active, alert_id])?;
+        Ok(result)
+    }
+
+    /// Disarms a fired alert. Cooldown alerts get a `cooldown_until` for
+    /// `reset_cooldowns` to pick up; one-shot and band alerts don't.
     pub async fn set_alert_cooldown(&self, alert_id: i64) -> Result<()> {
         let conn_guard = self.conn.lock().await;
-        let mut stmt = conn_guard.prepare("UPDATE alerts SET alerted = true, cooldown_until = datetime('now', '+1 minute') WHERE id = ?")?;
+        let mut stmt = conn_guard.prepare(r#"
+            UPDATE alerts SET
+            alerted = true,
+            cooldown_until = CASE
+                WHEN rearm_mode = 'cooldown' THEN datetime('now', '+' || CAST(rearm_value AS INTEGER) || ' seconds')
+                ELSE NULL
+            END
+            WHERE id = ?
+            "#)?;
         stmt.execute([alert_id])?;
         Ok(())
     }
 
+    /// Moves the extreme of the trailing alerts on `token` to `mark_px` when
+    /// it is a new high (for drops) or low (for rises). Fired alerts keep
+    /// tracking, so they measure from the extreme since they were set.
+    pub async fn update_trailing_extremes(&self, market: MarketType, token: &str, mark_px: f64) -> Result<usize> {
+        let conn_guard = self.conn.lock().await;
+        let mut stmt = conn_guard.prepare(r#"
+            UPDATE alerts SET price = ?
+            WHERE kind = 'trailing' AND token = ? AND market = ?
+            AND ((direction = 'below' AND price < ?) OR (direction = 'above' AND price > ?))
+            "#)?;
+        let result = stmt.execute(params![mark_px, token, market, mark_px, mark_px])?;
+        Ok(result)
+    }
+
+    /// Re-arms the fired band alerts on `token` whose target is at least
+    /// their band away from `mark_px`.
+    pub async fn rearm_band_alerts(&self, market: MarketType, token: &str, mark_px: f64) -> Result<usize> {
+        let conn_guard = self.conn.lock().await;
+        let mut stmt = conn_guard.prepare(r#"
+            UPDATE alerts SET alerted = false
+            WHERE alerted = true AND rearm_mode = 'band' AND kind = 'price' AND token = ? AND market = ?
+            AND abs(? - price) >= abs(price) * rearm_value / 100
+            "#)?;
+        let result = stmt.execute(params![token, market, mark_px])?;
+        Ok(result)
+    }
+
+    /// Changes how an alert owned by `chat_id` re-arms, and re-arms it.
+    pub async fn set_alert_rearm(&self, alert_id: i64, chat_id: ChatId, rearm: RearmMode) -> Result<usize> {
+        let conn_guard = self.conn.lock().await;
+        let mut stmt = conn_guard.prepare(r#"
+            UPDATE alerts SET
+            rearm_mode = ?,
+            rearm_value = ?,
+            alerted = false,
+            cooldown_until = NULL,
+            updated_at = CURRENT_TIMESTAMP
+            WHERE id = ? AND chat_id = ?
+            "#)?;
+        let result = stmt.execute(params![rearm.as_str(), rearm.value(), alert_id, chat_id.0])?;
+        Ok(result)
+    }
+
     pub async fn reset_cooldowns(&self) -> Result<usize> {
         let conn_guard = self.conn.lock().await;
         let mut stmt = conn_guard.prepare("UPDATE alerts SET alerted = false, cooldown_until = NULL WHERE cooldown_until < CURRENT_TIMESTAMP")?;
@@ -203,85 +1521,181 @@ impl Database {
     }
 
     // Cron alert methods
-    pub async fn insert_cron_alert(&self, chat_id: ChatId, coin: &str, token: &str, cron_schedule: &str) -> Result<()> {
+    pub async fn insert_cron_alert(&self, chat_id: ChatId, coin: &str, token: &str, cron_schedule: &str, template: ReportTemplate, next_trigger: DateTime<Utc>) -> Result<()> {
+        let conn_guard = self.conn.lock().await;
+        conn_guard.execute(r#"
+        INSERT INTO cron_alerts (chat_id, coin, token, cron_schedule, is_active, created_at, updated_at, next_trigger, template) 
+        VALUES (?, ?, ?, ?, ?, CURRENT_TIMESTAMP, CURRENT_TIMESTAMP, ?, ?)
+        "#, (chat_id.0, coin, token, cron_schedule, true, next_trigger, template))?;
+        Ok(())
+    }
+
+    /// Inserts a cron alert reporting on the account of `address`.
+    pub async fn insert_portfolio_cron_alert(&self, chat_id: ChatId, address: &str, cron_schedule: &str, next_trigger: DateTime<Utc>) -> Result<()> {
         let conn_guard = self.conn.lock().await;
-        let next_trigger = parse(cron_schedule, &chrono::Utc::now()).unwrap();
         conn_guard.execute(r#"
-        INSERT INTO cron_alerts (chat_id, coin, token, cron_schedule, is_active, created_at, updated_at, next_trigger) 
-        VALUES (?, ?, ?, ?, ?, CURRENT_TIMESTAMP, CURRENT_TIMESTAMP, ?)
-        "#, (chat_id.0, coin, token, cron_schedule, true, next_trigger))?;
+        INSERT INTO cron_alerts (chat_id, coin, token, cron_schedule, is_active, created_at, updated_at, next_trigger, kind, address)
+        VALUES (?, '', '', ?, ?, CURRENT_TIMESTAMP, CURRENT_TIMESTAMP, ?, ?, ?)
+        "#, (chat_id.0, cron_schedule, true, next_trigger, CronAlertKind::Portfolio, address))?;
         Ok(())
     }
 
     pub async fn get_all_cron_alerts(&self) -> Result<Vec<CronAlert>> {
         let conn_guard = self.conn.lock().await;
         let mut stmt = conn_guard.prepare("SELECT * FROM cron_alerts WHERE is_active = true")?;
-        let alerts = stmt.query_map([], |row| {
-            Ok(CronAlert {
-                id: row.get(0)?,
-                chat_id: row.get(1)?,
-                coin: row.get(2)?,
-                token: row.get(3)?  ,
-                cron_schedule: row.get(4)?,
-                is_active: row.get(5)?,
-                created_at: row.get::<_, DateTime<Utc>>(6)?,
-                updated_at: row.get::<_, DateTime<Utc>>(7)?,
-                last_triggered: row.get::<_, Option<DateTime<Utc>>>(8)?,
-                next_trigger: row.get::<_, Option<DateTime<Utc>>>(9)?,
-            })
-        })?.collect::<Result<Vec<CronAlert>>>()?;
+        let alerts = stmt.query_map([], cron_alert_from_row)?.collect::<Result<Vec<CronAlert>>>()?;
         Ok(alerts)
     }
 
     pub async fn get_cron_alerts_for_chat(&self, chat_id: ChatId) -> Result<Vec<CronAlert>> {
         let conn_guard = self.conn.lock().await;
         let mut stmt = conn_guard.prepare("SELECT * FROM cron_alerts WHERE chat_id = ? AND is_active = true")?;
-        let alerts = stmt.query_map([chat_id.0], |row| {
-            Ok(CronAlert {
-                id: row.get(0)?,
-                chat_id: row.get(1)?,
-                coin: row.get(2)?,
-                token: row.get(3)?,
-                cron_schedule: row.get(4)?,
-                is_active: row.get(5)?,
-                created_at: row.get::<_, DateTime<Utc>>(6)?,
-                updated_at: row.get::<_, DateTime<Utc>>(7)?,
-                last_triggered: row.get::<_, Option<DateTime<Utc>>>(8)?,
-                next_trigger: row.get::<_, Option<DateTime<Utc>>>(9)?,
-            })
-        })?.collect::<Result<Vec<CronAlert>>>()?;
+        let alerts = stmt.query_map([chat_id.0], cron_alert_from_row)?.collect::<Result<Vec<CronAlert>>>()?;
         Ok(alerts)
     }
 
-    pub async fn get_next_trigger_cron_alerts(&self) -> Result<Vec<CronAlert>> {
-        let conn_guard = self.conn.lock().await;
-        let mut stmt = conn_guard.prepare("SELECT * FROM cron_alerts WHERE next_trigger <= CURRENT_TIMESTAMP")?;
-        let alerts = stmt.query_map([], |row| {
-            Ok(CronAlert {
-                id: row.get(0)?,
-                chat_id: row.get(1)?,
-                coin: row.get(2)?,
-                token: row.get(3)?,
-                cron_schedule: row.get(4)?,
-                is_active: row.get(5)?,
-                created_at: row.get::<_, DateTime<Utc>>(6)?,
-                updated_at: row.get::<_, DateTime<Utc>>(7)?,
-                last_triggered: row.get::<_, Option<DateTime<Utc>>>(8)?,
-                next_trigger: row.get::<_, Option<DateTime<Utc>>>(9)?,
-            })
-        })?.collect::<Result<Vec<CronAlert>>>()?;
+    pub async fn get_cron_alert(&self, alert_id: i64) -> Result<Option<CronAlert>> {
+        let conn_guard = self.conn.lock().await;
+        let mut stmt = conn_guard.prepare("SELECT * FROM cron_alerts WHERE id = ?")?;
+        stmt.query_map([alert_id], cron_alert_from_row)?.next().transpose()
+    }
+
+    /// Returns the active cron alerts due at `now`.
+    pub async fn get_next_trigger_cron_alerts(&self, now: DateTime<Utc>) -> Result<Vec<CronAlert>> {
+        let conn_guard = self.conn.lock().await;
+        let mut stmt = conn_guard.prepare("SELECT * FROM cron_alerts WHERE is_active = true AND next_trigger <= ?")?;
+        let alerts = stmt.query_map([now], cron_alert_from_row)?.collect::<Result<Vec<CronAlert>>>()?;
         Ok(alerts)
     }
 
-    pub async fn update_cron_alert_last_triggered(&self, alert_id: i64, next_trigger: DateTime<Utc>) -> Result<()> {
+    /// The earliest next trigger of any active cron alert.
+    pub async fn get_earliest_cron_trigger(&self) -> Result<Option<DateTime<Utc>>> {
         let conn_guard = self.conn.lock().await;
-        let mut stmt = conn_guard.prepare(r#"
-            UPDATE cron_alerts SET 
-            last_triggered = CURRENT_TIMESTAMP, 
-            updated_at = CURRENT_TIMESTAMP, 
-            next_trigger = ?
-            WHERE id = ?
-            "#)?;
+        conn_guard.query_row("SELECT MIN(next_trigger) FROM cron_alerts WHERE is_active = true", [], |row| row.get(0))
+    }
+
+    pub async fn set_cron_alert_misfire(&self, alert_id: i64, chat_id: ChatId, misfire: MisfirePolicy) -> Result<usize> {
+        let conn_guard = self.conn.lock().await;
+        let mut stmt = conn_guard.prepare("UPDATE cron_alerts SET misfire = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ? AND chat_id = ?")?;
+        stmt.execute(params![misfire, alert_id, chat_id.0])
+    }
+
+    // Cron delivery methods
+    /// Claims `fires` of a cron alert and moves its next trigger from
+    /// `due_at` to `next_trigger`, in one transaction. Returns nothing if the
+    /// alert is no longer due at `due_at`, since someone else claimed it, and
+    /// skips fires that already have a delivery.
+    pub async fn claim_cron_deliveries(
+        &self,
+        alert_id: i64,
+        due_at: DateTime<Utc>,
+        fires: &[DateTime<Utc>],
+        next_trigger: DateTime<Utc>,
+    ) -> Result<Vec<CronDelivery>> {
+        let conn_guard = self.conn.lock().await;
+        let tx = conn_guard.unchecked_transaction()?;
+        let moved = tx.execute(
+            "UPDATE cron_alerts SET next_trigger = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ? AND next_trigger = ?",
+            params![next_trigger, alert_id, due_at],
+        )?;
+        if moved == 0 {
+            return Ok(Vec::new());
+        }
+        let mut ids = Vec::new();
+        for scheduled_for in fires {
+            let inserted = tx.execute(
+                r#"
+                INSERT OR IGNORE INTO cron_deliveries (cron_alert_id, scheduled_for, status, claimed_at)
+                VALUES (?, ?, ?, ?)
+                "#,
+                params![alert_id, scheduled_for, DeliveryStatus::Claimed, Utc::now()],
+            )?;
+            if inserted > 0 {
+                ids.push(tx.last_insert_rowid());
+            }
+        }
+        let mut stmt = tx.prepare("SELECT * FROM cron_deliveries WHERE id = ?")?;
+        let deliveries = ids
+            .into_iter()
+            .map(|id| stmt.query_row([id], cron_delivery_from_row))
+            .collect::<Result<Vec<CronDelivery>>>()?;
+        drop(stmt);
+        tx.commit()?;
+        Ok(deliveries)
+    }
+
+    /// Records that a claimed delivery reached its chat.
+    pub async fn complete_cron_delivery(&self, delivery_id: i64) -> Result<()> {
+        let conn_guard = self.conn.lock().await;
+        let tx = conn_guard.unchecked_transaction()?;
+        tx.execute(
+            "UPDATE cron_deliveries SET status = ?, attempts = attempts + 1, error = NULL, completed_at = ? WHERE id = ?",
+            params![DeliveryStatus::Sent, Utc::now(), delivery_id],
+        )?;
+        tx.execute(
+            r#"
+            UPDATE cron_alerts SET last_triggered = CURRENT_TIMESTAMP, updated_at = CURRENT_TIMESTAMP
+            WHERE id = (SELECT cron_alert_id FROM cron_deliveries WHERE id = ?)
+            "#,
+            [delivery_id],
+        )?;
+        tx.commit()
+    }
+
+    /// Records that a claimed delivery could not be sent.
+    pub async fn fail_cron_delivery(&self, delivery_id: i64, error: &str) -> Result<()> {
+        let conn_guard = self.conn.lock().await;
+        conn_guard.execute(
+            "UPDATE cron_deliveries SET status = ?, attempts = attempts + 1, error = ? WHERE id = ?",
+            params![DeliveryStatus::Failed, error, delivery_id],
+        )?;
+        Ok(())
+    }
+
+    /// Claims the failed deliveries with fewer than `max_attempts` attempts
+    /// whose last attempt was claimed at or before `retry_before`, for
+    /// another try.
+    pub async fn claim_failed_cron_deliveries(&self, max_attempts: i64, retry_before: DateTime<Utc>) -> Result<Vec<CronDelivery>> {
+        let conn_guard = self.conn.lock().await;
+        let mut stmt = conn_guard.prepare(
+            "UPDATE cron_deliveries SET status = ?, claimed_at = ? WHERE status = ? AND attempts < ? AND claimed_at <= ? RETURNING *",
+        )?;
+        let deliveries = stmt
+            .query_map(params![DeliveryStatus::Claimed, Utc::now(), DeliveryStatus::Failed, max_attempts, retry_before], cron_delivery_from_row)?
+            .collect::<Result<Vec<CronDelivery>>>()?;
+        Ok(deliveries)
+    }
+
+    /// Whether any failed delivery has fewer than `max_attempts` attempts.
+    pub async fn has_failed_cron_deliveries(&self, max_attempts: i64) -> Result<bool> {
+        let conn_guard = self.conn.lock().await;
+        conn_guard.query_row(
+            "SELECT EXISTS (SELECT 1 FROM cron_deliveries WHERE status = ? AND attempts < ?)",
+            params![DeliveryStatus::Failed, max_attempts],
+            |row| row.get(0),
+        )
+    }
+
+    /// Marks every delivery still claimed as abandoned and returns how many
+    /// there were. Only call this before the worker starts.
+    pub async fn abandon_claimed_cron_deliveries(&self) -> Result<usize> {
+        let conn_guard = self.conn.lock().await;
+        conn_guard.execute(
+            "UPDATE cron_deliveries SET status = ?, completed_at = ? WHERE status = ?",
+            params![DeliveryStatus::Abandoned, Utc::now(), DeliveryStatus::Claimed],
+        )
+    }
+
+    pub async fn get_cron_deliveries(&self, alert_id: i64) -> Result<Vec<CronDelivery>> {
+        let conn_guard = self.conn.lock().await;
+        let mut stmt = conn_guard.prepare("SELECT * FROM cron_deliveries WHERE cron_alert_id = ? ORDER BY scheduled_for")?;
+        let deliveries = stmt.query_map([alert_id], cron_delivery_from_row)?.collect::<Result<Vec<CronDelivery>>>()?;
+        Ok(deliveries)
+    }
+
+    pub async fn update_cron_alert_next_trigger(&self, alert_id: i64, next_trigger: DateTime<Utc>) -> Result<()> {
+        let conn_guard = self.conn.lock().await;
+        let mut stmt = conn_guard.prepare("UPDATE cron_alerts SET next_trigger = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?")?;
         stmt.execute(params![next_trigger, alert_id])?;
         Ok(())
     }
@@ -293,10 +1707,214 @@ impl Database {
         Ok(())
     }
 
-    pub async fn delete_cron_alert(&self, alert_id: i64) -> Result<()> {
+    pub async fn delete_cron_alert(&self, alert_id: i64, chat_id: ChatId) -> Result<usize> {
         let conn_guard = self.conn.lock().await;
-        let mut stmt = conn_guard.prepare("DELETE FROM cron_alerts WHERE id = ?")?;
-        stmt.execute([alert_id])?;
+        let tx = conn_guard.unchecked_transaction()?;
+        let result = tx.execute("DELETE FROM cron_alerts WHERE id = ? AND chat_id = ?", params![alert_id, chat_id.0])?;
+        if result > 0 {
+            tx.execute("DELETE FROM cron_deliveries WHERE cron_alert_id = ?", [alert_id])?;
+        }
+        tx.commit()?;
+        Ok(result)
+    }
+
+    // Watched address methods
+    pub async fn upsert_watched_address(&self, chat_id: ChatId, address: &str, liquidation_threshold: f64, tpsl_threshold: f64) -> Result<()> {
+        let conn_guard = self.conn.lock().await;
+        conn_guard.execute(r#"
+        INSERT INTO watched_addresses (chat_id, address, liquidation_threshold, tpsl_threshold, created_at, updated_at)
+        VALUES (?, ?, ?, ?, CURRENT_TIMESTAMP, CURRENT_TIMESTAMP)
+        ON CONFLICT (chat_id, address) DO UPDATE SET
+            liquidation_threshold = excluded.liquidation_threshold,
+            tpsl_threshold = excluded.tpsl_threshold,
+            updated_at = CURRENT_TIMESTAMP
+        "#, params![chat_id.0, address, liquidation_threshold, tpsl_threshold])?;
         Ok(())
     }
+
+    pub async fn delete_watched_address(&self, chat_id: ChatId, address: &str) -> Result<usize> {
+        let conn_guard = self.conn.lock().await;
+        let mut stmt = conn_guard.prepare("DELETE FROM watched_addresses WHERE chat_id = ? AND address = ?")?;
+        let result = stmt.execute(params![chat_id.0, address])?;
+        Ok(result)
+    }
+
+    pub async fn get_watched_addresses_for_chat(&self, chat_id: ChatId) -> Result<Vec<WatchedAddress>> {
+        let conn_guard = self.conn.lock().await;
+        let mut stmt = conn_guard.prepare("SELECT * FROM watched_addresses WHERE chat_id = ? ORDER BY id")?;
+        let addresses = stmt.query_map([chat_id.0], watched_address_from_row)?.collect::<Result<Vec<WatchedAddress>>>()?;
+        Ok(addresses)
+    }
+
+    pub async fn get_watchers_for_address(&self, address: &str) -> Result<Vec<WatchedAddress>> {
+        let conn_guard = self.conn.lock().await;
+        let mut stmt = conn_guard.prepare("SELECT * FROM watched_addresses WHERE address = ?")?;
+        let addresses = stmt.query_map([address], watched_address_from_row)?.collect::<Result<Vec<WatchedAddress>>>()?;
+        Ok(addresses)
+    }
+
+    pub async fn get_all_watched_addresses(&self) -> Result<Vec<WatchedAddress>> {
+        let conn_guard = self.conn.lock().await;
+        let mut stmt = conn_guard.prepare("SELECT * FROM watched_addresses")?;
+        let addresses = stmt.query_map([], watched_address_from_row)?.collect::<Result<Vec<WatchedAddress>>>()?;
+        Ok(addresses)
+    }
+
+    // Chat settings methods
+    pub async fn get_chat_account_events(&self, chat_id: ChatId) -> Result<Option<String>> {
+        let conn_guard = self.conn.lock().await;
+        let mut stmt = conn_guard.prepare("SELECT account_events FROM chat_settings WHERE chat_id = ?")?;
+        let events = stmt.query_map([chat_id.0], |row| row.get::<_, Option<String>>(0))?.next().transpose()?;
+        Ok(events.flatten())
+    }
+
+    pub async fn set_chat_account_events(&self, chat_id: ChatId, account_events: &str) -> Result<()> {
+        let conn_guard = self.conn.lock().await;
+        conn_guard.execute(r#"
+        INSERT INTO chat_settings (chat_id, account_events, created_at, updated_at)
+        VALUES (?, ?, CURRENT_TIMESTAMP, CURRENT_TIMESTAMP)
+        ON CONFLICT (chat_id) DO UPDATE SET
+            account_events = excluded.account_events,
+            updated_at = CURRENT_TIMESTAMP
+        "#, params![chat_id.0, account_events])?;
+        Ok(())
+    }
+
+    /// Opts `chat_id` in or out of listing notifications. Returns `false` if
+    /// the chat already had that setting.
+    pub async fn set_chat_listings(&self, chat_id: ChatId, listings: bool) -> Result<bool> {
+        let conn_guard = self.conn.lock().await;
+        let mut stmt = conn_guard.prepare("SELECT listings FROM chat_settings WHERE chat_id = ?")?;
+        let current = stmt.query_map([chat_id.0], |row| row.get::<_, Option<bool>>(0))?.next().transpose()?;
+        if current.flatten().unwrap_or_default() == listings {
+            return Ok(false);
+        }
+        conn_guard.execute(r#"
+        INSERT INTO chat_settings (chat_id, listings, created_at, updated_at)
+        VALUES (?, ?, CURRENT_TIMESTAMP, CURRENT_TIMESTAMP)
+        ON CONFLICT (chat_id) DO UPDATE SET
+            listings = excluded.listings,
+            updated_at = CURRENT_TIMESTAMP
+        "#, params![chat_id.0, listings])?;
+        Ok(true)
+    }
+
+    /// Returns the timezone `chat_id` set, or UTC if it never set one.
+    pub async fn get_chat_timezone(&self, chat_id: ChatId) -> Result<Tz> {
+        let conn_guard = self.conn.lock().await;
+        let mut stmt = conn_guard.prepare("SELECT timezone FROM chat_settings WHERE chat_id = ?")?;
+        let timezone = stmt.query_map([chat_id.0], |row| row.get::<_, Option<String>>(0))?.next().transpose()?.flatten();
+        match timezone {
+            Some(timezone) => timezone
+                .parse()
+                .map_err(|e: chrono_tz::ParseError| rusqlite::Error::FromSqlConversionFailure(0, rusqlite::types::Type::Text, e.into())),
+            None => Ok(Tz::UTC),
+        }
+    }
+
+    pub async fn set_chat_timezone(&self, chat_id: ChatId, timezone: Tz) -> Result<()> {
+        let conn_guard = self.conn.lock().await;
+        conn_guard.execute(r#"
+        INSERT INTO chat_settings (chat_id, timezone, created_at, updated_at)
+        VALUES (?, ?, CURRENT_TIMESTAMP, CURRENT_TIMESTAMP)
+        ON CONFLICT (chat_id) DO UPDATE SET
+            timezone = excluded.timezone,
+            updated_at = CURRENT_TIMESTAMP
+        "#, params![chat_id.0, timezone.name()])?;
+        Ok(())
+    }
+
+    /// Returns the chats that opted in to listing notifications.
+    pub async fn get_listing_chats(&self) -> Result<Vec<i64>> {
+        let conn_guard = self.conn.lock().await;
+        let mut stmt = conn_guard.prepare("SELECT chat_id FROM chat_settings WHERE listings = true ORDER BY chat_id")?;
+        let chats = stmt.query_map([], |row| row.get(0))?.collect::<Result<Vec<i64>>>()?;
+        Ok(chats)
+    }
+
+    // Market listing methods
+    pub async fn get_market_listings(&self) -> Result<Vec<MarketListing>> {
+        let conn_guard = self.conn.lock().await;
+        let mut stmt = conn_guard.prepare("SELECT kind, name, label FROM market_listings")?;
+        let listings = stmt.query_map([], market_listing_from_row)?.collect::<Result<Vec<MarketListing>>>()?;
+        Ok(listings)
+    }
+
+    /// Adds `listed` to and removes `delisted` from the listings snapshot.
+    pub async fn update_market_listings(&self, listed: &[MarketListing], delisted: &[MarketListing]) -> Result<()> {
+        let conn_guard = self.conn.lock().await;
+        let tx = conn_guard.unchecked_transaction()?;
+        for listing in listed {
+            tx.execute(
+                "INSERT OR REPLACE INTO market_listings (kind, name, label, created_at) VALUES (?, ?, ?, CURRENT_TIMESTAMP)",
+                params![listing.kind, listing.name, listing.label],
+            )?;
+        }
+        for listing in delisted {
+            tx.execute("DELETE FROM market_listings WHERE kind = ? AND name = ?", params![listing.kind, listing.name])?;
+        }
+        tx.commit()
+    }
+
+    // Whale alert methods
+    /// Inserts or replaces the whale alert of `chat_id` on `token`. Returns
+    /// `true` if the chat had no whale alert on the token yet.
+    pub async fn upsert_whale_alert(
+        &self,
+        chat_id: ChatId,
+        coin: &str,
+        token: &str,
+        market: MarketType,
+        min_notional: f64,
+        burst_seconds: Option<i64>,
+    ) -> Result<bool> {
+        let conn_guard = self.conn.lock().await;
+        let mut stmt = conn_guard.prepare("SELECT 1 FROM whale_alerts WHERE chat_id = ? AND token = ?")?;
+        let exists = stmt.exists(params![chat_id.0, token])?;
+        conn_guard.execute(r#"
+        INSERT INTO whale_alerts (chat_id, coin, token, market, min_notional, burst_seconds, created_at, updated_at)
+        VALUES (?, ?, ?, ?, ?, ?, CURRENT_TIMESTAMP, CURRENT_TIMESTAMP)
+        ON CONFLICT (chat_id, token) DO UPDATE SET
+            coin = excluded.coin,
+            min_notional = excluded.min_notional,
+            burst_seconds = excluded.burst_seconds,
+            updated_at = CURRENT_TIMESTAMP
+        "#, params![chat_id.0, coin, token, market, min_notional, burst_seconds])?;
+        Ok(!exists)
+    }
+
+    pub async fn get_whale_alert_for_chat(&self, alert_id: i64, chat_id: ChatId) -> Result<Option<WhaleAlert>> {
+        let conn_guard = self.conn.lock().await;
+        let mut stmt = conn_guard.prepare("SELECT * FROM whale_alerts WHERE id = ? AND chat_id = ?")?;
+        let alert = stmt.query_map(params![alert_id, chat_id.0], whale_alert_from_row)?.next().transpose()?;
+        Ok(alert)
+    }
+
+    pub async fn delete_whale_alert(&self, alert_id: i64, chat_id: ChatId) -> Result<usize> {
+        let conn_guard = self.conn.lock().await;
+        let mut stmt = conn_guard.prepare("DELETE FROM whale_alerts WHERE id = ? AND chat_id = ?")?;
+        let result = stmt.execute(params![alert_id, chat_id.0])?;
+        Ok(result)
+    }
+
+    pub async fn get_whale_alerts_for_chat(&self, chat_id: ChatId) -> Result<Vec<WhaleAlert>> {
+        let conn_guard = self.conn.lock().await;
+        let mut stmt = conn_guard.prepare("SELECT * FROM whale_alerts WHERE chat_id = ? ORDER BY id")?;
+        let alerts = stmt.query_map([chat_id.0], whale_alert_from_row)?.collect::<Result<Vec<WhaleAlert>>>()?;
+        Ok(alerts)
+    }
+
+    pub async fn get_whale_alerts_for_token(&self, token: &str) -> Result<Vec<WhaleAlert>> {
+        let conn_guard = self.conn.lock().await;
+        let mut stmt = conn_guard.prepare("SELECT * FROM whale_alerts WHERE token = ?")?;
+        let alerts = stmt.query_map([token], whale_alert_from_row)?.collect::<Result<Vec<WhaleAlert>>>()?;
+        Ok(alerts)
+    }
+
+    pub async fn get_all_whale_alerts(&self) -> Result<Vec<WhaleAlert>> {
+        let conn_guard = self.conn.lock().await;
+        let mut stmt = conn_guard.prepare("SELECT * FROM whale_alerts")?;
+        let alerts = stmt.query_map([], whale_alert_from_row)?.collect::<Result<Vec<WhaleAlert>>>()?;
+        Ok(alerts)
+    }
 }
@@ -0,0 +1,330 @@
+use crate::db::Database;
+use ethers::types::H160;
+use hyperliquid_rust_sdk::{InfoClient, LedgerUpdate, Message, Subscription, TradeInfo, UserFunding};
+use log::{error, info};
+use rusqlite::Result;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use teloxide::types::ChatId;
+use tokio::sync::Mutex;
+use tokio::sync::mpsc::UnboundedSender;
+
+/// The account events a chat can opt in to. Everything except fills and
+/// funding maps to a `LedgerUpdate` variant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AccountEventKind {
+    Fill,
+    Funding,
+    Deposit,
+    Withdraw,
+    InternalTransfer,
+    SubAccountTransfer,
+    Liquidation,
+    VaultDeposit,
+    VaultCreate,
+    VaultDistribution,
+    VaultWithdraw,
+    VaultLeaderCommission,
+    AccountClassTransfer,
+    SpotTransfer,
+    SpotGenesis,
+}
+
+impl AccountEventKind {
+    pub const ALL: [AccountEventKind; 15] = [
+        AccountEventKind::Fill,
+        AccountEventKind::Funding,
+        AccountEventKind::Deposit,
+        AccountEventKind::Withdraw,
+        AccountEventKind::InternalTransfer,
+        AccountEventKind::SubAccountTransfer,
+        AccountEventKind::Liquidation,
+        AccountEventKind::VaultDeposit,
+        AccountEventKind::VaultCreate,
+        AccountEventKind::VaultDistribution,
+        AccountEventKind::VaultWithdraw,
+        AccountEventKind::VaultLeaderCommission,
+        AccountEventKind::AccountClassTransfer,
+        AccountEventKind::SpotTransfer,
+        AccountEventKind::SpotGenesis,
+    ];
+
+    /// Enabled for chats that have not chosen their own events.
+    pub const DEFAULT: [AccountEventKind; 5] = [
+        AccountEventKind::Fill,
+        AccountEventKind::Funding,
+        AccountEventKind::Deposit,
+        AccountEventKind::Withdraw,
+        AccountEventKind::Liquidation,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AccountEventKind::Fill => "fill",
+            AccountEventKind::Funding => "funding",
+            AccountEventKind::Deposit => "deposit",
+            AccountEventKind::Withdraw => "withdraw",
+            AccountEventKind::InternalTransfer => "internaltransfer",
+            AccountEventKind::SubAccountTransfer => "subaccounttransfer",
+            AccountEventKind::Liquidation => "liquidation",
+            AccountEventKind::VaultDeposit => "vaultdeposit",
+            AccountEventKind::VaultCreate => "vaultcreate",
+            AccountEventKind::VaultDistribution => "vaultdistribution",
+            AccountEventKind::VaultWithdraw => "vaultwithdraw",
+            AccountEventKind::VaultLeaderCommission => "vaultleadercommission",
+            AccountEventKind::AccountClassTransfer => "accountclasstransfer",
+            AccountEventKind::SpotTransfer => "spottransfer",
+            AccountEventKind::SpotGenesis => "spotgenesis",
+        }
+    }
+}
+
+impl std::str::FromStr for AccountEventKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let s = s.to_lowercase();
+        AccountEventKind::ALL
+            .into_iter()
+            .find(|kind| kind.as_str() == s)
+            .ok_or_else(|| anyhow::anyhow!("Unknown event: {s}"))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AccountEvent {
+    pub address: String,
+    pub kind: AccountEventKind,
+    pub description: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct AccountNotification {
+    pub chat_id: i64,
+    pub event: AccountEvent,
+}
+
+impl std::fmt::Display for AccountNotification {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} [{}]", self.event.description, self.event.address)
+    }
+}
+
+fn describe_fill(fill: &TradeInfo) -> String {
+    let side = if fill.side == "B" { "Bought" } else { "Sold" };
+    format!(
+        "🟢 {side} {} {} @ {} ({}, closed PnL {}, fee {} {})",
+        fill.sz, fill.coin, fill.px, fill.dir, fill.closed_pnl, fill.fee, fill.fee_token
+    )
+}
+
+fn describe_funding(funding: &UserFunding) -> String {
+    format!(
+        "💸 Funding on {}: {} USDC (rate {}, size {})",
+        funding.coin, funding.usdc, funding.funding_rate, funding.szi
+    )
+}
+
+fn describe_ledger_update(update: &LedgerUpdate) -> (AccountEventKind, String) {
+    match update {
+        LedgerUpdate::Deposit(deposit) => (AccountEventKind::Deposit, format!("📥 Deposit of {} USDC", deposit.usdc)),
+        LedgerUpdate::Withdraw(withdraw) => (
+            AccountEventKind::Withdraw,
+            format!("📤 Withdrawal of {} USDC (fee {})", withdraw.usdc, withdraw.fee),
+        ),
+        LedgerUpdate::InternalTransfer(transfer) => (
+            AccountEventKind::InternalTransfer,
+            format!("🔁 Transfer of {} USDC from {:?} to {:?}", transfer.usdc, transfer.user, transfer.destination),
+        ),
+        LedgerUpdate::SubAccountTransfer(transfer) => (
+            AccountEventKind::SubAccountTransfer,
+            format!("🔁 Sub-account transfer of {} USDC from {:?} to {:?}", transfer.usdc, transfer.user, transfer.destination),
+        ),
+        LedgerUpdate::LedgerLiquidation(liquidation) => {
+            let positions = liquidation
+                .liquidated_positions
+                .iter()
+                .map(|position| format!("{} {}", position.szi, position.coin))
+                .collect::<Vec<String>>()
+                .join(", ");
+            (
+                AccountEventKind::Liquidation,
+                format!(
+                    "🚨 Liquidated ({}): {positions} (account value {})",
+                    liquidation.leverage_type, liquidation.account_value
+                ),
+            )
+        }
+        LedgerUpdate::VaultDeposit(delta) => (
+            AccountEventKind::VaultDeposit,
+            format!("🏦 Vault deposit of {} USDC into {:?}", delta.usdc, delta.vault),
+        ),
+        LedgerUpdate::VaultCreate(delta) => (
+            AccountEventKind::VaultCreate,
+            format!("🏦 Created vault {:?} with {} USDC", delta.vault, delta.usdc),
+        ),
+        LedgerUpdate::VaultDistribution(delta) => (
+            AccountEventKind::VaultDistribution,
+            format!("🏦 Vault distribution of {} USDC from {:?}", delta.usdc, delta.vault),
+        ),
+        LedgerUpdate::VaultWithdraw(withdraw) => (
+            AccountEventKind::VaultWithdraw,
+            format!(
+                "🏦 Vault withdrawal of {} USD from {:?} (net {})",
+                withdraw.requested_usd, withdraw.vault, withdraw.net_withdrawn_usd
+            ),
+        ),
+        LedgerUpdate::VaultLeaderCommission(commission) => (
+            AccountEventKind::VaultLeaderCommission,
+            format!("🏦 Vault leader commission of {} USDC", commission.usdc),
+        ),
+        LedgerUpdate::AccountClassTransfer(transfer) => {
+            let direction = if transfer.to_perp { "spot to perp" } else { "perp to spot" };
+            (
+                AccountEventKind::AccountClassTransfer,
+                format!("🔁 Moved {} USDC {direction}", transfer.usdc),
+            )
+        }
+        LedgerUpdate::SpotTransfer(transfer) => (
+            AccountEventKind::SpotTransfer,
+            format!(
+                "🔁 Spot transfer of {} {} (${}) from {:?} to {:?}",
+                transfer.amount, transfer.token, transfer.usdc_value, transfer.user, transfer.destination
+            ),
+        ),
+        LedgerUpdate::SpotGenesis(genesis) => (
+            AccountEventKind::SpotGenesis,
+            format!("🎁 Spot genesis of {} {}", genesis.amount, genesis.token),
+        ),
+    }
+}
+
+/// Streams fills, funding payments and non-funding ledger updates for every
+/// watched address and routes them to the chats that opted in to them.
+#[derive(Clone)]
+pub struct AccountEventService {
+    db: Database,
+    info_client: Arc<Mutex<InfoClient>>,
+    sender: UnboundedSender<Message>,
+    subscriptions: Arc<Mutex<HashMap<String, Vec<u32>>>>,
+}
+
+impl AccountEventService {
+    pub fn new(db: Database, info_client: InfoClient, sender: UnboundedSender<Message>) -> Self {
+        Self {
+            db,
+            info_client: Arc::new(Mutex::new(info_client)),
+            sender,
+            subscriptions: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub async fn get_enabled_events(&self, chat_id: ChatId) -> Result<Vec<AccountEventKind>> {
+        let Some(events) = self.db.get_chat_account_events(chat_id).await? else {
+            return Ok(AccountEventKind::DEFAULT.to_vec());
+        };
+        Ok(events.split(',').filter_map(|event| event.parse().ok()).collect())
+    }
+
+    pub async fn set_enabled_events(&self, chat_id: ChatId, events: &[AccountEventKind]) -> Result<()> {
+        let events = events.iter().map(|event| event.as_str()).collect::<Vec<&str>>().join(",");
+        self.db.set_chat_account_events(chat_id, &events).await
+    }
+
+    /// Subscribes to the account streams of newly watched addresses and
+    /// unsubscribes from addresses nobody watches anymore.
+    pub async fn sync_subscriptions(&self) -> anyhow::Result<()> {
+        let addresses: HashSet<String> = self
+            .db
+            .get_all_watched_addresses()
+            .await?
+            .into_iter()
+            .map(|watched| watched.address)
+            .collect();
+
+        let mut subscriptions = self.subscriptions.lock().await;
+        let mut info_client = self.info_client.lock().await;
+        let stale: Vec<String> = subscriptions.keys().filter(|address| !addresses.contains(*address)).cloned().collect();
+        for address in stale {
+            info!("Unsubscribing from account events for {address}");
+            for subscription_id in subscriptions.remove(&address).unwrap_or_default() {
+                if let Err(e) = info_client.unsubscribe(subscription_id).await {
+                    error!("Failed to unsubscribe from account events for {address}: {e}");
+                }
+            }
+        }
+
+        for address in addresses {
+            if subscriptions.contains_key(&address) {
+                continue;
+            }
+            let user = address.parse::<H160>()?;
+            info!("Subscribing to account events for {address}");
+            let mut subscription_ids = Vec::new();
+            for subscription in [
+                Subscription::UserFills { user },
+                Subscription::UserFundings { user },
+                Subscription::UserNonFundingLedgerUpdates { user },
+            ] {
+                subscription_ids.push(info_client.subscribe(subscription, self.sender.clone()).await?);
+            }
+            subscriptions.insert(address, subscription_ids);
+        }
+        Ok(())
+    }
+
+    /// Turns an account stream message into notifications for every chat
+    /// watching the address that has the event enabled. Snapshots sent on
+    /// subscribe are skipped so history isn't replayed.
+    pub async fn handle_message(&self, message: Message) -> Result<Vec<AccountNotification>> {
+        let (user, events) = match message {
+            Message::UserFills(fills) if fills.data.is_snapshot != Some(true) => (
+                fills.data.user,
+                fills
+                    .data
+                    .fills
+                    .iter()
+                    .map(|fill| (AccountEventKind::Fill, describe_fill(fill)))
+                    .collect::<Vec<_>>(),
+            ),
+            Message::UserFundings(fundings) if fundings.data.is_snapshot != Some(true) => (
+                fundings.data.user,
+                fundings
+                    .data
+                    .fundings
+                    .iter()
+                    .map(|funding| (AccountEventKind::Funding, describe_funding(funding)))
+                    .collect(),
+            ),
+            Message::UserNonFundingLedgerUpdates(updates) if updates.data.is_snapshot != Some(true) => (
+                updates.data.user,
+                updates
+                    .data
+                    .non_funding_ledger_updates
+                    .iter()
+                    .map(|update| describe_ledger_update(&update.delta))
+                    .collect(),
+            ),
+            _ => return Ok(Vec::new()),
+        };
+
+        let address = format!("{user:?}");
+        let mut notifications = Vec::new();
+        for watcher in self.db.get_watchers_for_address(&address).await? {
+            let enabled = self.get_enabled_events(ChatId(watcher.chat_id)).await?;
+            for (kind, description) in &events {
+                if enabled.contains(kind) {
+                    notifications.push(AccountNotification {
+                        chat_id: watcher.chat_id,
+                        event: AccountEvent {
+                            address: address.clone(),
+                            kind: *kind,
+                            description: description.clone(),
+                        },
+                    });
+                }
+            }
+        }
+        Ok(notifications)
+    }
+}
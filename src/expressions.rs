@@ -0,0 +1,523 @@
+use std::collections::HashMap;
+
+/// Most markets one expression may read, to keep subscriptions bounded.
+pub const MAX_EXPRESSION_MARKETS: usize = 8;
+
+/// A value of a market an expression can read. Funding is the hourly rate
+/// in percent, premium the mark/oracle premium in bps and open interest is
+/// in coins; all three are perp-only.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Metric {
+    Price,
+    Funding,
+    OpenInterest,
+    Premium,
+}
+
+impl Metric {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Metric::Price => "price",
+            Metric::Funding => "funding",
+            Metric::OpenInterest => "oi",
+            Metric::Premium => "premium",
+        }
+    }
+
+    fn parse(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "price" => Some(Metric::Price),
+            "funding" => Some(Metric::Funding),
+            "oi" => Some(Metric::OpenInterest),
+            "premium" => Some(Metric::Premium),
+            _ => None,
+        }
+    }
+}
+
+/// The latest asset context of one market, as read by expressions.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MarketSnapshot {
+    pub mark_px: f64,
+    /// Only present for perps.
+    pub funding_pct: Option<f64>,
+    pub premium_bps: Option<f64>,
+    pub open_interest: Option<f64>,
+}
+
+impl MarketSnapshot {
+    pub fn value(&self, metric: Metric) -> Option<f64> {
+        match metric {
+            Metric::Price => Some(self.mark_px),
+            Metric::Funding => self.funding_pct,
+            Metric::OpenInterest => self.open_interest,
+            Metric::Premium => self.premium_bps,
+        }
+    }
+}
+
+/// The latest asset contexts per token, along with the token that was just
+/// updated and its context before the update.
+pub struct ExpressionTick<'a> {
+    pub token: &'a str,
+    pub previous: Option<MarketSnapshot>,
+    pub contexts: &'a HashMap<String, MarketSnapshot>,
+}
+
+impl ExpressionTick<'_> {
+    /// The context of `token` before this update.
+    pub fn before(&self, token: &str) -> Option<MarketSnapshot> {
+        if token == self.token {
+            return self.previous;
+        }
+        self.contexts.get(token).copied()
+    }
+
+    /// The context of `token` after this update.
+    pub fn after(&self, token: &str) -> Option<MarketSnapshot> {
+        self.contexts.get(token).copied()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArithmeticOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+}
+
+impl ArithmeticOp {
+    fn as_str(&self) -> &'static str {
+        match self {
+            ArithmeticOp::Add => "+",
+            ArithmeticOp::Subtract => "-",
+            ArithmeticOp::Multiply => "*",
+            ArithmeticOp::Divide => "/",
+        }
+    }
+
+    fn precedence(&self) -> u8 {
+        match self {
+            ArithmeticOp::Add | ArithmeticOp::Subtract => 1,
+            ArithmeticOp::Multiply | ArithmeticOp::Divide => 2,
+        }
+    }
+
+    fn apply(&self, lhs: f64, rhs: f64) -> Option<f64> {
+        match self {
+            ArithmeticOp::Add => Some(lhs + rhs),
+            ArithmeticOp::Subtract => Some(lhs - rhs),
+            ArithmeticOp::Multiply => Some(lhs * rhs),
+            ArithmeticOp::Divide => Some(lhs / rhs).filter(|_| rhs != 0.0),
+        }
+    }
+}
+
+/// A number computed from market values, e.g. `price(ETH-PERP) / price(BTC-PERP)`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Number(f64),
+    Market { metric: Metric, coin: String },
+    Binary { lhs: Box<Value>, op: ArithmeticOp, rhs: Box<Value> },
+}
+
+impl Value {
+    fn evaluate(&self, value: &impl Fn(Metric, &str) -> Option<f64>) -> Option<f64> {
+        match self {
+            Value::Number(number) => Some(*number),
+            Value::Market { metric, coin } => value(*metric, coin),
+            Value::Binary { lhs, op, rhs } => op.apply(lhs.evaluate(value)?, rhs.evaluate(value)?),
+        }
+    }
+
+    fn precedence(&self) -> u8 {
+        match self {
+            Value::Binary { op, .. } => op.precedence(),
+            _ => u8::MAX,
+        }
+    }
+
+    fn collect_coins<'a>(&'a self, coins: &mut Vec<&'a str>) {
+        match self {
+            Value::Number(_) => {}
+            Value::Market { coin, .. } => {
+                if !coins.contains(&coin.as_str()) {
+                    coins.push(coin);
+                }
+            }
+            Value::Binary { lhs, rhs, .. } => {
+                lhs.collect_coins(coins);
+                rhs.collect_coins(coins);
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Number(number) => write!(f, "{number}"),
+            Value::Market { metric: Metric::Price, coin } => write!(f, "{coin}"),
+            Value::Market { metric, coin } => write!(f, "{}({coin})", metric.as_str()),
+            Value::Binary { lhs, op, rhs } => {
+                // The right side also needs parentheses on a tie, as `-` and
+                // `/` are left-associative.
+                if lhs.precedence() < op.precedence() {
+                    write!(f, "({lhs})")?;
+                } else {
+                    write!(f, "{lhs}")?;
+                }
+                write!(f, " {} ", op.as_str())?;
+                if rhs.precedence() <= op.precedence() {
+                    write!(f, "({rhs})")
+                } else {
+                    write!(f, "{rhs}")
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Greater,
+    GreaterOrEqual,
+    Less,
+    LessOrEqual,
+}
+
+impl Comparison {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Comparison::Greater => ">",
+            Comparison::GreaterOrEqual => ">=",
+            Comparison::Less => "<",
+            Comparison::LessOrEqual => "<=",
+        }
+    }
+
+    fn holds(&self, lhs: f64, rhs: f64) -> bool {
+        match self {
+            Comparison::Greater => lhs > rhs,
+            Comparison::GreaterOrEqual => lhs >= rhs,
+            Comparison::Less => lhs < rhs,
+            Comparison::LessOrEqual => lhs <= rhs,
+        }
+    }
+}
+
+/// A condition over one or more markets, e.g.
+/// `HYPE-PERP > 40 AND funding(HYPE-PERP) < 0`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expression {
+    Compare { lhs: Value, comparison: Comparison, rhs: Value },
+    And(Box<Expression>, Box<Expression>),
+    Or(Box<Expression>, Box<Expression>),
+}
+
+impl Expression {
+    /// Whether the expression holds, reading market values through `value`.
+    /// Comparisons with a missing input are unknown; `AND` and `OR` still
+    /// decide when the other side settles them.
+    pub fn evaluate(&self, value: &impl Fn(Metric, &str) -> Option<f64>) -> Option<bool> {
+        match self {
+            Expression::Compare { lhs, comparison, rhs } => {
+                Some(comparison.holds(lhs.evaluate(value)?, rhs.evaluate(value)?))
+            }
+            Expression::And(lhs, rhs) => match (lhs.evaluate(value), rhs.evaluate(value)) {
+                (Some(false), _) | (_, Some(false)) => Some(false),
+                (Some(true), Some(true)) => Some(true),
+                _ => None,
+            },
+            Expression::Or(lhs, rhs) => match (lhs.evaluate(value), rhs.evaluate(value)) {
+                (Some(true), _) | (_, Some(true)) => Some(true),
+                (Some(false), Some(false)) => Some(false),
+                _ => None,
+            },
+        }
+    }
+
+    /// Whether the expression became true between the contexts before and
+    /// after `tick`. `market_token` maps a coin to the token of its feed.
+    pub fn is_triggered(&self, tick: &ExpressionTick, market_token: impl Fn(&str) -> Option<String>) -> bool {
+        let current = |metric: Metric, coin: &str| tick.after(&market_token(coin)?)?.value(metric);
+        let previous = |metric: Metric, coin: &str| tick.before(&market_token(coin)?)?.value(metric);
+        self.evaluate(&previous) == Some(false) && self.evaluate(&current) == Some(true)
+    }
+
+    /// The coins the expression reads, in order of first appearance.
+    pub fn coins(&self) -> Vec<&str> {
+        let mut coins = Vec::new();
+        self.collect_coins(&mut coins);
+        coins
+    }
+
+    fn collect_coins<'a>(&'a self, coins: &mut Vec<&'a str>) {
+        match self {
+            Expression::Compare { lhs, rhs, .. } => {
+                lhs.collect_coins(coins);
+                rhs.collect_coins(coins);
+            }
+            Expression::And(lhs, rhs) | Expression::Or(lhs, rhs) => {
+                lhs.collect_coins(coins);
+                rhs.collect_coins(coins);
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for Expression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Expression::Compare { lhs, comparison, rhs } => write!(f, "{lhs} {} {rhs}", comparison.as_str()),
+            Expression::And(lhs, rhs) => {
+                let operand = |f: &mut std::fmt::Formatter<'_>, side: &Expression| match side {
+                    Expression::Or(..) => write!(f, "({side})"),
+                    _ => write!(f, "{side}"),
+                };
+                operand(f, lhs)?;
+                write!(f, " AND ")?;
+                operand(f, rhs)
+            }
+            Expression::Or(lhs, rhs) => write!(f, "{lhs} OR {rhs}"),
+        }
+    }
+}
+
+impl std::str::FromStr for Expression {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let mut parser = Parser { tokens: tokenize(s)?, position: 0 };
+        let expression = parser.parse_or()?;
+        if let Some(token) = parser.peek() {
+            anyhow::bail!("Unexpected {token} in {s}");
+        }
+        let markets = expression.coins().len();
+        if markets == 0 {
+            anyhow::bail!("An expression needs at least one coin: {s}");
+        }
+        if markets > MAX_EXPRESSION_MARKETS {
+            anyhow::bail!("An expression can read at most {MAX_EXPRESSION_MARKETS} markets, found {markets}");
+        }
+        Ok(expression)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Name(String),
+    Arithmetic(ArithmeticOp),
+    Comparison(Comparison),
+    And,
+    Or,
+    Open,
+    Close,
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Number(number) => write!(f, "{number}"),
+            Token::Name(name) => write!(f, "{name}"),
+            Token::Arithmetic(op) => write!(f, "{}", op.as_str()),
+            Token::Comparison(comparison) => write!(f, "{}", comparison.as_str()),
+            Token::And => write!(f, "AND"),
+            Token::Or => write!(f, "OR"),
+            Token::Open => write!(f, "("),
+            Token::Close => write!(f, ")"),
+        }
+    }
+}
+
+/// Splits an expression into tokens. Names are coins, metric functions or
+/// `AND`/`OR`; a `-PERP` suffix belongs to the coin rather than being a
+/// subtraction.
+fn tokenize(s: &str) -> anyhow::Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = s.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            '+' => Token::Arithmetic(ArithmeticOp::Add),
+            '-' => Token::Arithmetic(ArithmeticOp::Subtract),
+            '*' => Token::Arithmetic(ArithmeticOp::Multiply),
+            '/' => Token::Arithmetic(ArithmeticOp::Divide),
+            '>' | '<' => {
+                let or_equal = chars.next_if(|(_, c)| *c == '=').is_some();
+                Token::Comparison(match (c, or_equal) {
+                    ('>', false) => Comparison::Greater,
+                    ('>', true) => Comparison::GreaterOrEqual,
+                    ('<', false) => Comparison::Less,
+                    _ => Comparison::LessOrEqual,
+                })
+            }
+            c if c.is_ascii_digit() || c == '.' => {
+                let mut end = start + c.len_utf8();
+                while let Some((index, c)) = chars.next_if(|(_, c)| c.is_ascii_digit() || *c == '.') {
+                    end = index + c.len_utf8();
+                }
+                let number = &s[start..end];
+                Token::Number(number.parse().map_err(|_| anyhow::anyhow!("Invalid number: {number}"))?)
+            }
+            c if c.is_ascii_alphabetic() => {
+                let mut end = start + c.len_utf8();
+                while let Some((index, c)) = chars.next_if(|(_, c)| c.is_ascii_alphanumeric() || *c == '_') {
+                    end = index + c.len_utf8();
+                }
+                if s[end..].starts_with("-PERP") {
+                    for _ in 0.."-PERP".len() {
+                        chars.next();
+                    }
+                    end += "-PERP".len();
+                }
+                match &s[start..end] {
+                    name if name.eq_ignore_ascii_case("and") => Token::And,
+                    name if name.eq_ignore_ascii_case("or") => Token::Or,
+                    name => Token::Name(name.to_string()),
+                }
+            }
+            '&' if chars.next_if(|(_, c)| *c == '&').is_some() => Token::And,
+            '|' if chars.next_if(|(_, c)| *c == '|').is_some() => Token::Or,
+            c => anyhow::bail!("Unexpected character in expression: {c}"),
+        };
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+/// A recursive descent parser over the grammar
+///
+/// ```text
+/// or         := and (OR and)*
+/// and        := comparison (AND comparison)*
+/// comparison := "(" or ")" | sum (">" | ">=" | "<" | "<=") sum
+/// sum        := product (("+" | "-") product)*
+/// product    := atom (("*" | "/") atom)*
+/// atom       := number | "-" number | coin | metric "(" coin ")" | "(" sum ")"
+/// ```
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> anyhow::Result<Token> {
+        let token = self
+            .tokens
+            .get(self.position)
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("Expression ends too early"))?;
+        self.position += 1;
+        Ok(token)
+    }
+
+    fn expect(&mut self, expected: Token) -> anyhow::Result<()> {
+        match self.next()? {
+            token if token == expected => Ok(()),
+            token => Err(anyhow::anyhow!("Expected {expected} but found {token}")),
+        }
+    }
+
+    fn parse_or(&mut self) -> anyhow::Result<Expression> {
+        let mut expression = self.parse_and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.position += 1;
+            expression = Expression::Or(Box::new(expression), Box::new(self.parse_and()?));
+        }
+        Ok(expression)
+    }
+
+    fn parse_and(&mut self) -> anyhow::Result<Expression> {
+        let mut expression = self.parse_comparison()?;
+        while self.peek() == Some(&Token::And) {
+            self.position += 1;
+            expression = Expression::And(Box::new(expression), Box::new(self.parse_comparison()?));
+        }
+        Ok(expression)
+    }
+
+    /// A parenthesis here opens either a condition or a value, so a grouped
+    /// condition is tried first and the parser backtracks if it isn't one.
+    fn parse_comparison(&mut self) -> anyhow::Result<Expression> {
+        if self.peek() == Some(&Token::Open) {
+            let start = self.position;
+            self.position += 1;
+            if let Ok(expression) = self.parse_or()
+                && self.expect(Token::Close).is_ok()
+                && !matches!(self.peek(), Some(Token::Comparison(_) | Token::Arithmetic(_)))
+            {
+                return Ok(expression);
+            }
+            self.position = start;
+        }
+        let lhs = self.parse_sum()?;
+        let comparison = match self.next()? {
+            Token::Comparison(comparison) => comparison,
+            token => anyhow::bail!("Expected a comparison (>, >=, <, <=) after {lhs} but found {token}"),
+        };
+        let rhs = self.parse_sum()?;
+        Ok(Expression::Compare { lhs, comparison, rhs })
+    }
+
+    fn parse_sum(&mut self) -> anyhow::Result<Value> {
+        let mut value = self.parse_product()?;
+        while let Some(Token::Arithmetic(op @ (ArithmeticOp::Add | ArithmeticOp::Subtract))) = self.peek() {
+            let op = *op;
+            self.position += 1;
+            value = Value::Binary { lhs: Box::new(value), op, rhs: Box::new(self.parse_product()?) };
+        }
+        Ok(value)
+    }
+
+    fn parse_product(&mut self) -> anyhow::Result<Value> {
+        let mut value = self.parse_atom()?;
+        while let Some(Token::Arithmetic(op @ (ArithmeticOp::Multiply | ArithmeticOp::Divide))) = self.peek() {
+            let op = *op;
+            self.position += 1;
+            value = Value::Binary { lhs: Box::new(value), op, rhs: Box::new(self.parse_atom()?) };
+        }
+        Ok(value)
+    }
+
+    fn parse_atom(&mut self) -> anyhow::Result<Value> {
+        match self.next()? {
+            Token::Number(number) => Ok(Value::Number(number)),
+            Token::Arithmetic(ArithmeticOp::Subtract) => match self.next()? {
+                Token::Number(number) => Ok(Value::Number(-number)),
+                token => Err(anyhow::anyhow!("Only numbers can be negated, found -{token}")),
+            },
+            Token::Open => {
+                let value = self.parse_sum()?;
+                self.expect(Token::Close)?;
+                Ok(value)
+            }
+            Token::Name(name) if self.peek() == Some(&Token::Open) => {
+                let metric = Metric::parse(&name)
+                    .ok_or_else(|| anyhow::anyhow!("Unknown function: {name} (use price, funding, oi or premium)"))?;
+                self.position += 1;
+                let Token::Name(coin) = self.next()? else {
+                    anyhow::bail!("{name}(...) takes a coin, e.g. {name}(HYPE)");
+                };
+                self.expect(Token::Close)?;
+                // Funding, open interest and premium only exist on perps.
+                let coin = match metric {
+                    Metric::Price => coin,
+                    _ if coin.ends_with("-PERP") => coin,
+                    _ => format!("{coin}-PERP"),
+                };
+                Ok(Value::Market { metric, coin })
+            }
+            Token::Name(coin) => Ok(Value::Market { metric: Metric::Price, coin }),
+            token => Err(anyhow::anyhow!("Expected a number or a coin but found {token}")),
+        }
+    }
+}
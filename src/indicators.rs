@@ -0,0 +1,386 @@
+use crate::db::AlertDirection;
+use chrono::{DateTime, Duration, Utc};
+use hyperliquid_rust_sdk::{CandleData, InfoClient};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+/// Candles kept per series; enough for a 200-period average plus warm-up.
+const MAX_CANDLES: usize = 500;
+/// Candles fetched from the snapshot when a series is first needed.
+const WARM_UP_CANDLES: u64 = 300;
+/// How long a live candle waits before retrying a failed warm-up.
+const WARM_UP_RETRY_SECS: i64 = 60;
+/// Standard deviations between the Bollinger middle band and its bands.
+const BOLLINGER_STD_DEVS: f64 = 2.0;
+
+/// A candle interval supported by Hyperliquid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Interval {
+    OneMinute,
+    ThreeMinutes,
+    FiveMinutes,
+    FifteenMinutes,
+    ThirtyMinutes,
+    OneHour,
+    TwoHours,
+    FourHours,
+    EightHours,
+    TwelveHours,
+    OneDay,
+    ThreeDays,
+    OneWeek,
+    OneMonth,
+}
+
+impl Interval {
+    pub const ALL: [Interval; 14] = [
+        Interval::OneMinute,
+        Interval::ThreeMinutes,
+        Interval::FiveMinutes,
+        Interval::FifteenMinutes,
+        Interval::ThirtyMinutes,
+        Interval::OneHour,
+        Interval::TwoHours,
+        Interval::FourHours,
+        Interval::EightHours,
+        Interval::TwelveHours,
+        Interval::OneDay,
+        Interval::ThreeDays,
+        Interval::OneWeek,
+        Interval::OneMonth,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Interval::OneMinute => "1m",
+            Interval::ThreeMinutes => "3m",
+            Interval::FiveMinutes => "5m",
+            Interval::FifteenMinutes => "15m",
+            Interval::ThirtyMinutes => "30m",
+            Interval::OneHour => "1h",
+            Interval::TwoHours => "2h",
+            Interval::FourHours => "4h",
+            Interval::EightHours => "8h",
+            Interval::TwelveHours => "12h",
+            Interval::OneDay => "1d",
+            Interval::ThreeDays => "3d",
+            Interval::OneWeek => "1w",
+            Interval::OneMonth => "1M",
+        }
+    }
+
+    /// Length of one candle in milliseconds. Months count as 30 days.
+    pub fn millis(&self) -> u64 {
+        const MINUTE: u64 = 60 * 1000;
+        match self {
+            Interval::OneMinute => MINUTE,
+            Interval::ThreeMinutes => 3 * MINUTE,
+            Interval::FiveMinutes => 5 * MINUTE,
+            Interval::FifteenMinutes => 15 * MINUTE,
+            Interval::ThirtyMinutes => 30 * MINUTE,
+            Interval::OneHour => 60 * MINUTE,
+            Interval::TwoHours => 2 * 60 * MINUTE,
+            Interval::FourHours => 4 * 60 * MINUTE,
+            Interval::EightHours => 8 * 60 * MINUTE,
+            Interval::TwelveHours => 12 * 60 * MINUTE,
+            Interval::OneDay => 24 * 60 * MINUTE,
+            Interval::ThreeDays => 3 * 24 * 60 * MINUTE,
+            Interval::OneWeek => 7 * 24 * 60 * MINUTE,
+            Interval::OneMonth => 30 * 24 * 60 * MINUTE,
+        }
+    }
+}
+
+impl std::str::FromStr for Interval {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        Interval::ALL
+            .into_iter()
+            .find(|interval| interval.as_str() == s)
+            .ok_or_else(|| anyhow::anyhow!("Unknown interval: {s}"))
+    }
+}
+
+/// One side of an indicator condition.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operand {
+    Close,
+    Number(f64),
+    Sma(usize),
+    Ema(usize),
+    Rsi(usize),
+    BollingerUpper(usize),
+    BollingerLower(usize),
+}
+
+impl Operand {
+    /// Parses an operand such as `30`, `close`, `rsi14(1h)` or `bbu20(4h)`,
+    /// returning the interval it names, if any.
+    fn parse(operand: &str) -> anyhow::Result<(Self, Option<Interval>)> {
+        if let Ok(number) = operand.parse::<f64>() {
+            return Ok((Operand::Number(number), None));
+        }
+        if operand == "close" || operand == "price" {
+            return Ok((Operand::Close, None));
+        }
+        let invalid = || anyhow::anyhow!("Invalid indicator: {operand} (use e.g. rsi14(1h), sma50(4h), ema20(1d), bbu20(1h))");
+        let (name, rest) = operand.split_at(operand.find(|c: char| c.is_ascii_digit()).ok_or_else(invalid)?);
+        let (period, interval) = rest.strip_suffix(')').and_then(|rest| rest.split_once('(')).ok_or_else(invalid)?;
+        let period = period.parse::<usize>().ok().filter(|period| (2..=MAX_CANDLES / 2).contains(period)).ok_or_else(invalid)?;
+        let operand = match name {
+            "sma" | "ma" => Operand::Sma(period),
+            "ema" => Operand::Ema(period),
+            "rsi" => Operand::Rsi(period),
+            "bbu" => Operand::BollingerUpper(period),
+            "bbl" => Operand::BollingerLower(period),
+            _ => return Err(invalid()),
+        };
+        Ok((operand, Some(interval.parse()?)))
+    }
+
+    /// The operand's value at the last of `closes`, or `None` while there
+    /// are too few candles.
+    pub fn value(&self, closes: &[f64]) -> Option<f64> {
+        match *self {
+            Operand::Close => closes.last().copied(),
+            Operand::Number(number) => Some(number),
+            Operand::Sma(period) => sma(closes, period),
+            Operand::Ema(period) => ema(closes, period),
+            Operand::Rsi(period) => rsi(closes, period),
+            Operand::BollingerUpper(period) => bollinger(closes, period).map(|(_, upper)| upper),
+            Operand::BollingerLower(period) => bollinger(closes, period).map(|(lower, _)| lower),
+        }
+    }
+
+    fn fmt_with(&self, f: &mut std::fmt::Formatter<'_>, interval: Interval) -> std::fmt::Result {
+        let interval = interval.as_str();
+        match self {
+            Operand::Close => write!(f, "close"),
+            Operand::Number(number) => write!(f, "{number}"),
+            Operand::Sma(period) => write!(f, "sma{period}({interval})"),
+            Operand::Ema(period) => write!(f, "ema{period}({interval})"),
+            Operand::Rsi(period) => write!(f, "rsi{period}({interval})"),
+            Operand::BollingerUpper(period) => write!(f, "bbu{period}({interval})"),
+            Operand::BollingerLower(period) => write!(f, "bbl{period}({interval})"),
+        }
+    }
+}
+
+fn sma(closes: &[f64], period: usize) -> Option<f64> {
+    let window = closes.get(closes.len().checked_sub(period)?..)?;
+    Some(window.iter().sum::<f64>() / period as f64)
+}
+
+/// EMA seeded with the SMA of the first `period` closes.
+fn ema(closes: &[f64], period: usize) -> Option<f64> {
+    let seed = sma(closes.get(..period)?, period)?;
+    let k = 2.0 / (period as f64 + 1.0);
+    Some(closes[period..].iter().fold(seed, |ema, close| close * k + ema * (1.0 - k)))
+}
+
+/// RSI with Wilder's smoothing.
+fn rsi(closes: &[f64], period: usize) -> Option<f64> {
+    if closes.len() <= period {
+        return None;
+    }
+    let changes: Vec<f64> = closes.windows(2).map(|pair| pair[1] - pair[0]).collect();
+    let mut avg_gain = changes[..period].iter().map(|change| change.max(0.0)).sum::<f64>() / period as f64;
+    let mut avg_loss = changes[..period].iter().map(|change| (-change).max(0.0)).sum::<f64>() / period as f64;
+    for change in &changes[period..] {
+        avg_gain = (avg_gain * (period as f64 - 1.0) + change.max(0.0)) / period as f64;
+        avg_loss = (avg_loss * (period as f64 - 1.0) + (-change).max(0.0)) / period as f64;
+    }
+    if avg_loss == 0.0 {
+        return Some(100.0);
+    }
+    Some(100.0 - 100.0 / (1.0 + avg_gain / avg_loss))
+}
+
+/// Lower and upper Bollinger bands.
+fn bollinger(closes: &[f64], period: usize) -> Option<(f64, f64)> {
+    let mean = sma(closes, period)?;
+    let window = &closes[closes.len() - period..];
+    let std_dev = (window.iter().map(|close| (close - mean).powi(2)).sum::<f64>() / period as f64).sqrt();
+    Some((mean - BOLLINGER_STD_DEVS * std_dev, mean + BOLLINGER_STD_DEVS * std_dev))
+}
+
+/// A comparison between two operands on one candle interval, e.g.
+/// `rsi14(1h) < 30` or `ema12(4h) > ema26(4h)`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndicatorCondition {
+    pub lhs: Operand,
+    pub direction: AlertDirection,
+    pub rhs: Operand,
+    pub interval: Interval,
+}
+
+impl IndicatorCondition {
+    /// Whether the condition became true between the series `previous` and
+    /// `current`, i.e. the left side crossed the right side in `direction`.
+    pub fn is_triggered(&self, previous: &[f64], current: &[f64]) -> bool {
+        let difference = |closes: &[f64]| Some(self.lhs.value(closes)? - self.rhs.value(closes)?);
+        let (Some(previous), Some(current)) = (difference(previous), difference(current)) else {
+            return false;
+        };
+        self.direction.is_triggered(previous, current, 0.0)
+    }
+}
+
+impl std::str::FromStr for IndicatorCondition {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let condition: String = s.split_whitespace().collect();
+        let (index, direction) = condition
+            .char_indices()
+            .find_map(|(index, c)| match c {
+                '>' => Some((index, AlertDirection::Above)),
+                '<' => Some((index, AlertDirection::Below)),
+                _ => None,
+            })
+            .ok_or_else(|| anyhow::anyhow!("Use > or < to compare indicators: {s}"))?;
+        let (lhs, lhs_interval) = Operand::parse(&condition[..index])?;
+        let (rhs, rhs_interval) = Operand::parse(&condition[index + 1..])?;
+        let interval = match (lhs_interval, rhs_interval) {
+            (Some(lhs), Some(rhs)) if lhs != rhs => anyhow::bail!("Both indicators need the same interval: {s}"),
+            (Some(interval), _) | (None, Some(interval)) => interval,
+            (None, None) => anyhow::bail!("Compare at least one indicator: {s}"),
+        };
+        Ok(Self { lhs, direction, rhs, interval })
+    }
+}
+
+impl std::fmt::Display for IndicatorCondition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.lhs.fmt_with(f, self.interval)?;
+        write!(f, " {} ", if self.direction == AlertDirection::Below { "<" } else { ">" })?;
+        self.rhs.fmt_with(f, self.interval)
+    }
+}
+
+/// Close prices of one (token, interval), oldest first, keyed by candle
+/// open time. The last candle is usually still open and updates in place.
+#[derive(Debug, Clone, Default)]
+pub struct CandleSeries {
+    candles: VecDeque<(u64, f64)>,
+}
+
+impl CandleSeries {
+    fn update(&mut self, time_open: u64, close: f64) {
+        match self.candles.back_mut() {
+            Some((last_open, last_close)) if *last_open == time_open => *last_close = close,
+            Some((last_open, _)) if *last_open > time_open => {}
+            _ => self.candles.push_back((time_open, close)),
+        }
+        while self.candles.len() > MAX_CANDLES {
+            self.candles.pop_front();
+        }
+    }
+
+    /// Merges `candles`, oldest first, under the candles already in the
+    /// series, which are newer.
+    fn backfill(&mut self, candles: impl IntoIterator<Item = (u64, f64)>) {
+        let live = std::mem::take(&mut self.candles);
+        for (time_open, close) in candles.into_iter().chain(live) {
+            self.update(time_open, close);
+        }
+    }
+
+    pub fn closes(&self) -> Vec<f64> {
+        self.candles.iter().map(|(_, close)| *close).collect()
+    }
+}
+
+/// The closes of a series right before and right after a candle update.
+pub struct CandleUpdate {
+    pub token: String,
+    pub interval: Interval,
+    pub previous: Vec<f64>,
+    pub current: Vec<f64>,
+}
+
+/// Whether a series has been loaded from `candles_snapshot`.
+#[derive(Debug, Clone, Copy)]
+enum WarmUp {
+    Loaded,
+    Failed(DateTime<Utc>),
+}
+
+/// Keeps rolling candle series per (token, interval), warmed up from
+/// `candles_snapshot` and kept current by `Candle` subscription updates.
+#[derive(Clone)]
+pub struct IndicatorEngine {
+    info_client: Arc<Mutex<InfoClient>>,
+    series: Arc<Mutex<HashMap<(String, Interval), CandleSeries>>>,
+    // Tracked apart from `series`, which live candles create, so a series
+    // whose warm-up failed is backfilled later instead of running cold.
+    warm_ups: Arc<Mutex<HashMap<(String, Interval), WarmUp>>>,
+}
+
+impl IndicatorEngine {
+    pub fn new(info_client: Arc<Mutex<InfoClient>>) -> Self {
+        Self {
+            info_client,
+            series: Arc::new(Mutex::new(HashMap::new())),
+            warm_ups: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Loads recent candles for a series that has not been loaded yet,
+    /// merging them under any live candles it already has.
+    pub async fn warm_up(&self, token: &str, interval: Interval) -> anyhow::Result<()> {
+        let key = (token.to_string(), interval);
+        if matches!(self.warm_ups.lock().await.get(&key), Some(WarmUp::Loaded)) {
+            return Ok(());
+        }
+        let end_time = Utc::now().timestamp_millis() as u64;
+        let start_time = end_time.saturating_sub(WARM_UP_CANDLES * interval.millis());
+        let candles = self
+            .info_client
+            .lock()
+            .await
+            .candles_snapshot(token.to_string(), interval.as_str().to_string(), start_time, end_time)
+            .await;
+        let mut candles = match candles {
+            Ok(candles) => candles,
+            Err(e) => {
+                self.warm_ups.lock().await.insert(key, WarmUp::Failed(Utc::now()));
+                return Err(e.into());
+            }
+        };
+
+        candles.sort_by_key(|candle| candle.time_open);
+        let closes = candles
+            .iter()
+            .filter_map(|candle| Some((candle.time_open, candle.close.parse::<f64>().ok()?)));
+        self.series.lock().await.entry(key.clone()).or_default().backfill(closes);
+        self.warm_ups.lock().await.insert(key, WarmUp::Loaded);
+        Ok(())
+    }
+
+    /// Applies a live candle to its series and returns the closes before and
+    /// after the update. A series whose warm-up failed is retried first.
+    pub async fn apply_candle(&self, candle: &CandleData) -> Option<CandleUpdate> {
+        let interval = candle.interval.parse::<Interval>().ok()?;
+        let close = candle.close.parse::<f64>().ok()?;
+        let retry = match self.warm_ups.lock().await.get(&(candle.coin.clone(), interval)) {
+            Some(WarmUp::Failed(at)) => Utc::now() - *at >= Duration::seconds(WARM_UP_RETRY_SECS),
+            Some(WarmUp::Loaded) | None => false,
+        };
+        if retry && let Err(e) = self.warm_up(&candle.coin, interval).await {
+            log::error!("Failed to load {} candles for {}: {e}", interval.as_str(), candle.coin);
+        }
+        let mut series = self.series.lock().await;
+        let series = series.entry((candle.coin.clone(), interval)).or_default();
+        let previous = series.closes();
+        series.update(candle.time_open, close);
+        Some(CandleUpdate {
+            token: candle.coin.clone(),
+            interval,
+            previous,
+            current: series.closes(),
+        })
+    }
+}
@@ -1,4 +1,16 @@
 pub mod db;
+pub mod assets;
 pub mod notification;
 pub mod alerts;
 pub mod cron;
+pub mod monitor;
+pub mod subscriptions;
+pub mod positions;
+pub mod orders;
+pub mod events;
+pub mod book;
+pub mod whales;
+pub mod indicators;
+pub mod expressions;
+pub mod listings;
+pub mod reports;
@@ -0,0 +1,138 @@
+use crate::db::{Database, ListingKind, MarketListing};
+use hyperliquid_rust_sdk::{InfoClient, Meta, SpotMeta};
+use rusqlite::Result;
+use std::collections::HashSet;
+use std::sync::Arc;
+use teloxide::types::ChatId;
+use tokio::sync::Mutex;
+
+/// Perps, spot pairs and spot tokens that appeared on or disappeared from
+/// Hyperliquid since the last check, for one chat.
+#[derive(Debug, Clone)]
+pub struct ListingNotification {
+    pub chat_id: i64,
+    pub listed: Vec<MarketListing>,
+    pub delisted: Vec<MarketListing>,
+}
+
+impl std::fmt::Display for ListingNotification {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut sections = Vec::new();
+        if !self.listed.is_empty() {
+            sections.push(("🆕 Listed on Hyperliquid:", &self.listed));
+        }
+        if !self.delisted.is_empty() {
+            sections.push(("🚫 Delisted from Hyperliquid:", &self.delisted));
+        }
+        for (i, (title, listings)) in sections.into_iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{title}")?;
+            for listing in listings {
+                write!(f, "\n- {} ({})", listing.label, listing.kind)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Every live perp, spot pair and spot token in `meta` and `spot_meta`.
+/// Delisted perps stay in `meta` with a flag, so they are left out here.
+pub fn market_listings(meta: &Meta, spot_meta: &SpotMeta) -> Vec<MarketListing> {
+    let perps = meta.universe.iter().filter(|asset| !asset.is_delisted).map(|asset| MarketListing {
+        kind: ListingKind::Perp,
+        name: asset.name.clone(),
+        label: format!("{}-PERP", asset.name),
+    });
+    let token_name = |index: usize| spot_meta.tokens.iter().find(|token| token.index == index).map(|token| token.name.as_str());
+    let pairs = spot_meta.universe.iter().map(|pair| {
+        let label = match (token_name(pair.tokens[0]), token_name(pair.tokens[1])) {
+            (Some(base), Some(quote)) if pair.name != format!("{base}/{quote}") => format!("{base}/{quote} ({})", pair.name),
+            _ => pair.name.clone(),
+        };
+        MarketListing {
+            kind: ListingKind::SpotPair,
+            name: pair.name.clone(),
+            label,
+        }
+    });
+    let tokens = spot_meta.tokens.iter().map(|token| MarketListing {
+        kind: ListingKind::SpotToken,
+        name: token.name.clone(),
+        label: token.name.clone(),
+    });
+    perps.chain(pairs).chain(tokens).collect()
+}
+
+/// Tells opted-in chats about new and removed markets by diffing the
+/// exchange metadata against a snapshot kept in SQLite.
+#[derive(Clone)]
+pub struct ListingService {
+    db: Database,
+    info_client: Arc<Mutex<InfoClient>>,
+}
+
+impl ListingService {
+    pub fn new(db: Database, info_client: Arc<Mutex<InfoClient>>) -> Self {
+        Self { db, info_client }
+    }
+
+    /// Opts `chat_id` in to listing notifications. Returns `false` if it
+    /// already was.
+    pub async fn subscribe(&self, chat_id: ChatId) -> Result<bool> {
+        self.db.set_chat_listings(chat_id, true).await
+    }
+
+    /// Opts `chat_id` out of listing notifications. Returns `false` if it
+    /// was not subscribed.
+    pub async fn unsubscribe(&self, chat_id: ChatId) -> Result<bool> {
+        self.db.set_chat_listings(chat_id, false).await
+    }
+
+    /// Fetches the current markets and diffs them against the snapshot.
+    pub async fn check(&self) -> anyhow::Result<Vec<ListingNotification>> {
+        let (meta, spot_meta) = {
+            let info_client = self.info_client.lock().await;
+            (info_client.meta().await?, info_client.spot_meta().await?)
+        };
+        Ok(self.sync(market_listings(&meta, &spot_meta)).await?)
+    }
+
+    /// Stores `current` as the snapshot and returns a notification for every
+    /// subscribed chat if anything was listed or delisted since the last one.
+    /// The first snapshot only seeds the table, so existing markets are not
+    /// announced.
+    pub async fn sync(&self, current: Vec<MarketListing>) -> Result<Vec<ListingNotification>> {
+        let previous = self.db.get_market_listings().await?;
+        let known: HashSet<(ListingKind, &str)> = previous.iter().map(|listing| (listing.kind, listing.name.as_str())).collect();
+        let live: HashSet<(ListingKind, &str)> = current.iter().map(|listing| (listing.kind, listing.name.as_str())).collect();
+        let listed: Vec<MarketListing> = current
+            .iter()
+            .filter(|listing| !known.contains(&(listing.kind, listing.name.as_str())))
+            .cloned()
+            .collect();
+        let delisted: Vec<MarketListing> = previous
+            .iter()
+            .filter(|listing| !live.contains(&(listing.kind, listing.name.as_str())))
+            .cloned()
+            .collect();
+        if listed.is_empty() && delisted.is_empty() {
+            return Ok(Vec::new());
+        }
+        self.db.update_market_listings(&listed, &delisted).await?;
+        if previous.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let chats = self.db.get_listing_chats().await?;
+        Ok(chats
+            .into_iter()
+            .map(|chat_id| ListingNotification {
+                chat_id,
+                listed: listed.clone(),
+                delisted: delisted.clone(),
+            })
+            .collect())
+    }
+}
@@ -1,16 +1,23 @@
-use hyperliquid_rust_sdk::{BaseUrl, InfoClient, Subscription};
+use hyperliquid_rust_sdk::{BaseUrl, InfoClient};
 use log::info;
 use tokio::{sync::mpsc::unbounded_channel};
 use teloxide::prelude::*;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 use backend::{
+    assets::AssetRegistry,
     db::Database,
-    notification::{NotificationService, Command},
+    notification::{NotificationService, Command, alert_message},
     alerts::AlertService,
     cron::CronService,
+    events::AccountEventService,
+    listings::ListingService,
+    monitor::PriceMonitor,
+    orders::OrderService,
+    positions::PositionService,
+    subscriptions::SubscriptionManager,
+    whales::WhaleService,
 };
-use cron_parser::parse;
 
 
 #[tokio::main]
@@ -22,12 +29,35 @@ async fn main() {
 
     let db = Database::new("alerts.db").unwrap();
     db.initialize().await.unwrap();
-    let tokens = db.get_all_unique_tokens().await.unwrap();
     
     let info_client = Arc::new(Mutex::new(InfoClient::new(None, Some(BaseUrl::Mainnet)).await.unwrap()));
 
-    let alert_service = AlertService::new(db.clone(), info_client.clone());
-    let cron_service = CronService::new(db.clone(), info_client.clone());
+    let (sender, receiver) = unbounded_channel();
+    let subscriptions = SubscriptionManager::new(InfoClient::new(None, Some(BaseUrl::Mainnet)).await.unwrap(), sender);
+
+    let assets = AssetRegistry::new(info_client.clone());
+    if let Err(e) = assets.refresh().await {
+        log::error!("Failed to load asset registry: {e}");
+    }
+
+    let alert_service = AlertService::new(db.clone(), info_client.clone(), subscriptions.clone(), assets.clone());
+    let cron_service = CronService::new(db.clone(), info_client.clone(), assets.clone());
+    let position_service = PositionService::new(db.clone(), info_client.clone());
+    let (order_sender, mut order_receiver) = unbounded_channel();
+    let order_service = OrderService::new(db.clone(), info_client.clone(), order_sender);
+    let (account_event_sender, mut account_event_receiver) = unbounded_channel();
+    let account_event_service = AccountEventService::new(
+        db.clone(),
+        InfoClient::new(None, Some(BaseUrl::Mainnet)).await.unwrap(),
+        account_event_sender,
+    );
+    let (whale_sender, mut whale_receiver) = unbounded_channel();
+    let whale_service = WhaleService::new(
+        db.clone(),
+        assets.clone(),
+        SubscriptionManager::new(InfoClient::new(None, Some(BaseUrl::Mainnet)).await.unwrap(), whale_sender),
+    );
+    let listing_service = ListingService::new(db.clone(), info_client.clone());
     
     let alerts = alert_service.get_all_alerts().await.unwrap();
     for alert in alerts {
@@ -50,28 +80,42 @@ async fn main() {
     
     
 
-    let (sender, mut receiver) = unbounded_channel();
-    let mut subscription_ids = Vec::new();
-    for token in tokens {
-        let subscription_id = info_client
-            .lock().await
-            .subscribe(
-                Subscription::ActiveAssetCtx {
-                    coin: token,
-                },
-                sender.clone(),
-            )
-            .await
-            .unwrap();
-        subscription_ids.push(subscription_id);
+    if let Err(e) = alert_service.subscribe_existing_alerts().await {
+        log::error!("Failed to subscribe to existing alerts: {e}");
     }
+    whale_service.subscribe_existing_alerts().await.unwrap();
 
 
-    let alert_service_for_price_updates = alert_service.clone();
+    let (alert_sender, mut alert_receiver) = unbounded_channel();
+    let price_monitor = PriceMonitor::new(alert_service.clone());
+    if let Err(e) = price_monitor.seed_perp_metrics().await {
+        log::error!("Failed to seed perp metrics: {e}");
+    }
     let alert_service_for_cooldowns = alert_service.clone();
+    let alert_service_for_expiry = alert_service.clone();
+    let bot_for_expiry = bot.clone();
     let cron_service_for_worker = cron_service.clone();
     let bot_for_cron = bot.clone();
-    let notification_service = NotificationService::new(alert_service, cron_service.clone());
+    let position_service_for_worker = position_service.clone();
+    let bot_for_positions = bot.clone();
+    let order_service_for_worker = order_service.clone();
+    let bot_for_orders = bot.clone();
+    let bot_for_order_updates = bot.clone();
+    let account_event_service_for_worker = account_event_service.clone();
+    let account_event_service_for_listener = account_event_service.clone();
+    let bot_for_account_events = bot.clone();
+    let whale_service_for_listener = whale_service.clone();
+    let bot_for_whales = bot.clone();
+    let listing_service_for_worker = listing_service.clone();
+    let bot_for_listings = bot.clone();
+    let notification_service = NotificationService::new(
+        alert_service,
+        cron_service.clone(),
+        position_service,
+        account_event_service,
+        whale_service,
+        listing_service,
+    );
     tokio::select! {
         _ = Command::repl(bot.clone(), move |bot, msg, cmd| {
             let notification_service = notification_service.clone();
@@ -80,27 +124,32 @@ async fn main() {
             }
         }) => {
             info!("Telegram bot stopped, unsubscribing from price updates");
-            for subscription_id in subscription_ids {
-                info_client.lock().await.unsubscribe(subscription_id).await.unwrap();
-            }
+            subscriptions.unsubscribe_all().await.unwrap();
+        }
+        _ = price_monitor.run(receiver, alert_sender) => {
+            info!("Price monitoring stopped, unsubscribing from price updates");
+            subscriptions.unsubscribe_all().await.unwrap();
         }
         _ = async move {
-            while let Some(hyperliquid_rust_sdk::Message::ActiveSpotAssetCtx(order_updates)) = receiver.recv().await {
-                info!("Received order update data: {order_updates:?}");
-                let mark_px = order_updates.data.ctx.shared.mark_px.parse::<f64>().unwrap();
-                let alerts = alert_service_for_price_updates.get_triggered_alerts(mark_px).await.unwrap();
-                for alert in &alerts {
-                    println!("Alert triggered: {alert:?}");
-                    
-                    bot.send_message(teloxide::types::ChatId(alert.chat_id), format!("🔔 Price Alert: {} is at {}", alert.coin, alert.price)).await.unwrap();
+            while let Some(alert) = alert_receiver.recv().await {
+                if let Err(e) = bot.send_message(teloxide::types::ChatId(alert.chat_id), alert_message(&alert)).await {
+                    log::error!("Failed to send alert {} to chat {}: {e}", alert.id, alert.chat_id);
                 }
-                alert_service_for_price_updates.set_alert_cooldowns(&alerts).await.unwrap();
             }
         } => {
-            info!("Price monitoring stopped, unsubscribing from price updates");
-            for subscription_id in subscription_ids {
-                info_client.lock().await.unsubscribe(subscription_id).await.unwrap();
+            info!("Alert notifier stopped");
+        }
+        _ = async move {
+            let mut interval = tokio::time::interval(std::time::Duration::from_secs(600));
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                if let Err(e) = assets.refresh().await {
+                    log::error!("Failed to refresh asset registry: {e}");
+                }
             }
+        } => {
+            info!("Asset registry refresher stopped");
         }
         _ = async move {
             loop {
@@ -110,27 +159,204 @@ async fn main() {
         } => {
         }
         _ = async move {
-            let mut interval = tokio::time::interval(std::time::Duration::from_secs(60)); // Check every minute
+            let mut interval = tokio::time::interval(std::time::Duration::from_secs(60));
+            loop {
+                interval.tick().await;
+                let expired = match alert_service_for_expiry.expire_alerts().await {
+                    Ok(expired) => expired,
+                    Err(e) => {
+                        log::error!("Failed to expire alerts: {e}");
+                        continue;
+                    }
+                };
+                for alert in expired {
+                    let timezone = alert_service_for_expiry.timezone(teloxide::types::ChatId(alert.chat_id)).await.unwrap_or(chrono_tz::Tz::UTC);
+                    let message = format!("⌛ Alert expired: {}", alert.localized(timezone));
+                    if let Err(e) = bot_for_expiry.send_message(teloxide::types::ChatId(alert.chat_id), message).await {
+                        log::error!("Failed to send alert expiry to chat {}: {e}", alert.chat_id);
+                    }
+                }
+            }
+        } => {
+            info!("Alert expiry sweeper stopped");
+        }
+        _ = async move {
+            match cron_service_for_worker.abandon_interrupted_deliveries().await {
+                Ok(0) => {}
+                Ok(abandoned) => log::warn!("Abandoned {abandoned} cron deliveries interrupted by the last shutdown; they may not have been sent"),
+                Err(e) => log::error!("Failed to abandon interrupted cron deliveries: {e}"),
+            }
             loop {
                 info!("Checking for scheduled alerts");
-                let cron_alerts = cron_service_for_worker.get_triggered_cron_alerts().await.unwrap();
-                for cron_alert in cron_alerts {
+                let deliveries = match cron_service_for_worker.claim_due().await {
+                    Ok(deliveries) => deliveries,
+                    Err(e) => {
+                        log::error!("Failed to claim cron alerts: {e}");
+                        Vec::new()
+                    }
+                };
+                for (cron_alert, delivery) in deliveries {
                     info!("Sending cron alert: {}", cron_alert.token);
-                    let price = cron_service_for_worker.get_price(&cron_alert.token).await.unwrap();
-                    bot_for_cron.send_message(
-                        teloxide::types::ChatId(cron_alert.chat_id), 
-                        format!("⏰ {}: {}", cron_alert.coin, price)
-                    ).await.unwrap();
-
-                    let next_trigger = parse(cron_alert.cron_schedule.trim(), &chrono::Utc::now()).unwrap();
+                    let sent = match cron_service_for_worker.message(&cron_alert, &delivery).await {
+                        Ok(message) => bot_for_cron
+                            .send_message(teloxide::types::ChatId(cron_alert.chat_id), message)
+                            .await
+                            .map(|_| ())
+                            .map_err(anyhow::Error::from),
+                        Err(e) => Err(e),
+                    };
+                    let recorded = match sent {
+                        Ok(()) => cron_service_for_worker.complete_delivery(delivery.id).await,
+                        Err(e) => {
+                            log::error!("Failed to send cron alert {}: {e}", cron_alert.id);
+                            cron_service_for_worker.fail_delivery(delivery.id, &e.to_string()).await
+                        }
+                    };
+                    if let Err(e) = recorded {
+                        log::error!("Failed to record cron delivery {}: {e}", delivery.id);
+                    }
+                }
 
-                    cron_service_for_worker.mark_cron_alert_triggered(cron_alert.id, next_trigger).await.unwrap();
+                let wait = cron_service_for_worker.until_next_trigger().await.unwrap_or_else(|e| {
+                    log::error!("Failed to find the next cron trigger: {e}");
+                    std::time::Duration::from_secs(60)
+                });
+                tokio::select! {
+                    _ = tokio::time::sleep(wait) => {}
+                    _ = cron_service_for_worker.changed() => {}
                 }
-                interval.tick().await;
             }
         } => {
             info!("Cron worker stopped");
         }
+        _ = async move {
+            let mut interval = tokio::time::interval(std::time::Duration::from_secs(30));
+            loop {
+                interval.tick().await;
+                let warnings = match position_service_for_worker.check_positions().await {
+                    Ok(warnings) => warnings,
+                    Err(e) => {
+                        log::error!("Failed to check watched positions: {e}");
+                        continue;
+                    }
+                };
+                for warning in warnings {
+                    if let Err(e) = bot_for_positions.send_message(teloxide::types::ChatId(warning.chat_id), warning.to_string()).await {
+                        log::error!("Failed to send position warning to chat {}: {e}", warning.chat_id);
+                    }
+                }
+            }
+        } => {
+            info!("Position monitor stopped");
+        }
+        _ = async move {
+            let mut interval = tokio::time::interval(std::time::Duration::from_secs(30));
+            loop {
+                interval.tick().await;
+                let notifications = match order_service_for_worker.refresh().await {
+                    Ok(notifications) => notifications,
+                    Err(e) => {
+                        log::error!("Failed to refresh trigger orders: {e}");
+                        continue;
+                    }
+                };
+                for notification in notifications {
+                    if let Err(e) = bot_for_orders.send_message(teloxide::types::ChatId(notification.chat_id), notification.to_string()).await {
+                        log::error!("Failed to send order notification to chat {}: {e}", notification.chat_id);
+                    }
+                }
+            }
+        } => {
+            info!("Trigger order monitor stopped");
+        }
+        _ = async move {
+            while let Some((address, message)) = order_receiver.recv().await {
+                let hyperliquid_rust_sdk::Message::OrderUpdates(updates) = message else {
+                    continue;
+                };
+                let notifications = match order_service.handle_order_updates(&address, updates).await {
+                    Ok(notifications) => notifications,
+                    Err(e) => {
+                        log::error!("Failed to handle order updates for {address}: {e}");
+                        continue;
+                    }
+                };
+                for notification in notifications {
+                    if let Err(e) = bot_for_order_updates.send_message(teloxide::types::ChatId(notification.chat_id), notification.to_string()).await {
+                        log::error!("Failed to send order notification to chat {}: {e}", notification.chat_id);
+                    }
+                }
+            }
+        } => {
+            info!("Order update listener stopped");
+        }
+        _ = async move {
+            let mut interval = tokio::time::interval(std::time::Duration::from_secs(30));
+            loop {
+                interval.tick().await;
+                if let Err(e) = account_event_service_for_worker.sync_subscriptions().await {
+                    log::error!("Failed to sync account event subscriptions: {e}");
+                }
+            }
+        } => {
+            info!("Account event subscription worker stopped");
+        }
+        _ = async move {
+            while let Some(message) = account_event_receiver.recv().await {
+                let notifications = match account_event_service_for_listener.handle_message(message).await {
+                    Ok(notifications) => notifications,
+                    Err(e) => {
+                        log::error!("Failed to handle account event: {e}");
+                        continue;
+                    }
+                };
+                for notification in notifications {
+                    if let Err(e) = bot_for_account_events.send_message(teloxide::types::ChatId(notification.chat_id), notification.to_string()).await {
+                        log::error!("Failed to send account event to chat {}: {e}", notification.chat_id);
+                    }
+                }
+            }
+        } => {
+            info!("Account event listener stopped");
+        }
+        _ = async move {
+            while let Some(message) = whale_receiver.recv().await {
+                let notifications = match whale_service_for_listener.handle_message(message).await {
+                    Ok(notifications) => notifications,
+                    Err(e) => {
+                        log::error!("Failed to handle trades: {e}");
+                        continue;
+                    }
+                };
+                for notification in notifications {
+                    if let Err(e) = bot_for_whales.send_message(teloxide::types::ChatId(notification.chat_id), notification.to_string()).await {
+                        log::error!("Failed to send whale trade to chat {}: {e}", notification.chat_id);
+                    }
+                }
+            }
+        } => {
+            info!("Whale trade listener stopped");
+        }
+        _ = async move {
+            let mut interval = tokio::time::interval(std::time::Duration::from_secs(60));
+            loop {
+                interval.tick().await;
+                let notifications = match listing_service_for_worker.check().await {
+                    Ok(notifications) => notifications,
+                    Err(e) => {
+                        log::error!("Failed to check listings: {e}");
+                        continue;
+                    }
+                };
+                for notification in notifications {
+                    if let Err(e) = bot_for_listings.send_message(teloxide::types::ChatId(notification.chat_id), notification.to_string()).await {
+                        log::error!("Failed to send listing to chat {}: {e}", notification.chat_id);
+                    }
+                }
+            }
+        } => {
+            info!("Listing monitor stopped");
+        }
     }
 }
 
@@ -0,0 +1,245 @@
+use crate::alerts::{AlertService, BookTick, PerpMetrics, PerpTick, PriceTick, RollingHistory};
+use crate::book::OrderBook;
+use crate::db::{AlertTable, MarketType};
+use crate::expressions::{ExpressionTick, MarketSnapshot};
+use crate::subscriptions::Feed;
+use chrono::Utc;
+use hyperliquid_rust_sdk::{AssetCtx, Message, PerpAssetContext};
+use log::{error, info};
+use rusqlite::Result;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+
+#[derive(Clone)]
+pub struct PriceMonitor {
+    alert_service: AlertService,
+    // Last mark price seen per token, used to detect crossings between ticks.
+    last_prices: Arc<Mutex<HashMap<String, f64>>>,
+    // Rolling window of recent prices per token for windowed change alerts.
+    histories: Arc<Mutex<HashMap<String, RollingHistory>>>,
+    // Last funding and premium seen per perp, seeded at startup.
+    last_perp_metrics: Arc<Mutex<HashMap<String, PerpMetrics>>>,
+    // Rolling window of recent open interest per perp.
+    open_interest_histories: Arc<Mutex<HashMap<String, RollingHistory>>>,
+    // Last book seen per feed and token, used to detect crossings.
+    last_books: Arc<Mutex<HashMap<(Feed, String), OrderBook>>>,
+    // Latest asset context per token, read by expression alerts.
+    asset_contexts: Arc<Mutex<HashMap<String, MarketSnapshot>>>,
+}
+
+/// The perp-only values of an update, with open interest in coins.
+struct PerpUpdate {
+    metrics: PerpMetrics,
+    open_interest: f64,
+}
+
+impl PerpUpdate {
+    fn parse(mark_px: f64, oracle_px: &str, funding: &str, open_interest: &str) -> Option<Self> {
+        let oracle_px = oracle_px.parse::<f64>().ok().filter(|px| *px > 0.0)?;
+        Some(Self {
+            metrics: PerpMetrics {
+                funding_pct: funding.parse::<f64>().ok()? * 100.0,
+                premium_bps: (mark_px - oracle_px) / oracle_px * 10_000.0,
+            },
+            open_interest: open_interest.parse::<f64>().ok()?,
+        })
+    }
+}
+
+impl PriceMonitor {
+    pub fn new(alert_service: AlertService) -> Self {
+        Self {
+            alert_service,
+            last_prices: Arc::new(Mutex::new(HashMap::new())),
+            histories: Arc::new(Mutex::new(HashMap::new())),
+            last_perp_metrics: Arc::new(Mutex::new(HashMap::new())),
+            open_interest_histories: Arc::new(Mutex::new(HashMap::new())),
+            last_books: Arc::new(Mutex::new(HashMap::new())),
+            asset_contexts: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Seeds the previous funding, premium and open interest of perps with
+    /// perp metric alerts from their current contexts, so crossings between
+    /// startup and the first update are picked up.
+    pub async fn seed_perp_metrics(&self) -> anyhow::Result<()> {
+        let tokens = self.alert_service.get_perp_metric_tokens().await?;
+        if tokens.is_empty() {
+            return Ok(());
+        }
+        let contexts = self.alert_service.fetch_perp_asset_contexts().await?;
+        for token in tokens {
+            if let Some(ctx) = contexts.get(&token) {
+                self.seed_perp_context(&token, ctx).await;
+            }
+        }
+        Ok(())
+    }
+
+    /// Records `ctx` as the last update of `token`, with the same formulas
+    /// the live updates use, unless an update has already arrived.
+    pub async fn seed_perp_context(&self, token: &str, ctx: &PerpAssetContext) {
+        let Some(perp) = ctx.mark_px.parse::<f64>().ok().and_then(|mark_px| PerpUpdate::parse(mark_px, &ctx.oracle_px, &ctx.funding, &ctx.open_interest)) else {
+            return;
+        };
+        self.last_perp_metrics.lock().await.entry(token.to_string()).or_insert(perp.metrics);
+        self.open_interest_histories.lock().await.entry(token.to_string()).or_default().record(Utc::now(), perp.open_interest);
+    }
+
+    /// Consumes price updates until the channel closes and forwards every
+    /// triggered alert to `triggered_sender`.
+    pub async fn run(&self, mut receiver: UnboundedReceiver<Message>, triggered_sender: UnboundedSender<AlertTable>) {
+        while let Some(message) = receiver.recv().await {
+            let alerts = match self.handle_message(message).await {
+                Ok(alerts) => alerts,
+                Err(e) => {
+                    error!("Failed to evaluate price update: {e}");
+                    continue;
+                }
+            };
+            for alert in alerts {
+                if triggered_sender.send(alert).is_err() {
+                    return;
+                }
+            }
+        }
+    }
+
+    pub async fn handle_message(&self, message: Message) -> Result<Vec<AlertTable>> {
+        match message {
+            Message::ActiveSpotAssetCtx(order_updates) => {
+                info!("Received order update data: {order_updates:?}");
+                let shared = &order_updates.data.ctx.shared;
+                let Ok(mark_px) = shared.mark_px.parse::<f64>() else {
+                    return Ok(Vec::new());
+                };
+                let prev_day_px = shared.prev_day_px.parse::<f64>().ok();
+                self.handle_price(MarketType::Spot, &order_updates.data.coin, mark_px, prev_day_px, None).await
+            }
+            Message::ActiveAssetCtx(asset_ctx) => {
+                info!("Received asset context data: {asset_ctx:?}");
+                let (market, shared) = match &asset_ctx.data.ctx {
+                    AssetCtx::Perps(ctx) => (MarketType::Perp, &ctx.shared),
+                    AssetCtx::Spot(ctx) => (MarketType::Spot, &ctx.shared),
+                };
+                let Ok(mark_px) = shared.mark_px.parse::<f64>() else {
+                    return Ok(Vec::new());
+                };
+                let prev_day_px = shared.prev_day_px.parse::<f64>().ok();
+                let perp = match &asset_ctx.data.ctx {
+                    AssetCtx::Perps(ctx) => PerpUpdate::parse(mark_px, &ctx.oracle_px, &ctx.funding, &ctx.open_interest),
+                    AssetCtx::Spot(_) => None,
+                };
+                self.handle_price(market, &asset_ctx.data.coin, mark_px, prev_day_px, perp).await
+            }
+            Message::Bbo(bbo) => {
+                self.handle_book(Feed::Bbo, &bbo.data.coin, OrderBook::from_bbo(&bbo.data)).await
+            }
+            Message::L2Book(l2_book) => {
+                self.handle_book(Feed::L2Book, &l2_book.data.coin, OrderBook::from_l2_book(&l2_book.data)).await
+            }
+            Message::Candle(candle) => {
+                let alerts = self.alert_service.get_triggered_indicator_alerts(&candle.data).await?;
+                self.alert_service.set_alert_cooldowns(&alerts).await?;
+                Ok(alerts)
+            }
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    async fn handle_book(&self, feed: Feed, token: &str, book: OrderBook) -> Result<Vec<AlertTable>> {
+        let mut last_books = self.last_books.lock().await;
+        let key = (feed, token.to_string());
+        let tick = BookTick {
+            feed,
+            book: &book,
+            previous_book: last_books.get(&key),
+        };
+        let alerts = self.alert_service.get_triggered_book_alerts(token, &tick).await?;
+        last_books.insert(key, book);
+        drop(last_books);
+        self.alert_service.set_alert_cooldowns(&alerts).await?;
+        Ok(alerts)
+    }
+
+    async fn handle_price(
+        &self,
+        market: MarketType,
+        token: &str,
+        mark_px: f64,
+        prev_day_px: Option<f64>,
+        perp: Option<PerpUpdate>,
+    ) -> Result<Vec<AlertTable>> {
+        let last_px = self.last_prices.lock().await.insert(token.to_string(), mark_px);
+        let previous_px = last_px.unwrap_or(mark_px);
+        // Trailing extremes and bands only change with a move.
+        let moved = last_px != Some(mark_px);
+        let now = Utc::now();
+        let mut histories = self.histories.lock().await;
+        let history = histories.entry(token.to_string()).or_default();
+        let mut open_interest_histories = self.open_interest_histories.lock().await;
+        let perp_tick = match &perp {
+            Some(perp) => {
+                let previous = self
+                    .last_perp_metrics
+                    .lock()
+                    .await
+                    .insert(token.to_string(), perp.metrics)
+                    .unwrap_or(perp.metrics);
+                Some(PerpTick {
+                    funding_pct: perp.metrics.funding_pct,
+                    previous_funding_pct: previous.funding_pct,
+                    premium_bps: perp.metrics.premium_bps,
+                    previous_premium_bps: previous.premium_bps,
+                    open_interest: perp.open_interest,
+                    open_interest_history: open_interest_histories.entry(token.to_string()).or_default(),
+                })
+            }
+            None => None,
+        };
+        let tick = PriceTick {
+            previous_px,
+            mark_px,
+            prev_day_px,
+            history,
+            at: now,
+            perp: perp_tick,
+        };
+        if moved {
+            self.alert_service.update_trailing_extremes(market, token, mark_px).await?;
+        }
+        let mut alerts = self.alert_service.get_triggered_alerts(market, token, &tick).await?;
+        history.record(now, mark_px);
+        if let Some(perp) = &perp {
+            open_interest_histories.entry(token.to_string()).or_default().record(now, perp.open_interest);
+        }
+        drop(histories);
+        drop(open_interest_histories);
+
+        let cross_market_alerts = self.alert_service.get_cross_market_alerts(token).await?;
+        let mut asset_contexts = self.asset_contexts.lock().await;
+        let previous = asset_contexts.insert(
+            token.to_string(),
+            MarketSnapshot {
+                mark_px,
+                funding_pct: perp.as_ref().map(|perp| perp.metrics.funding_pct),
+                premium_bps: perp.as_ref().map(|perp| perp.metrics.premium_bps),
+                open_interest: perp.as_ref().map(|perp| perp.open_interest),
+            },
+        );
+        let tick = ExpressionTick {
+            token,
+            previous,
+            contexts: &asset_contexts,
+        };
+        alerts.extend(cross_market_alerts.triggered(&tick));
+        drop(asset_contexts);
+        if moved {
+            self.alert_service.rearm_band_alerts(market, token, mark_px).await?;
+        }
+        self.alert_service.set_alert_cooldowns(&alerts).await?;
+        Ok(alerts)
+    }
+}
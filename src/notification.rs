@@ -1,7 +1,13 @@
+use chrono_tz::Tz;
 use teloxide::{prelude::*, utils::command::BotCommands};
-use crate::db::AlertTable;
+use crate::db::{ActiveWindow, AlertDirection, AlertKind, AlertTable, MisfirePolicy, ReportTemplate};
 use crate::alerts::AlertService;
+use crate::assets::UnknownCoin;
 use crate::cron::CronService;
+use crate::events::{AccountEventKind, AccountEventService};
+use crate::listings::ListingService;
+use crate::positions::{PositionService, DEFAULT_LIQUIDATION_THRESHOLD, DEFAULT_TPSL_THRESHOLD};
+use crate::whales::{WhaleService, MAX_BURST_SECONDS};
 
 #[derive(BotCommands, Clone)]
 #[command(rename_rule = "lowercase", description = "These commands are supported:")]
@@ -10,27 +16,80 @@ pub enum Command {
     Help,
     #[command(description = "Display all alerts.")]
     Alert,
-    #[command(parse_with = "split", alias = "ua", hide_aliases)]
-    SetAlert{coin: String, price: f64},
+    #[command(alias = "ua", hide_aliases)]
+    SetAlert{args: String},
+    #[command(description = "Alert when a condition over one or more markets becomes true, e.g. /setexpression HYPE > 40 AND funding(HYPE) < 0.")]
+    SetExpression{expression: String},
+    #[command(parse_with = "split", description = "Delete a price alert by ID.")]
+    DeleteAlert{id: i64},
+    #[command(description = "Change the target of an alert by ID.")]
+    EditAlert{args: String},
+    #[command(description = "Choose how an alert re-arms after firing: /setrearm <id> once | cooldown <30s|15m|4h|1d> | band <pct>%.")]
+    SetRearm{args: String},
+    #[command(description = "Expire an alert: /setexpiry <id> <12h|7d|2026-12-31|2026-12-31 18:00|never>, in the chat's timezone.")]
+    SetExpiry{args: String},
+    #[command(description = "Only let an alert fire in a window of the chat's timezone: /setwindow <id> [08:00-22:00] [weekdays|weekends|mon,wed,...] | always.")]
+    SetWindow{args: String},
+    #[command(description = "Delete all price alerts in this chat.")]
+    ClearAlerts,
     #[command(description = "Display all cron alerts.")]
     CronAlerts,
-    #[command(parse_with = "split", description = "Create a cron alert at a specific time.")]
-    SetCronAlert{coin: String, schedule: String, time: String},
+    #[command(description = "Send a market report on coins, or a portfolio summary of an address, on a schedule in the chat's timezone: /setcronalert <coin[,coin...]> [price|summary|full] | <address> hourly | every 4h | daily 08:00 | monday 08:00 | weekdays 09:30 | monthly 1 09:00 | <cron>.")]
+    SetCronAlert{args: String},
     #[command(parse_with = "split", description = "Delete a cron alert by ID.")]
     DeleteCronAlert{id: i64},
+    #[command(description = "Choose what a cron alert does about fires missed while the bot was down: /setmisfire <id> once | all | skip.")]
+    SetMisfire{args: String},
+    #[command(description = "Set the timezone for schedules, windows and times shown: /settimezone Europe/London.")]
+    SetTimezone{timezone: String},
+    #[command(description = "Watch an address for positions near liquidation or TP/SL: /watch <address> [liq%] [tpsl%].")]
+    Watch{args: String},
+    #[command(parse_with = "split", description = "Stop watching an address.")]
+    Unwatch{address: String},
+    #[command(description = "Display all watched addresses.")]
+    Watched,
+    #[command(description = "Display which account events are sent for watched addresses.")]
+    Events,
+    #[command(description = "Choose account events for watched addresses: /setevents <event...> | all | none.")]
+    SetEvents{args: String},
+    #[command(description = "Display all whale trade alerts.")]
+    Whales,
+    #[command(description = "Alert on trades over a notional: /setwhale <coin> <notional> [burst seconds].")]
+    SetWhale{args: String},
+    #[command(parse_with = "split", description = "Delete a whale trade alert by ID.")]
+    DeleteWhale{id: i64},
+    #[command(description = "Get notified when perps or spot pairs are listed or delisted.")]
+    SubscribeListings,
+    #[command(description = "Stop listing and delisting notifications.")]
+    UnsubscribeListings,
 }
 
 #[derive(Clone)]
 pub struct NotificationService {
     alert_service: AlertService,
     cron_service: CronService,
+    position_service: PositionService,
+    account_event_service: AccountEventService,
+    whale_service: WhaleService,
+    listing_service: ListingService,
 }
 
 impl NotificationService {
-    pub fn new(alert_service: AlertService, cron_service: CronService) -> Self {
+    pub fn new(
+        alert_service: AlertService,
+        cron_service: CronService,
+        position_service: PositionService,
+        account_event_service: AccountEventService,
+        whale_service: WhaleService,
+        listing_service: ListingService,
+    ) -> Self {
         Self {
             alert_service,
             cron_service,
+            position_service,
+            account_event_service,
+            whale_service,
+            listing_service,
         }
     }
 
@@ -42,35 +101,395 @@ impl NotificationService {
             Command::Alert => {
                 // let mut alert_price_guard = self.alert_price.lock().await;
                 // *alert_price_guard = price;
-                let alerts = self.alert_service.get_all_alerts_for_chat(msg.chat.id).await.unwrap();
-                let alerts_buffer = alerts.iter().map(|alert| alert.to_string()).collect::<Vec<String>>().join("\n");
+                let (alerts, timezone) = match (self.alert_service.get_all_alerts_for_chat(msg.chat.id).await, self.alert_service.timezone(msg.chat.id).await) {
+                    (Ok(alerts), Ok(timezone)) => (alerts, timezone),
+                    (Err(e), _) | (_, Err(e)) => {
+                        bot.send_message(msg.chat.id, failure("Failed to load alerts", &e.into())).await?;
+                        return Ok(());
+                    }
+                };
+                let alerts_buffer = alerts.iter().map(|alert| alert.localized(timezone).to_string()).collect::<Vec<String>>().join("\n");
                 bot.send_message(msg.chat.id, format!("Alerts: {alerts_buffer}")).await?
             }
-            Command::SetAlert{coin, price} => {
-                self.alert_service.create_alert("0x00",msg.chat.id, &coin, price).await.unwrap();
-                bot.send_message(msg.chat.id, format!("Alert price set to {price} for {coin}.")).await?
+            Command::SetAlert{args} => {
+                let Some((coin, target)) = split_target(&args) else {
+                    bot.send_message(msg.chat.id, "Usage: /setalert <coin> <target>").await?;
+                    return Ok(());
+                };
+                let target = match AlertService::parse_target(&target) {
+                    Ok(parsed) => parsed,
+                    Err(e) => {
+                        bot.send_message(msg.chat.id, e.to_string()).await?;
+                        return Ok(());
+                    }
+                };
+                match self.alert_service.create_alert("0x00", msg.chat.id, coin, target).await {
+                    Ok(target) => bot.send_message(msg.chat.id, format!("Alert set for {coin} {target}.")).await?,
+                    Err(e) => bot.send_message(msg.chat.id, failure("Failed to set alert", &e)).await?,
+                }
+            }
+            Command::SetExpression{expression} => {
+                let target = match AlertService::parse_expression(&expression) {
+                    Ok(parsed) => parsed,
+                    Err(e) => {
+                        bot.send_message(msg.chat.id, format!("{e}\nUsage: /setexpression <expression>")).await?;
+                        return Ok(());
+                    }
+                };
+                match self.alert_service.create_expression_alert("0x00", msg.chat.id, target).await {
+                    Ok(target) => bot.send_message(msg.chat.id, format!("Alert set for {target}.")).await?,
+                    Err(e) => bot.send_message(msg.chat.id, failure("Failed to set alert", &e)).await?,
+                }
+            }
+            Command::DeleteAlert{id} => {
+                match self.alert_service.delete_alert(id, msg.chat.id).await {
+                    Ok(true) => bot.send_message(msg.chat.id, format!("Alert {id} deleted.")).await?,
+                    Ok(false) => bot.send_message(msg.chat.id, format!("Alert {id} not found.")).await?,
+                    Err(e) => bot.send_message(msg.chat.id, failure(&format!("Failed to delete alert {id}"), &e.into())).await?,
+                }
+            }
+            Command::EditAlert{args} => {
+                let Some((id, target)) = split_target(&args).and_then(|(id, target)| Some((id.parse::<i64>().ok()?, target))) else {
+                    bot.send_message(msg.chat.id, "Usage: /editalert <id> <target>").await?;
+                    return Ok(());
+                };
+                // Expressions need their whitespace to tell `AND` and `OR`
+                // apart from coins, so they are parsed from the raw arguments.
+                let raw_target = args.trim().split_once(char::is_whitespace).map(|(_, target)| target).unwrap_or_default();
+                let target = match AlertService::parse_target(&target).or_else(|e| AlertService::parse_expression(raw_target).map_err(|_| e)) {
+                    Ok(parsed) => parsed,
+                    Err(e) => {
+                        bot.send_message(msg.chat.id, e.to_string()).await?;
+                        return Ok(());
+                    }
+                };
+                match self.alert_service.edit_alert(id, msg.chat.id, target).await {
+                    Ok(Some(target)) => {
+                        bot.send_message(msg.chat.id, format!("Alert {id} updated to {target}.")).await?
+                    }
+                    Ok(None) => bot.send_message(msg.chat.id, format!("Alert {id} not found.")).await?,
+                    Err(e) => bot.send_message(msg.chat.id, failure(&format!("Failed to update alert {id}"), &e)).await?,
+                }
+            }
+            Command::SetRearm{args} => {
+                const USAGE: &str = "Usage: /setrearm <id> once | cooldown <30s|15m|4h|1d> | band <pct>%";
+                let Some((id, mode)) = args.trim().split_once(char::is_whitespace).and_then(|(id, mode)| Some((id.parse::<i64>().ok()?, mode))) else {
+                    bot.send_message(msg.chat.id, USAGE).await?;
+                    return Ok(());
+                };
+                let rearm = match AlertService::parse_rearm_mode(mode) {
+                    Ok(rearm) => rearm,
+                    Err(e) => {
+                        bot.send_message(msg.chat.id, format!("{e}\n{USAGE}")).await?;
+                        return Ok(());
+                    }
+                };
+                match self.alert_service.set_rearm_mode(id, msg.chat.id, rearm).await {
+                    Ok(true) => bot.send_message(msg.chat.id, format!("Alert {id} is now {rearm} and armed.")).await?,
+                    Ok(false) => bot.send_message(msg.chat.id, format!("Alert {id} not found.")).await?,
+                    Err(e) => bot.send_message(msg.chat.id, failure(&format!("Failed to update alert {id}"), &e)).await?,
+                }
+            }
+            Command::SetExpiry{args} => {
+                const USAGE: &str = "Usage: /setexpiry <id> <12h|7d|2026-12-31|2026-12-31 18:00|never>";
+                let Some((id, expiry)) = args.trim().split_once(char::is_whitespace).and_then(|(id, expiry)| Some((id.parse::<i64>().ok()?, expiry))) else {
+                    bot.send_message(msg.chat.id, USAGE).await?;
+                    return Ok(());
+                };
+                let timezone = match self.alert_service.timezone(msg.chat.id).await {
+                    Ok(timezone) => timezone,
+                    Err(e) => {
+                        bot.send_message(msg.chat.id, failure(&format!("Failed to set the expiry of alert {id}"), &e.into())).await?;
+                        return Ok(());
+                    }
+                };
+                let expires_at = match AlertService::parse_expiry(expiry, timezone) {
+                    Ok(expires_at) => expires_at,
+                    Err(e) => {
+                        bot.send_message(msg.chat.id, format!("{e}\n{USAGE}")).await?;
+                        return Ok(());
+                    }
+                };
+                match self.alert_service.set_expiry(id, msg.chat.id, expires_at).await {
+                    Ok(false) => bot.send_message(msg.chat.id, format!("Alert {id} not found.")).await?,
+                    Ok(true) => match expires_at {
+                        Some(expires_at) => bot.send_message(msg.chat.id, format!("Alert {id} expires {}.", expires_at.with_timezone(&timezone).format("%Y-%m-%d %H:%M:%S %Z"))).await?,
+                        None => bot.send_message(msg.chat.id, format!("Alert {id} no longer expires.")).await?,
+                    },
+                    Err(e) => bot.send_message(msg.chat.id, failure(&format!("Failed to set the expiry of alert {id}"), &e.into())).await?,
+                }
+            }
+            Command::SetWindow{args} => {
+                const USAGE: &str = "Usage: /setwindow <id> [08:00-22:00] [weekdays|weekends|mon,wed,...] | always";
+                let Some((id, window)) = args.trim().split_once(char::is_whitespace).and_then(|(id, window)| Some((id.parse::<i64>().ok()?, window.trim()))) else {
+                    bot.send_message(msg.chat.id, USAGE).await?;
+                    return Ok(());
+                };
+                let window = match window {
+                    "always" => None,
+                    window => match window.parse::<ActiveWindow>() {
+                        // Windows without a timezone are in the chat's.
+                        Ok(mut parsed) => {
+                            if window.split_whitespace().last().is_none_or(|last| last.parse::<Tz>().is_err()) {
+                                parsed.timezone = match self.alert_service.timezone(msg.chat.id).await {
+                                    Ok(timezone) => timezone,
+                                    Err(e) => {
+                                        bot.send_message(msg.chat.id, failure(&format!("Failed to set the window of alert {id}"), &e.into())).await?;
+                                        return Ok(());
+                                    }
+                                };
+                            }
+                            Some(parsed)
+                        }
+                        Err(e) => {
+                            bot.send_message(msg.chat.id, format!("{e}\n{USAGE}")).await?;
+                            return Ok(());
+                        }
+                    },
+                };
+                match self.alert_service.set_active_window(id, msg.chat.id, window.as_ref()).await {
+                    Ok(false) => bot.send_message(msg.chat.id, format!("Alert {id} not found.")).await?,
+                    Ok(true) => match window {
+                        Some(window) => {
+                            let utc = if window.timezone == Tz::UTC { " UTC" } else { "" };
+                            bot.send_message(msg.chat.id, format!("Alert {id} only fires {window}{utc}.")).await?
+                        }
+                        None => bot.send_message(msg.chat.id, format!("Alert {id} fires at any time.")).await?,
+                    },
+                    Err(e) => bot.send_message(msg.chat.id, failure(&format!("Failed to set the window of alert {id}"), &e.into())).await?,
+                }
+            }
+            Command::ClearAlerts => {
+                match self.alert_service.clear_alerts(msg.chat.id).await {
+                    Ok(deleted) => bot.send_message(msg.chat.id, format!("Deleted {deleted} alerts.")).await?,
+                    Err(e) => bot.send_message(msg.chat.id, failure("Failed to clear alerts", &e.into())).await?,
+                }
             }
             Command::CronAlerts => {
-                let cron_alerts = self.cron_service.get_cron_alerts_for_chat(msg.chat.id).await.unwrap();
-                let alerts_buffer = cron_alerts.iter().map(|alert| alert.to_string()).collect::<Vec<String>>().join("\n");
+                let (cron_alerts, timezone) = match (self.cron_service.get_cron_alerts_for_chat(msg.chat.id).await, self.cron_service.timezone(msg.chat.id).await) {
+                    (Ok(cron_alerts), Ok(timezone)) => (cron_alerts, timezone),
+                    (Err(e), _) | (_, Err(e)) => {
+                        bot.send_message(msg.chat.id, failure("Failed to load cron alerts", &e.into())).await?;
+                        return Ok(());
+                    }
+                };
+                let alerts_buffer = cron_alerts.iter().map(|alert| alert.localized(timezone).to_string()).collect::<Vec<String>>().join("\n");
                 bot.send_message(msg.chat.id, format!("Cron Alerts:\n{alerts_buffer}")).await?
             }
-            Command::SetCronAlert{coin, schedule, time} => {
-                let cron_schedule = self.cron_service.create_schedule(&schedule, &time).await.unwrap();
-                if let Ok(_) = cron_parser::parse(&cron_schedule, &chrono::Utc::now()) {
-                    println!("Cron alert set with schedule {cron_schedule} for {coin}.");
-                    self.cron_service.create_cron_alert(msg.chat.id, &coin, &cron_schedule).await.unwrap();
-                    bot.send_message(msg.chat.id, format!("Cron alert set with schedule {cron_schedule} for {coin}.")).await?
-                } else {
-                    println!("Invalid schedule: {schedule}");
-                    bot.send_message(msg.chat.id, format!("Invalid schedule: {schedule}")).await?;
+            Command::SetCronAlert{args} => {
+                const USAGE: &str = "Usage: /setcronalert <coin[,coin...]> [price|summary|full] <schedule> or /setcronalert <address> <schedule>, e.g. /setcronalert HYPE,BTC-PERP summary weekdays 09:30";
+                let Some((coin, schedule)) = args.trim().split_once(char::is_whitespace) else {
+                    bot.send_message(msg.chat.id, USAGE).await?;
                     return Ok(());
+                };
+                if coin.starts_with("0x") {
+                    let address = match PositionService::parse_address(coin) {
+                        Ok(address) => address,
+                        Err(e) => {
+                            bot.send_message(msg.chat.id, format!("{e}\n{USAGE}")).await?;
+                            return Ok(());
+                        }
+                    };
+                    let cron_schedule = match CronService::parse_schedule(schedule) {
+                        Ok(cron_schedule) => cron_schedule,
+                        Err(e) => {
+                            bot.send_message(msg.chat.id, format!("{e}\n{USAGE}")).await?;
+                            return Ok(());
+                        }
+                    };
+                    match self.cron_service.create_portfolio_alert(msg.chat.id, &address, &cron_schedule).await {
+                        Ok(()) => bot.send_message(msg.chat.id, format!("Cron alert set with schedule {cron_schedule} for the portfolio of {address}.")).await?,
+                        Err(e) => bot.send_message(msg.chat.id, failure("Failed to set cron alert", &e)).await?,
+                    };
+                    return Ok(());
+                }
+                let (template, schedule) = match schedule.trim().split_once(char::is_whitespace) {
+                    Some((template, schedule)) if template.parse::<ReportTemplate>().is_ok() => (template.parse().unwrap(), schedule),
+                    _ => (ReportTemplate::default(), schedule),
+                };
+                let cron_schedule = match CronService::parse_schedule(schedule) {
+                    Ok(cron_schedule) => cron_schedule,
+                    Err(e) => {
+                        bot.send_message(msg.chat.id, format!("{e}\n{USAGE}")).await?;
+                        return Ok(());
+                    }
+                };
+                match self.cron_service.create_cron_alert(msg.chat.id, coin, &cron_schedule, template).await {
+                    Ok(()) => bot.send_message(msg.chat.id, format!("Cron alert set with schedule {cron_schedule} for {coin} ({template} report).")).await?,
+                    Err(e) => bot.send_message(msg.chat.id, failure("Failed to set cron alert", &e)).await?,
                 }
-                
             }
             Command::DeleteCronAlert{id} => {
-                self.cron_service.delete_cron_alert(id).await.unwrap();
-                bot.send_message(msg.chat.id, format!("Cron alert {id} deleted.")).await?
+                match self.cron_service.delete_cron_alert(id, msg.chat.id).await {
+                    Ok(true) => bot.send_message(msg.chat.id, format!("Cron alert {id} deleted.")).await?,
+                    Ok(false) => bot.send_message(msg.chat.id, format!("Cron alert {id} not found.")).await?,
+                    Err(e) => bot.send_message(msg.chat.id, failure(&format!("Failed to delete cron alert {id}"), &e.into())).await?,
+                }
+            }
+            Command::SetMisfire{args} => {
+                const USAGE: &str = "Usage: /setmisfire <id> once | all | skip";
+                let Some((id, misfire)) = args.trim().split_once(char::is_whitespace).and_then(|(id, misfire)| Some((id.parse::<i64>().ok()?, misfire.trim()))) else {
+                    bot.send_message(msg.chat.id, USAGE).await?;
+                    return Ok(());
+                };
+                let misfire = match misfire.parse::<MisfirePolicy>() {
+                    Ok(misfire) => misfire,
+                    Err(e) => {
+                        bot.send_message(msg.chat.id, format!("{e}\n{USAGE}")).await?;
+                        return Ok(());
+                    }
+                };
+                match self.cron_service.set_misfire_policy(id, msg.chat.id, misfire).await {
+                    Ok(true) => bot.send_message(msg.chat.id, format!("Cron alert {id} now uses the {misfire} misfire policy.")).await?,
+                    Ok(false) => bot.send_message(msg.chat.id, format!("Cron alert {id} not found.")).await?,
+                    Err(e) => bot.send_message(msg.chat.id, failure(&format!("Failed to set the misfire policy of cron alert {id}"), &e.into())).await?,
+                }
+            }
+            Command::SetTimezone{timezone} => {
+                const USAGE: &str = "Usage: /settimezone <Area/City>, e.g. /settimezone Europe/London";
+                let timezone = timezone.trim();
+                if timezone.is_empty() {
+                    match self.cron_service.timezone(msg.chat.id).await {
+                        Ok(current) => bot.send_message(msg.chat.id, format!("Timezone: {current}\n{USAGE}")).await?,
+                        Err(e) => bot.send_message(msg.chat.id, failure("Failed to load the timezone", &e.into())).await?,
+                    };
+                    return Ok(());
+                }
+                let Ok(timezone) = timezone.parse::<Tz>() else {
+                    bot.send_message(msg.chat.id, format!("Unknown timezone: {timezone}\n{USAGE}")).await?;
+                    return Ok(());
+                };
+                match self.cron_service.set_timezone(msg.chat.id, timezone).await {
+                    Ok(()) => bot.send_message(msg.chat.id, format!("Timezone set to {timezone}. Schedules and times now use it.")).await?,
+                    Err(e) => bot.send_message(msg.chat.id, failure("Failed to set timezone", &e)).await?,
+                }
+            }
+            Command::Watch{args} => {
+                let mut args = args.split_whitespace();
+                let address = match PositionService::parse_address(args.next().unwrap_or_default()) {
+                    Ok(address) => address,
+                    Err(e) => {
+                        bot.send_message(msg.chat.id, format!("{e}\nUsage: /watch <address> [liq%] [tpsl%]")).await?;
+                        return Ok(());
+                    }
+                };
+                let (Some(liquidation_threshold), Some(tpsl_threshold)) = (
+                    parse_percentage(args.next(), DEFAULT_LIQUIDATION_THRESHOLD),
+                    parse_percentage(args.next(), DEFAULT_TPSL_THRESHOLD),
+                ) else {
+                    bot.send_message(msg.chat.id, "Invalid percentage. Usage: /watch <address> [liq%] [tpsl%]").await?;
+                    return Ok(());
+                };
+                match self.position_service.watch_address(msg.chat.id, &address, liquidation_threshold, tpsl_threshold).await {
+                    Ok(()) => bot.send_message(msg.chat.id, format!("Watching {address}. You'll be warned when a position is within {liquidation_threshold}% of liquidation or price is within {tpsl_threshold}% of a TP/SL.")).await?,
+                    Err(e) => bot.send_message(msg.chat.id, failure(&format!("Failed to watch {address}"), &e.into())).await?,
+                }
+            }
+            Command::Unwatch{address} => {
+                let address = match PositionService::parse_address(&address) {
+                    Ok(address) => address,
+                    Err(e) => {
+                        bot.send_message(msg.chat.id, e.to_string()).await?;
+                        return Ok(());
+                    }
+                };
+                match self.position_service.unwatch_address(msg.chat.id, &address).await {
+                    Ok(true) => bot.send_message(msg.chat.id, format!("Stopped watching {address}.")).await?,
+                    Ok(false) => bot.send_message(msg.chat.id, format!("{address} is not being watched.")).await?,
+                    Err(e) => bot.send_message(msg.chat.id, failure(&format!("Failed to stop watching {address}"), &e.into())).await?,
+                }
+            }
+            Command::Watched => {
+                match self.position_service.get_watched_addresses_for_chat(msg.chat.id).await {
+                    Ok(watched) => {
+                        let watched_buffer = watched.iter().map(|watched| watched.to_string()).collect::<Vec<String>>().join("\n");
+                        bot.send_message(msg.chat.id, format!("Watched addresses:\n{watched_buffer}")).await?
+                    }
+                    Err(e) => bot.send_message(msg.chat.id, failure("Failed to load watched addresses", &e.into())).await?,
+                }
+            }
+            Command::Events => {
+                let enabled = match self.account_event_service.get_enabled_events(msg.chat.id).await {
+                    Ok(enabled) => enabled,
+                    Err(e) => {
+                        bot.send_message(msg.chat.id, failure("Failed to load enabled events", &e.into())).await?;
+                        return Ok(());
+                    }
+                };
+                let enabled = enabled.iter().map(|event| event.as_str()).collect::<Vec<&str>>().join(", ");
+                let available = AccountEventKind::ALL.iter().map(|event| event.as_str()).collect::<Vec<&str>>().join(", ");
+                bot.send_message(msg.chat.id, format!("Enabled events: {enabled}\nAvailable events: {available}")).await?
+            }
+            Command::SetEvents{args} => {
+                let events = match args.trim() {
+                    "all" => Ok(AccountEventKind::ALL.to_vec()),
+                    "none" => Ok(Vec::new()),
+                    args => args.split_whitespace().map(|event| event.parse::<AccountEventKind>()).collect::<anyhow::Result<Vec<_>>>(),
+                };
+                let events = match events {
+                    Ok(events) => events,
+                    Err(e) => {
+                        bot.send_message(msg.chat.id, format!("{e}\nUse /events to see the available events.")).await?;
+                        return Ok(());
+                    }
+                };
+                if let Err(e) = self.account_event_service.set_enabled_events(msg.chat.id, &events).await {
+                    bot.send_message(msg.chat.id, failure("Failed to set enabled events", &e.into())).await?;
+                    return Ok(());
+                }
+                let enabled = events.iter().map(|event| event.as_str()).collect::<Vec<&str>>().join(", ");
+                bot.send_message(msg.chat.id, format!("Enabled events: {enabled}")).await?
+            }
+            Command::Whales => {
+                match self.whale_service.get_whale_alerts_for_chat(msg.chat.id).await {
+                    Ok(alerts) => {
+                        let alerts_buffer = alerts.iter().map(|alert| alert.to_string()).collect::<Vec<String>>().join("\n");
+                        bot.send_message(msg.chat.id, format!("Whale alerts:\n{alerts_buffer}")).await?
+                    }
+                    Err(e) => bot.send_message(msg.chat.id, failure("Failed to load whale alerts", &e.into())).await?,
+                }
+            }
+            Command::SetWhale{args} => {
+                const USAGE: &str = "Usage: /setwhale <coin> <notional> [burst seconds]";
+                let mut args = args.split_whitespace();
+                let coin = args.next().unwrap_or_default().to_string();
+                let min_notional = args.next().and_then(|notional| notional.trim_start_matches('$').parse::<f64>().ok());
+                let burst_seconds = args.next().map(|seconds| seconds.trim_end_matches('s').parse::<i64>().ok());
+                let (false, Some(min_notional), None | Some(Some(1..=MAX_BURST_SECONDS))) =
+                    (coin.is_empty(), min_notional.filter(|notional| *notional > 0.0), burst_seconds)
+                else {
+                    bot.send_message(msg.chat.id, format!("Invalid whale alert. {USAGE} (burst of at most {MAX_BURST_SECONDS}s)")).await?;
+                    return Ok(());
+                };
+                let burst_seconds = burst_seconds.flatten();
+                match self.whale_service.set_whale_alert(msg.chat.id, &coin, min_notional, burst_seconds).await {
+                    Ok(()) => {
+                        let burst = burst_seconds.map(|seconds| format!(" or same-side bursts within {seconds}s")).unwrap_or_default();
+                        bot.send_message(msg.chat.id, format!("Whale alert set for {coin} trades over ${min_notional}{burst}.")).await?
+                    }
+                    Err(e) => bot.send_message(msg.chat.id, failure("Failed to set whale alert", &e)).await?,
+                }
+            }
+            Command::DeleteWhale{id} => {
+                match self.whale_service.delete_whale_alert(id, msg.chat.id).await {
+                    Ok(true) => bot.send_message(msg.chat.id, format!("Whale alert {id} deleted.")).await?,
+                    Ok(false) => bot.send_message(msg.chat.id, format!("Whale alert {id} not found.")).await?,
+                    Err(e) => bot.send_message(msg.chat.id, failure(&format!("Failed to delete whale alert {id}"), &e.into())).await?,
+                }
+            }
+            Command::SubscribeListings => {
+                match self.listing_service.subscribe(msg.chat.id).await {
+                    Ok(true) => bot.send_message(msg.chat.id, "You'll be notified of new listings and delistings.").await?,
+                    Ok(false) => bot.send_message(msg.chat.id, "Already subscribed to listings.").await?,
+                    Err(e) => bot.send_message(msg.chat.id, failure("Failed to subscribe to listings", &e.into())).await?,
+                }
+            }
+            Command::UnsubscribeListings => {
+                match self.listing_service.unsubscribe(msg.chat.id).await {
+                    Ok(true) => bot.send_message(msg.chat.id, "Unsubscribed from listings.").await?,
+                    Ok(false) => bot.send_message(msg.chat.id, "Not subscribed to listings.").await?,
+                    Err(e) => bot.send_message(msg.chat.id, failure("Failed to unsubscribe from listings", &e.into())).await?,
+                }
             }
         };
 
@@ -80,9 +499,49 @@ impl NotificationService {
     pub async fn send_alert(&self, bot: Bot, alert: &AlertTable) -> ResponseResult<()> {
         bot.send_message(
             teloxide::types::ChatId(alert.chat_id), 
-            format!("🔔 Price Alert: {} is at {}", alert.coin, alert.price)
+            alert_message(alert)
         ).await?;
         Ok(())
     }
 
 }
+
+pub fn alert_message(alert: &AlertTable) -> String {
+    match (alert.kind, alert.direction) {
+        (AlertKind::Price, AlertDirection::Above) => format!("🔔 Price Alert: {} crossed above {}", alert.coin, alert.price),
+        (AlertKind::Price, AlertDirection::Below) => format!("🔔 Price Alert: {} crossed below {}", alert.coin, alert.price),
+        (AlertKind::Price, AlertDirection::Cross) => format!("🔔 Price Alert: {} is at {}", alert.coin, alert.price),
+        (AlertKind::Indicator, _) => format!("📈 Indicator Alert: {} {}", alert.coin, alert.target()),
+        (AlertKind::Expression, _) => format!("🧮 Expression Alert: {}", alert.target()),
+        (AlertKind::Ratio | AlertKind::Basis, _) => format!("⚖️ Pair Alert: {} {}", alert.coin, alert.target()),
+        (AlertKind::Trailing, _) => format!("📉 Trailing Alert: {} {}", alert.coin, alert.target()),
+        _ => format!("🔔 Move Alert: {} {}", alert.coin, alert.target()),
+    }
+}
+
+/// The reply to a command that failed with `e`. Unknown coins are reported
+/// as they are, anything else after `action`.
+fn failure(action: &str, e: &anyhow::Error) -> String {
+    match e.downcast_ref::<UnknownCoin>() {
+        Some(unknown) => unknown.to_string(),
+        None => format!("{action}: {e}"),
+    }
+}
+
+/// Splits `<first> <target>` command arguments, dropping the whitespace
+/// inside the target so `rsi14(1h) < 30` parses like `rsi14(1h)<30`.
+fn split_target(args: &str) -> Option<(&str, String)> {
+    let (first, target) = args.trim().split_once(char::is_whitespace)?;
+    let target: String = target.split_whitespace().collect();
+    Some((first, target))
+}
+
+/// Parses an optional positive percentage such as `5` or `5%`, falling back to
+/// `default` when it is missing.
+fn parse_percentage(arg: Option<&str>, default: f64) -> Option<f64> {
+    match arg.map(|arg| arg.trim_end_matches('%').parse::<f64>()) {
+        None => Some(default),
+        Some(Ok(percentage)) if percentage > 0.0 => Some(percentage),
+        Some(_) => None,
+    }
+}
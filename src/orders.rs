@@ -0,0 +1,330 @@
+use crate::db::Database;
+use ethers::types::H160;
+use hyperliquid_rust_sdk::{BaseUrl, InfoClient, Message, OrderUpdate, OrderUpdates, Subscription, UserFillsResponse};
+use log::{error, info};
+use rusqlite::Result;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tokio::sync::mpsc::{UnboundedSender, unbounded_channel};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriggerKind {
+    TakeProfit,
+    StopLoss,
+}
+
+impl TriggerKind {
+    fn from_order_type(order_type: &str) -> Option<Self> {
+        if order_type.starts_with("Take Profit") {
+            Some(TriggerKind::TakeProfit)
+        } else if order_type.starts_with("Stop") {
+            Some(TriggerKind::StopLoss)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TriggerKind::TakeProfit => "TP",
+            TriggerKind::StopLoss => "SL",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TriggerOrder {
+    pub address: String,
+    pub oid: u64,
+    pub coin: String,
+    pub side: String,
+    pub sz: String,
+    pub trigger_px: f64,
+    pub kind: TriggerKind,
+}
+
+#[derive(Debug, Clone)]
+pub enum OrderEvent {
+    Approaching { mid_px: f64, distance_pct: f64 },
+    Triggered,
+    /// `avg_px` is the size-weighted price of the order's fills, `None` when
+    /// they could not be found.
+    Filled { avg_px: Option<f64>, limit_px: String },
+    Cancelled { status: String },
+}
+
+impl OrderEvent {
+    /// Maps an order update to the event it reports, taking the fill price
+    /// from the address's `fills`. Updates that report nothing, such as a
+    /// resting order, give `None`.
+    pub fn from_update(update: &OrderUpdate, fills: &[UserFillsResponse]) -> Option<Self> {
+        match update.status.as_str() {
+            "triggered" => Some(OrderEvent::Triggered),
+            "filled" => Some(OrderEvent::Filled {
+                avg_px: average_fill_price(fills, update.order.oid),
+                limit_px: update.order.limit_px.clone(),
+            }),
+            status if status.ends_with("anceled") || status.ends_with("ejected") => Some(OrderEvent::Cancelled { status: status.to_string() }),
+            _ => None,
+        }
+    }
+}
+
+fn average_fill_price(fills: &[UserFillsResponse], oid: u64) -> Option<f64> {
+    let (notional, size) = fills
+        .iter()
+        .filter(|fill| fill.oid == oid)
+        .filter_map(|fill| Some((fill.px.parse::<f64>().ok()?, fill.sz.parse::<f64>().ok()?)))
+        .fold((0.0, 0.0), |(notional, size), (px, sz)| (notional + px * sz, size + sz));
+    (size > 0.0).then(|| notional / size)
+}
+
+#[derive(Debug, Clone)]
+pub struct OrderNotification {
+    pub chat_id: i64,
+    pub order: TriggerOrder,
+    pub event: OrderEvent,
+}
+
+impl std::fmt::Display for OrderNotification {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let kind = self.order.kind.as_str();
+        let coin = &self.order.coin;
+        match &self.event {
+            OrderEvent::Approaching { mid_px, distance_pct } => write!(
+                f,
+                "⚠️ Your {kind} on {coin} is {distance_pct:.2}% away (trigger {}, price {mid_px}) [{}]",
+                self.order.trigger_px, self.order.address
+            ),
+            OrderEvent::Triggered => write!(
+                f,
+                "⚡ Your {kind} on {coin} triggered at {} [{}]",
+                self.order.trigger_px, self.order.address
+            ),
+            OrderEvent::Filled { avg_px: Some(avg_px), .. } => write!(
+                f,
+                "✅ Your {kind} on {coin} filled at {avg_px} (size {}) [{}]",
+                self.order.sz, self.order.address
+            ),
+            OrderEvent::Filled { avg_px: None, limit_px } => write!(
+                f,
+                "✅ Your {kind} on {coin} filled (limit price {limit_px}, size {}) [{}]",
+                self.order.sz, self.order.address
+            ),
+            OrderEvent::Cancelled { status } => write!(
+                f,
+                "❌ Your {kind} on {coin} was cancelled ({status}) [{}]",
+                self.order.address
+            ),
+        }
+    }
+}
+
+/// Tracks the TP/SL trigger orders of watched addresses. Open orders are
+/// polled via `refresh`, and fills/cancellations arrive through one
+/// `OrderUpdates` subscription per address, forwarded to `sender` tagged with
+/// the address they belong to.
+#[derive(Clone)]
+pub struct OrderService {
+    db: Database,
+    info_client: Arc<Mutex<InfoClient>>,
+    sender: UnboundedSender<(String, Message)>,
+    // Order updates carry no user, and the SDK multiplexes every
+    // `OrderUpdates` subscription on a connection into one stream, so each
+    // address gets its own websocket client.
+    clients: Arc<Mutex<HashMap<String, InfoClient>>>,
+    trigger_orders: Arc<Mutex<HashMap<String, HashMap<u64, TriggerOrder>>>>,
+    // (chat_id, oid) of orders that have already been warned about.
+    warned: Arc<Mutex<HashSet<(i64, u64)>>>,
+}
+
+impl OrderService {
+    pub fn new(db: Database, info_client: Arc<Mutex<InfoClient>>, sender: UnboundedSender<(String, Message)>) -> Self {
+        Self {
+            db,
+            info_client,
+            sender,
+            clients: Arc::new(Mutex::new(HashMap::new())),
+            trigger_orders: Arc::new(Mutex::new(HashMap::new())),
+            warned: Arc::new(Mutex::new(HashSet::new())),
+        }
+    }
+
+    /// Syncs order update subscriptions with the watched addresses, reloads
+    /// their open trigger orders and returns a warning for every order whose
+    /// trigger price the market has moved close to.
+    pub async fn refresh(&self) -> Result<Vec<OrderNotification>> {
+        let watched = self.db.get_all_watched_addresses().await?;
+        let addresses: HashSet<String> = watched.iter().map(|watched| watched.address.clone()).collect();
+        self.sync_subscriptions(&addresses).await;
+
+        let mut trigger_orders = HashMap::new();
+        for address in &addresses {
+            match self.fetch_trigger_orders(address).await {
+                Ok(orders) => {
+                    trigger_orders.insert(address.clone(), orders);
+                }
+                Err(e) => error!("Failed to fetch trigger orders for {address}: {e}"),
+            }
+        }
+
+        let mids = match self.info_client.lock().await.all_mids().await {
+            Ok(mids) => mids,
+            Err(e) => {
+                error!("Failed to fetch mids: {e}");
+                HashMap::new()
+            }
+        };
+
+        let mut notifications = Vec::new();
+        let mut warned = self.warned.lock().await;
+        let open_oids: HashSet<u64> = trigger_orders.values().flat_map(|orders| orders.keys().copied()).collect();
+        warned.retain(|(_, oid)| open_oids.contains(oid));
+        for watcher in &watched {
+            let Some(orders) = trigger_orders.get(&watcher.address) else {
+                continue;
+            };
+            for order in orders.values() {
+                let Some(mid_px) = mids.get(&order.coin).and_then(|px| px.parse::<f64>().ok()) else {
+                    continue;
+                };
+                let distance_pct = (mid_px - order.trigger_px).abs() / mid_px * 100.0;
+                let key = (watcher.chat_id, order.oid);
+                if distance_pct > watcher.tpsl_threshold {
+                    warned.remove(&key);
+                } else if warned.insert(key) {
+                    notifications.push(OrderNotification {
+                        chat_id: watcher.chat_id,
+                        order: order.clone(),
+                        event: OrderEvent::Approaching { mid_px, distance_pct },
+                    });
+                }
+            }
+        }
+
+        let mut tracked = self.trigger_orders.lock().await;
+        tracked.retain(|address, _| addresses.contains(address));
+        tracked.extend(trigger_orders);
+        Ok(notifications)
+    }
+
+    /// Turns order updates for `address` into notifications for every chat
+    /// watching it. Only tracked trigger orders are reported.
+    pub async fn handle_order_updates(&self, address: &str, updates: OrderUpdates) -> Result<Vec<OrderNotification>> {
+        let fills = if updates.data.iter().any(|update| update.status == "filled") {
+            self.fetch_fills(address).await.unwrap_or_else(|e| {
+                error!("Failed to fetch fills for {address}: {e}");
+                Vec::new()
+            })
+        } else {
+            Vec::new()
+        };
+
+        let mut events = Vec::new();
+        {
+            let mut tracked = self.trigger_orders.lock().await;
+            let Some(orders) = tracked.get_mut(address) else {
+                return Ok(Vec::new());
+            };
+            for update in updates.data {
+                let oid = update.order.oid;
+                let Some(event) = OrderEvent::from_update(&update, &fills) else {
+                    continue;
+                };
+                let order = if matches!(event, OrderEvent::Triggered) {
+                    orders.get(&oid).cloned()
+                } else {
+                    orders.remove(&oid)
+                };
+                if let Some(order) = order {
+                    events.push((order, event));
+                }
+            }
+        }
+
+        let watchers = self.db.get_watchers_for_address(address).await?;
+        Ok(events
+            .into_iter()
+            .flat_map(|(order, event)| {
+                watchers.iter().map(move |watcher| OrderNotification {
+                    chat_id: watcher.chat_id,
+                    order: order.clone(),
+                    event: event.clone(),
+                })
+            })
+            .collect())
+    }
+
+    async fn fetch_fills(&self, address: &str) -> anyhow::Result<Vec<UserFillsResponse>> {
+        let user = address.parse::<H160>()?;
+        let fills = self.info_client.lock().await.user_fills(user).await?;
+        Ok(fills)
+    }
+
+    async fn fetch_trigger_orders(&self, address: &str) -> anyhow::Result<HashMap<u64, TriggerOrder>> {
+        let user = address.parse::<H160>()?;
+        let info_client = self.info_client.lock().await;
+        let open_oids: HashSet<u64> = info_client.open_orders(user).await?.into_iter().map(|order| order.oid).collect();
+        let historical_orders = info_client.historical_orders(user).await?;
+
+        let mut orders = HashMap::new();
+        for order_info in historical_orders {
+            let order = order_info.order;
+            if !order.is_trigger || !open_oids.contains(&order.oid) {
+                continue;
+            }
+            let (Some(kind), Ok(trigger_px)) = (TriggerKind::from_order_type(&order.order_type), order.trigger_px.parse::<f64>()) else {
+                continue;
+            };
+            orders.insert(
+                order.oid,
+                TriggerOrder {
+                    address: address.to_string(),
+                    oid: order.oid,
+                    coin: order.coin,
+                    side: order.side,
+                    sz: order.sz,
+                    trigger_px,
+                    kind,
+                },
+            );
+        }
+        Ok(orders)
+    }
+
+    async fn sync_subscriptions(&self, addresses: &HashSet<String>) {
+        let mut clients = self.clients.lock().await;
+        clients.retain(|address, _| addresses.contains(address));
+        for address in addresses {
+            if clients.contains_key(address) {
+                continue;
+            }
+            match self.subscribe(address).await {
+                Ok(client) => {
+                    clients.insert(address.clone(), client);
+                }
+                Err(e) => error!("Failed to subscribe to order updates for {address}: {e}"),
+            }
+        }
+    }
+
+    async fn subscribe(&self, address: &str) -> anyhow::Result<InfoClient> {
+        info!("Subscribing to order updates for {address}");
+        let user = address.parse::<H160>()?;
+        let mut client = InfoClient::new(None, Some(BaseUrl::Mainnet)).await?;
+        let (sender, mut receiver) = unbounded_channel();
+        client.subscribe(Subscription::OrderUpdates { user }, sender).await?;
+
+        let forward_to = self.sender.clone();
+        let address = address.to_string();
+        tokio::spawn(async move {
+            while let Some(message) = receiver.recv().await {
+                if forward_to.send((address.clone(), message)).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(client)
+    }
+}
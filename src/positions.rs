@@ -0,0 +1,155 @@
+use crate::db::{Database, WatchedAddress};
+use ethers::types::H160;
+use hyperliquid_rust_sdk::{InfoClient, UserStateResponse};
+use log::error;
+use rusqlite::Result;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use teloxide::types::ChatId;
+use tokio::sync::Mutex;
+
+pub const DEFAULT_LIQUIDATION_THRESHOLD: f64 = 10.0;
+pub const DEFAULT_TPSL_THRESHOLD: f64 = 1.0;
+
+#[derive(Debug, Clone)]
+pub struct LiquidationWarning {
+    pub chat_id: i64,
+    pub address: String,
+    pub coin: String,
+    pub szi: f64,
+    pub entry_px: Option<f64>,
+    pub liquidation_px: f64,
+    pub mark_px: f64,
+    pub distance_pct: f64,
+}
+
+impl std::fmt::Display for LiquidationWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let side = if self.szi > 0.0 { "long" } else { "short" };
+        write!(
+            f,
+            "⚠️ {} {} {} on {} is {:.2}% from liquidation (mark {}, liquidation {}, entry {})",
+            self.coin,
+            side,
+            self.szi.abs(),
+            self.address,
+            self.distance_pct,
+            self.mark_px,
+            self.liquidation_px,
+            self.entry_px.map(|px| px.to_string()).unwrap_or_else(|| "-".to_string())
+        )
+    }
+}
+
+#[derive(Clone)]
+pub struct PositionService {
+    db: Database,
+    info_client: Arc<Mutex<InfoClient>>,
+    // (chat_id, address, coin) of positions that have already been warned
+    // about, so a warning is only sent again after the position recovers.
+    warned: Arc<Mutex<HashSet<(i64, String, String)>>>,
+}
+
+impl PositionService {
+    pub fn new(db: Database, info_client: Arc<Mutex<InfoClient>>) -> Self {
+        Self {
+            db,
+            info_client,
+            warned: Arc::new(Mutex::new(HashSet::new())),
+        }
+    }
+
+    /// Normalises a user-supplied address to the lowercase `0x…` form it is
+    /// stored under.
+    pub fn parse_address(address: &str) -> anyhow::Result<String> {
+        let address = address
+            .parse::<H160>()
+            .map_err(|_| anyhow::anyhow!("Invalid address: {address}"))?;
+        Ok(format!("{address:?}"))
+    }
+
+    pub async fn watch_address(&self, chat_id: ChatId, address: &str, liquidation_threshold: f64, tpsl_threshold: f64) -> Result<()> {
+        self.db.upsert_watched_address(chat_id, address, liquidation_threshold, tpsl_threshold).await
+    }
+
+    /// Stops watching `address` for `chat_id`. Returns `false` if the chat was
+    /// not watching it.
+    pub async fn unwatch_address(&self, chat_id: ChatId, address: &str) -> Result<bool> {
+        Ok(self.db.delete_watched_address(chat_id, address).await? > 0)
+    }
+
+    pub async fn get_watched_addresses_for_chat(&self, chat_id: ChatId) -> Result<Vec<WatchedAddress>> {
+        self.db.get_watched_addresses_for_chat(chat_id).await
+    }
+
+    /// Fetches the positions of every watched address and returns a warning
+    /// for each position that has moved within its watcher's threshold of
+    /// liquidation since the last check.
+    pub async fn check_positions(&self) -> Result<Vec<LiquidationWarning>> {
+        let mut watchers_by_address: HashMap<String, Vec<WatchedAddress>> = HashMap::new();
+        for watched in self.db.get_all_watched_addresses().await? {
+            watchers_by_address.entry(watched.address.clone()).or_default().push(watched);
+        }
+
+        let mut user_states = Vec::with_capacity(watchers_by_address.len());
+        for (address, watchers) in watchers_by_address {
+            let Ok(user) = address.parse::<H160>() else {
+                continue;
+            };
+            let user_state = self.info_client.lock().await.user_state(user).await;
+            match user_state {
+                Ok(user_state) => user_states.push((address, watchers, user_state)),
+                Err(e) => error!("Failed to fetch user state for {address}: {e}"),
+            }
+        }
+
+        let mut warnings = Vec::new();
+        for (address, watchers, user_state) in user_states {
+            warnings.extend(self.warnings_for(&address, &watchers, &user_state).await);
+        }
+        Ok(warnings)
+    }
+
+    /// Compares the positions in `user_state` against each watcher's
+    /// threshold. A position is warned about once, and again only after it
+    /// has moved back beyond the threshold or been closed.
+    pub async fn warnings_for(&self, address: &str, watchers: &[WatchedAddress], user_state: &UserStateResponse) -> Vec<LiquidationWarning> {
+        let mut warnings = Vec::new();
+        let mut warned = self.warned.lock().await;
+        let mut open_coins = HashSet::new();
+        for asset_position in &user_state.asset_positions {
+            let position = &asset_position.position;
+            let Some(liquidation_px) = position.liquidation_px.as_deref().and_then(|px| px.parse::<f64>().ok()) else {
+                continue;
+            };
+            let szi = position.szi.parse::<f64>().unwrap_or(0.0);
+            let position_value = position.position_value.parse::<f64>().unwrap_or(0.0);
+            if szi == 0.0 || position_value == 0.0 {
+                continue;
+            }
+            open_coins.insert(position.coin.clone());
+
+            let mark_px = position_value / szi.abs();
+            let distance_pct = (mark_px - liquidation_px).abs() / mark_px * 100.0;
+            for watcher in watchers {
+                let key = (watcher.chat_id, address.to_string(), position.coin.clone());
+                if distance_pct > watcher.liquidation_threshold {
+                    warned.remove(&key);
+                } else if warned.insert(key) {
+                    warnings.push(LiquidationWarning {
+                        chat_id: watcher.chat_id,
+                        address: address.to_string(),
+                        coin: position.coin.clone(),
+                        szi,
+                        entry_px: position.entry_px.as_deref().and_then(|px| px.parse::<f64>().ok()),
+                        liquidation_px,
+                        mark_px,
+                        distance_pct,
+                    });
+                }
+            }
+        }
+        warned.retain(|(_, warned_address, coin)| warned_address != address || open_coins.contains(coin));
+        warnings
+    }
+}
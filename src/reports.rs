@@ -0,0 +1,352 @@
+use crate::db::{MarketType, ReportTemplate};
+use chrono::{DateTime, Duration, Utc};
+use ethers::types::H160;
+use hyperliquid_rust_sdk::{
+    CandlesSnapshotResponse, FundingHistoryResponse, InfoClient, MetaAndAssetCtxs, PerpAssetContext, SpotAssetContext,
+    SpotMetaAndAssetCtxs, UserFillsResponse, UserStateResponse, UserTokenBalanceResponse,
+};
+use std::collections::HashMap;
+use tokio::sync::Mutex;
+
+/// Most coins one cron alert may report on, since a full report makes a
+/// few requests per coin.
+pub const MAX_REPORT_COINS: usize = 10;
+/// Most fills `user_fills` returns, the most recent first.
+pub const USER_FILLS_LIMIT: usize = 2_000;
+
+/// Funding and open interest of a perp.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PerpStats {
+    /// Current hourly funding rate, as a fraction.
+    pub funding: f64,
+    /// Average hourly funding rate over the last 24h, as a fraction.
+    pub avg_funding: Option<f64>,
+    /// Open interest in the coin.
+    pub open_interest: f64,
+}
+
+/// The last 24h of one market, as shown in a report.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MarketStats {
+    pub coin: String,
+    pub price: f64,
+    pub prev_day_price: f64,
+    /// Notional volume in USD.
+    pub volume: f64,
+    pub high: Option<f64>,
+    pub low: Option<f64>,
+    /// `None` for spot markets.
+    pub perp: Option<PerpStats>,
+}
+
+fn number(value: &str) -> anyhow::Result<f64> {
+    value.parse().map_err(|_| anyhow::anyhow!("Invalid number in market data: {value}"))
+}
+
+impl MarketStats {
+    pub fn spot(coin: &str, ctx: &SpotAssetContext) -> anyhow::Result<Self> {
+        Ok(Self {
+            coin: coin.to_string(),
+            price: number(ctx.mid_px.as_deref().unwrap_or(&ctx.mark_px))?,
+            prev_day_price: number(&ctx.prev_day_px)?,
+            volume: number(&ctx.day_ntl_vlm)?,
+            high: None,
+            low: None,
+            perp: None,
+        })
+    }
+
+    pub fn perp(coin: &str, ctx: &PerpAssetContext) -> anyhow::Result<Self> {
+        Ok(Self {
+            coin: coin.to_string(),
+            price: number(ctx.mid_px.as_deref().unwrap_or(&ctx.mark_px))?,
+            prev_day_price: number(&ctx.prev_day_px)?,
+            volume: number(&ctx.day_ntl_vlm)?,
+            high: None,
+            low: None,
+            perp: Some(PerpStats {
+                funding: number(&ctx.funding)?,
+                avg_funding: None,
+                open_interest: number(&ctx.open_interest)?,
+            }),
+        })
+    }
+
+    /// Sets the 24h high and low from hourly candles.
+    pub fn with_candles(mut self, candles: &[CandlesSnapshotResponse]) -> anyhow::Result<Self> {
+        for candle in candles {
+            let (high, low) = (number(&candle.high)?, number(&candle.low)?);
+            self.high = Some(self.high.map_or(high, |h| h.max(high)));
+            self.low = Some(self.low.map_or(low, |l| l.min(low)));
+        }
+        Ok(self)
+    }
+
+    /// Sets the average funding rate from the last 24h of funding payments.
+    pub fn with_funding_history(mut self, history: &[FundingHistoryResponse]) -> anyhow::Result<Self> {
+        if let Some(perp) = self.perp.as_mut().filter(|_| !history.is_empty()) {
+            let rates = history.iter().map(|funding| number(&funding.funding_rate)).collect::<anyhow::Result<Vec<f64>>>()?;
+            perp.avg_funding = Some(rates.iter().sum::<f64>() / rates.len() as f64);
+        }
+        Ok(self)
+    }
+
+    /// Change from the price 24h ago, in percent.
+    pub fn change_pct(&self) -> Option<f64> {
+        (self.prev_day_price > 0.0).then(|| (self.price - self.prev_day_price) / self.prev_day_price * 100.0)
+    }
+}
+
+/// Formats a USD amount with a K, M or B suffix.
+fn usd(value: f64) -> String {
+    match value.abs() {
+        v if v >= 1e9 => format!("${:.2}B", value / 1e9),
+        v if v >= 1e6 => format!("${:.2}M", value / 1e6),
+        v if v >= 1e3 => format!("${:.2}K", value / 1e3),
+        _ => format!("${value:.2}"),
+    }
+}
+
+/// Formats a signed USD amount, e.g. `+$1.20K`.
+fn signed_usd(value: f64) -> String {
+    let sign = if value < 0.0 { "-" } else { "+" };
+    format!("{sign}{}", usd(value.abs()))
+}
+
+/// What a cron alert sends: its coins laid out by its template.
+#[derive(Debug, Clone)]
+pub struct MarketReport {
+    pub template: ReportTemplate,
+    pub stats: Vec<MarketStats>,
+}
+
+impl std::fmt::Display for MarketReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.template == ReportTemplate::Price {
+            let lines: Vec<String> = self.stats.iter().map(|stats| format!("⏰ {}: {}", stats.coin, stats.price)).collect();
+            return write!(f, "{}", lines.join("\n"));
+        }
+        write!(f, "📊 Market report")?;
+        for stats in &self.stats {
+            write!(f, "\n{} {}", stats.coin, stats.price)?;
+            if let Some(change_pct) = stats.change_pct() {
+                write!(f, " ({change_pct:+.2}% 24h)")?;
+            }
+            if self.template != ReportTemplate::Full {
+                continue;
+            }
+            write!(f, "\n  ")?;
+            if let (Some(high), Some(low)) = (stats.high, stats.low) {
+                write!(f, "24h high {high}, low {low}, ")?;
+            }
+            write!(f, "volume {}", usd(stats.volume))?;
+            if let Some(perp) = &stats.perp {
+                write!(f, "\n  funding {:.4}%/h", perp.funding * 100.0)?;
+                if let Some(avg_funding) = perp.avg_funding {
+                    write!(f, " (24h avg {:.4}%/h)", avg_funding * 100.0)?;
+                }
+                write!(f, ", OI {}", usd(perp.open_interest * stats.price))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Fetches the current context of every perp, by name.
+pub async fn perp_asset_contexts(info_client: &Mutex<InfoClient>) -> anyhow::Result<HashMap<String, PerpAssetContext>> {
+    let (mut names, mut ctxs) = (Vec::new(), Vec::new());
+    let responses = info_client.lock().await.meta_and_asset_contexts().await?;
+    for response in responses {
+        match response {
+            MetaAndAssetCtxs::Meta(meta) => names = meta.universe.into_iter().map(|asset| asset.name).collect(),
+            MetaAndAssetCtxs::Context(context) => ctxs = context,
+        }
+    }
+    Ok(names.into_iter().zip(ctxs).collect())
+}
+
+/// Fetches the stats of `markets`, given as (coin, market, token), for a
+/// report. Only full reports fetch candles and funding history. The client
+/// is locked for one request at a time, so a long report doesn't hold up
+/// other users of it.
+pub async fn market_stats(
+    info_client: &Mutex<InfoClient>,
+    markets: &[(String, MarketType, String)],
+    template: ReportTemplate,
+) -> anyhow::Result<Vec<MarketStats>> {
+    let mut spot_ctxs: HashMap<String, SpotAssetContext> = HashMap::new();
+    if markets.iter().any(|(_, market, _)| *market == MarketType::Spot) {
+        let responses = info_client.lock().await.spot_meta_and_asset_contexts().await?;
+        for response in responses {
+            if let SpotMetaAndAssetCtxs::Context(ctxs) = response {
+                spot_ctxs.extend(ctxs.into_iter().map(|ctx| (ctx.coin.clone(), ctx)));
+            }
+        }
+    }
+    let mut perp_ctxs: HashMap<String, PerpAssetContext> = HashMap::new();
+    if markets.iter().any(|(_, market, _)| *market == MarketType::Perp) {
+        perp_ctxs = perp_asset_contexts(info_client).await?;
+    }
+
+    let end = Utc::now();
+    let start = (end - Duration::hours(24)).timestamp_millis() as u64;
+    let mut stats = Vec::with_capacity(markets.len());
+    for (coin, market, token) in markets {
+        let no_data = || anyhow::anyhow!("No market data for {coin}");
+        let mut coin_stats = match market {
+            MarketType::Spot => MarketStats::spot(coin, spot_ctxs.get(token).ok_or_else(no_data)?)?,
+            MarketType::Perp => MarketStats::perp(coin, perp_ctxs.get(token).ok_or_else(no_data)?)?,
+        };
+        if template == ReportTemplate::Full {
+            let candles = info_client
+                .lock()
+                .await
+                .candles_snapshot(token.clone(), "1h".to_string(), start, end.timestamp_millis() as u64)
+                .await?;
+            coin_stats = coin_stats.with_candles(&candles)?;
+            if *market == MarketType::Perp {
+                let history = info_client.lock().await.funding_history(token.clone(), start, None).await?;
+                coin_stats = coin_stats.with_funding_history(&history)?;
+            }
+        }
+        stats.push(coin_stats);
+    }
+    Ok(stats)
+}
+
+/// One open perp position of a portfolio.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PositionSummary {
+    pub coin: String,
+    /// Negative for shorts.
+    pub size: f64,
+    pub entry_price: Option<f64>,
+    pub unrealized_pnl: f64,
+    /// Return on equity, as a fraction.
+    pub return_on_equity: f64,
+}
+
+/// An account's value, margin, positions and spot balances, and its
+/// realised PnL and fees since a point in the day.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PortfolioReport {
+    pub address: String,
+    pub account_value: f64,
+    pub margin_used: f64,
+    pub withdrawable: f64,
+    pub positions: Vec<PositionSummary>,
+    /// Non-zero spot balances, by token.
+    pub balances: Vec<(String, f64)>,
+    pub realized_pnl: f64,
+    pub fees: f64,
+    pub fills: usize,
+    /// Whether `user_fills` hit its limit before reaching `since`, so older
+    /// fills of the day are missing from the PnL and fees.
+    pub truncated: bool,
+}
+
+impl PortfolioReport {
+    /// Summarises `address` from its perp state, spot balances and the
+    /// fills made at or after `since`.
+    pub fn new(
+        address: &str,
+        state: &UserStateResponse,
+        balances: &UserTokenBalanceResponse,
+        fills: &[UserFillsResponse],
+        since: DateTime<Utc>,
+    ) -> anyhow::Result<Self> {
+        let positions = state
+            .asset_positions
+            .iter()
+            .map(|asset_position| {
+                let position = &asset_position.position;
+                Ok(PositionSummary {
+                    coin: position.coin.clone(),
+                    size: number(&position.szi)?,
+                    entry_price: position.entry_px.as_deref().map(number).transpose()?,
+                    unrealized_pnl: number(&position.unrealized_pnl)?,
+                    return_on_equity: number(&position.return_on_equity)?,
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let mut spot = Vec::new();
+        for balance in &balances.balances {
+            let total = number(&balance.total)?;
+            if total != 0.0 {
+                spot.push((balance.coin.clone(), total));
+            }
+        }
+        let since = since.timestamp_millis() as u64;
+        let todays_fills: Vec<&UserFillsResponse> = fills.iter().filter(|fill| fill.time >= since).collect();
+        let mut realized_pnl = 0.0;
+        let mut fees = 0.0;
+        for fill in &todays_fills {
+            realized_pnl += number(&fill.closed_pnl)?;
+            fees += number(&fill.fee)?;
+        }
+        Ok(Self {
+            address: address.to_string(),
+            account_value: number(&state.margin_summary.account_value)?,
+            margin_used: number(&state.margin_summary.total_margin_used)?,
+            withdrawable: number(&state.withdrawable)?,
+            positions,
+            balances: spot,
+            realized_pnl,
+            fees,
+            fills: todays_fills.len(),
+            truncated: fills.len() >= USER_FILLS_LIMIT && fills.iter().all(|fill| fill.time > since),
+        })
+    }
+}
+
+impl std::fmt::Display for PortfolioReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "💼 Portfolio {}", self.address)?;
+        write!(f, "Account value {}, margin used {}", usd(self.account_value), usd(self.margin_used))?;
+        if self.account_value > 0.0 {
+            write!(f, " ({:.1}%)", self.margin_used / self.account_value * 100.0)?;
+        }
+        write!(f, ", withdrawable {}", usd(self.withdrawable))?;
+        if !self.positions.is_empty() {
+            write!(f, "\nPositions:")?;
+        }
+        for position in &self.positions {
+            let side = if position.size < 0.0 { "short" } else { "long" };
+            write!(f, "\n- {} {side} {}", position.coin, position.size.abs())?;
+            if let Some(entry_price) = position.entry_price {
+                write!(f, " @ {entry_price}")?;
+            }
+            write!(
+                f,
+                ": PnL {} (ROE {:+.2}%)",
+                signed_usd(position.unrealized_pnl),
+                position.return_on_equity * 100.0
+            )?;
+        }
+        if !self.balances.is_empty() {
+            let balances: Vec<String> = self.balances.iter().map(|(coin, total)| format!("{coin} {total}")).collect();
+            write!(f, "\nSpot: {}", balances.join(", "))?;
+        }
+        write!(
+            f,
+            "\nToday: realised PnL {}, fees {} over {} fills",
+            signed_usd(self.realized_pnl),
+            usd(self.fees),
+            self.fills
+        )?;
+        if self.truncated {
+            write!(f, " (only the latest {USER_FILLS_LIMIT} fills are available, earlier ones today are not counted)")?;
+        }
+        Ok(())
+    }
+}
+
+/// Fetches the account of `address` for a portfolio report, counting fills
+/// from `since`. The client is locked for one request at a time.
+pub async fn portfolio_report(info_client: &Mutex<InfoClient>, address: &str, since: DateTime<Utc>) -> anyhow::Result<PortfolioReport> {
+    let user = address.parse::<H160>().map_err(|_| anyhow::anyhow!("Invalid address: {address}"))?;
+    let state = info_client.lock().await.user_state(user).await?;
+    let balances = info_client.lock().await.user_token_balances(user).await?;
+    let fills = info_client.lock().await.user_fills(user).await?;
+    PortfolioReport::new(address, &state, &balances, &fills, since)
+}
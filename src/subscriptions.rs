@@ -0,0 +1,128 @@
+use crate::indicators::Interval;
+use hyperliquid_rust_sdk::{InfoClient, Message, Subscription};
+use log::info;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tokio::sync::mpsc::UnboundedSender;
+
+struct TokenSubscription {
+    subscription_id: u32,
+    ref_count: usize,
+}
+
+/// The websocket feed an alert reads from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Feed {
+    /// `ActiveAssetCtx`: mark price, funding, open interest.
+    AssetCtx,
+    /// `Bbo`: best bid and offer.
+    Bbo,
+    /// `L2Book`: aggregated order book levels.
+    L2Book,
+    /// `Trades`: every trade on the token.
+    Trades,
+    /// `Candle`: the current candle of one interval.
+    Candle(Interval),
+}
+
+impl Feed {
+    fn subscription(&self, token: &str) -> Subscription {
+        let coin = token.to_string();
+        match self {
+            Feed::AssetCtx => Subscription::ActiveAssetCtx { coin },
+            Feed::Bbo => Subscription::Bbo { coin },
+            Feed::L2Book => Subscription::L2Book { coin },
+            Feed::Trades => Subscription::Trades { coin },
+            Feed::Candle(interval) => Subscription::Candle {
+                coin,
+                interval: interval.as_str().to_string(),
+            },
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Feed::AssetCtx => "price updates",
+            Feed::Bbo => "best bid/offer",
+            Feed::L2Book => "order book",
+            Feed::Trades => "trades",
+            Feed::Candle(_) => "candles",
+        }
+    }
+}
+
+/// Keeps one subscription per feed and token alive for as long as at least
+/// one alert is watching it.
+#[derive(Clone)]
+pub struct SubscriptionManager {
+    info_client: Arc<Mutex<InfoClient>>,
+    sender: UnboundedSender<Message>,
+    subscriptions: Arc<Mutex<HashMap<(Feed, String), TokenSubscription>>>,
+}
+
+impl SubscriptionManager {
+    pub fn new(info_client: InfoClient, sender: UnboundedSender<Message>) -> Self {
+        Self {
+            info_client: Arc::new(Mutex::new(info_client)),
+            sender,
+            subscriptions: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Registers interest in `feed` for `token`, subscribing on the first
+    /// reference.
+    pub async fn acquire(&self, feed: Feed, token: &str) -> anyhow::Result<()> {
+        let key = (feed, token.to_string());
+        let mut subscriptions = self.subscriptions.lock().await;
+        if let Some(subscription) = subscriptions.get_mut(&key) {
+            subscription.ref_count += 1;
+            return Ok(());
+        }
+
+        info!("Subscribing to {} for {token}", feed.as_str());
+        let subscription_id = self
+            .info_client
+            .lock()
+            .await
+            .subscribe(feed.subscription(token), self.sender.clone())
+            .await?;
+        subscriptions.insert(
+            key,
+            TokenSubscription {
+                subscription_id,
+                ref_count: 1,
+            },
+        );
+        Ok(())
+    }
+
+    /// Drops one reference to `feed` for `token`, unsubscribing once nothing
+    /// watches it.
+    pub async fn release(&self, feed: Feed, token: &str) -> anyhow::Result<()> {
+        let key = (feed, token.to_string());
+        let mut subscriptions = self.subscriptions.lock().await;
+        let Some(subscription) = subscriptions.get_mut(&key) else {
+            return Ok(());
+        };
+        subscription.ref_count -= 1;
+        if subscription.ref_count > 0 {
+            return Ok(());
+        }
+
+        info!("Unsubscribing from {} for {token}", feed.as_str());
+        let subscription_id = subscription.subscription_id;
+        subscriptions.remove(&key);
+        self.info_client.lock().await.unsubscribe(subscription_id).await?;
+        Ok(())
+    }
+
+    pub async fn unsubscribe_all(&self) -> anyhow::Result<()> {
+        let mut subscriptions = self.subscriptions.lock().await;
+        let mut info_client = self.info_client.lock().await;
+        for (_, subscription) in subscriptions.drain() {
+            info_client.unsubscribe(subscription.subscription_id).await?;
+        }
+        Ok(())
+    }
+}
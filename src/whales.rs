@@ -0,0 +1,214 @@
+use crate::assets::AssetRegistry;
+use crate::db::{Database, WhaleAlert};
+use crate::subscriptions::{Feed, SubscriptionManager};
+use chrono::Utc;
+use hyperliquid_rust_sdk::{Message, Trade};
+use log::error;
+use rusqlite::Result;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use teloxide::types::ChatId;
+use tokio::sync::Mutex;
+
+/// Longest burst window a chat can ask for.
+pub const MAX_BURST_SECONDS: i64 = 300;
+/// Trades older than this are skipped, so the recent trades replayed on
+/// subscribe don't fire alerts.
+const STALE_TRADE_SECS: i64 = 60;
+/// At most this many trades are listed in a burst notification.
+const MAX_LISTED_TRADES: usize = 5;
+
+/// A trade from the trades stream with its numbers parsed.
+#[derive(Debug, Clone)]
+pub struct WhaleTrade {
+    /// The token as named by the feed, e.g. `@107`.
+    pub token: String,
+    pub side: String,
+    pub px: f64,
+    pub sz: f64,
+    pub time: u64,
+    pub buyer: String,
+    pub seller: String,
+}
+
+impl WhaleTrade {
+    fn parse(trade: &Trade) -> Option<Self> {
+        Some(Self {
+            token: trade.coin.clone(),
+            side: trade.side.clone(),
+            px: trade.px.parse().ok()?,
+            sz: trade.sz.parse().ok()?,
+            time: trade.time,
+            buyer: trade.users.0.clone(),
+            seller: trade.users.1.clone(),
+        })
+    }
+
+    pub fn notional(&self) -> f64 {
+        self.px * self.sz
+    }
+
+    fn side_name(&self) -> &'static str {
+        if self.side == "B" { "BUY" } else { "SELL" }
+    }
+}
+
+impl std::fmt::Display for WhaleTrade {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} @ {} (${:.0}) buyer {} seller {}",
+            self.side_name(),
+            self.sz,
+            self.px,
+            self.notional(),
+            self.buyer,
+            self.seller
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct WhaleNotification {
+    pub chat_id: i64,
+    pub coin: String,
+    /// The single large trade, or every trade of the burst.
+    pub trades: Vec<WhaleTrade>,
+}
+
+impl std::fmt::Display for WhaleNotification {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let [trade] = self.trades.as_slice() else {
+            let notional: f64 = self.trades.iter().map(WhaleTrade::notional).sum();
+            let size: f64 = self.trades.iter().map(|trade| trade.sz).sum();
+            let side = self.trades.first().map(WhaleTrade::side_name).unwrap_or_default();
+            write!(
+                f,
+                "🐋 Burst of {} {side} trades on {}: {size} for ${notional:.0}",
+                self.trades.len(),
+                self.coin
+            )?;
+            for trade in self.trades.iter().rev().take(MAX_LISTED_TRADES) {
+                write!(f, "\n- {trade}")?;
+            }
+            return Ok(());
+        };
+        write!(f, "🐋 Whale trade on {}: {trade}", self.coin)
+    }
+}
+
+/// Alerts chats about large trades on the tokens they set a whale threshold
+/// for. Trades arrive through one `Trades` subscription per token.
+#[derive(Clone)]
+pub struct WhaleService {
+    db: Database,
+    assets: AssetRegistry,
+    subscriptions: SubscriptionManager,
+    // Recent trades per token, kept for burst detection.
+    recent_trades: Arc<Mutex<HashMap<String, VecDeque<WhaleTrade>>>>,
+    // (whale alert id, side) -> time of the last trade in a reported burst,
+    // so one burst is only reported once.
+    reported_bursts: Arc<Mutex<HashMap<(i64, String), u64>>>,
+}
+
+impl WhaleService {
+    pub fn new(db: Database, assets: AssetRegistry, subscriptions: SubscriptionManager) -> Self {
+        Self {
+            db,
+            assets,
+            subscriptions,
+            recent_trades: Arc::new(Mutex::new(HashMap::new())),
+            reported_bursts: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Sets the whale threshold of `chat_id` on `coin`, replacing any
+    /// previous one.
+    pub async fn set_whale_alert(&self, chat_id: ChatId, coin: &str, min_notional: f64, burst_seconds: Option<i64>) -> anyhow::Result<()> {
+        let (market, token) = self.assets.resolve(coin).await?;
+        let created = self.db.upsert_whale_alert(chat_id, coin, &token, market, min_notional, burst_seconds).await?;
+        if created && let Err(e) = self.subscriptions.acquire(Feed::Trades, &token).await {
+            error!("Failed to subscribe to trades for {token}: {e}");
+        }
+        Ok(())
+    }
+
+    /// Deletes a whale alert owned by `chat_id`. Returns `false` if the chat
+    /// has no whale alert with that id.
+    pub async fn delete_whale_alert(&self, alert_id: i64, chat_id: ChatId) -> Result<bool> {
+        let Some(alert) = self.db.get_whale_alert_for_chat(alert_id, chat_id).await? else {
+            return Ok(false);
+        };
+        self.db.delete_whale_alert(alert_id, chat_id).await?;
+        if let Err(e) = self.subscriptions.release(Feed::Trades, &alert.token).await {
+            error!("Failed to unsubscribe from trades for {}: {e}", alert.token);
+        }
+        Ok(true)
+    }
+
+    pub async fn get_whale_alerts_for_chat(&self, chat_id: ChatId) -> Result<Vec<WhaleAlert>> {
+        self.db.get_whale_alerts_for_chat(chat_id).await
+    }
+
+    /// Subscribes to trades for every stored whale alert.
+    pub async fn subscribe_existing_alerts(&self) -> anyhow::Result<()> {
+        for alert in self.db.get_all_whale_alerts().await? {
+            self.subscriptions.acquire(Feed::Trades, &alert.token).await?;
+        }
+        Ok(())
+    }
+
+    /// Turns a batch of trades into notifications for every chat whose
+    /// threshold a single trade, or a burst of same-side trades, exceeds.
+    pub async fn handle_message(&self, message: Message) -> Result<Vec<WhaleNotification>> {
+        let Message::Trades(trades) = message else {
+            return Ok(Vec::new());
+        };
+        let oldest = (Utc::now().timestamp() - STALE_TRADE_SECS) as u64 * 1000;
+        let mut notifications = Vec::new();
+        let mut recent_trades = self.recent_trades.lock().await;
+        let mut reported_bursts = self.reported_bursts.lock().await;
+        for trade in &trades.data {
+            let Some(trade) = WhaleTrade::parse(trade).filter(|trade| trade.time >= oldest) else {
+                continue;
+            };
+            let alerts = self.db.get_whale_alerts_for_token(&trade.token).await?;
+            let recent = recent_trades.entry(trade.token.clone()).or_default();
+            recent.push_back(trade.clone());
+            while recent.front().is_some_and(|old| old.time + (MAX_BURST_SECONDS as u64) * 1000 < trade.time) {
+                recent.pop_front();
+            }
+
+            for alert in alerts {
+                if trade.notional() >= alert.min_notional {
+                    notifications.push(WhaleNotification {
+                        chat_id: alert.chat_id,
+                        coin: alert.coin.clone(),
+                        trades: vec![trade.clone()],
+                    });
+                    continue;
+                }
+                let Some(burst_seconds) = alert.burst_seconds else {
+                    continue;
+                };
+                let key = (alert.id, trade.side.clone());
+                let since = trade.time.saturating_sub(burst_seconds as u64 * 1000);
+                let since = reported_bursts.get(&key).map_or(since, |reported| since.max(reported + 1));
+                let burst: Vec<WhaleTrade> = recent
+                    .iter()
+                    .filter(|recent| recent.side == trade.side && recent.time >= since && recent.notional() < alert.min_notional)
+                    .cloned()
+                    .collect();
+                if burst.len() > 1 && burst.iter().map(WhaleTrade::notional).sum::<f64>() >= alert.min_notional {
+                    reported_bursts.insert(key, trade.time);
+                    notifications.push(WhaleNotification {
+                        chat_id: alert.chat_id,
+                        coin: alert.coin.clone(),
+                        trades: burst,
+                    });
+                }
+            }
+        }
+        Ok(notifications)
+    }
+}
@@ -0,0 +1,39 @@
+mod common;
+
+use backend::events::{AccountEventKind, AccountEventService};
+use hyperliquid_rust_sdk::{BaseUrl, InfoClient, Message};
+use teloxide::types::ChatId;
+use tokio::sync::mpsc::unbounded_channel;
+
+const ADDRESS: &str = "0x0000000000000000000000000000000000000001";
+
+fn deposit(is_snapshot: bool) -> Message {
+    serde_json::from_value(serde_json::json!({
+        "channel": "userNonFundingLedgerUpdates",
+        "data": {
+            "isSnapshot": is_snapshot,
+            "user": ADDRESS,
+            "nonFundingLedgerUpdates": [
+                {"time": 1, "hash": "0x00", "delta": {"type": "deposit", "usdc": "100.0"}}
+            ]
+        }
+    }))
+    .unwrap()
+}
+
+#[tokio::test]
+async fn ledger_updates_reach_chats_that_enabled_them() {
+    let (db, _) = common::setup().await;
+    let (sender, _receiver) = unbounded_channel();
+    let service = AccountEventService::new(db.clone(), InfoClient::new(None, Some(BaseUrl::Mainnet)).await.unwrap(), sender);
+    db.upsert_watched_address(ChatId(1), ADDRESS, 10.0, 1.0).await.unwrap();
+    db.upsert_watched_address(ChatId(2), ADDRESS, 10.0, 1.0).await.unwrap();
+    service.set_enabled_events(ChatId(2), &[AccountEventKind::Fill]).await.unwrap();
+
+    assert!(service.handle_message(deposit(true)).await.unwrap().is_empty());
+
+    let notifications = service.handle_message(deposit(false)).await.unwrap();
+    assert_eq!(notifications.len(), 1);
+    assert_eq!(notifications[0].chat_id, 1);
+    assert_eq!(notifications[0].event.kind, AccountEventKind::Deposit);
+}
@@ -0,0 +1,152 @@
+mod common;
+
+use backend::alerts::AlertService;
+use backend::db::{ActiveWindow, AlertDirection, AlertKind, AlertTable, AlertTarget, MarketType, RearmMode};
+use chrono::{TimeZone, Utc};
+use chrono_tz::Tz;
+use common::{alert, setup};
+use teloxide::types::ChatId;
+
+#[tokio::test]
+async fn chats_can_only_change_their_own_alerts() {
+    let (db, alert_service) = setup().await;
+    db.insert_alert(&alert(ChatId(1), "HYPE", "@107", MarketType::Spot, 40.0, AlertDirection::Cross)).await.unwrap();
+    let id = db.get_all_alerts().await.unwrap()[0].id;
+
+    assert!(alert_service.edit_alert(id, ChatId(2), AlertTarget::price(AlertDirection::Above, 45.0)).await.unwrap().is_none());
+    assert!(!alert_service.delete_alert(id, ChatId(2)).await.unwrap());
+    assert_eq!(alert_service.clear_alerts(ChatId(2)).await.unwrap(), 0);
+
+    assert!(alert_service.edit_alert(id, ChatId(1), AlertTarget::price(AlertDirection::Above, 45.0)).await.unwrap().is_some());
+    let edited = db.get_alert_for_chat(id, ChatId(1)).await.unwrap().unwrap();
+    assert_eq!((edited.price, edited.direction), (45.0, AlertDirection::Above));
+
+    assert!(alert_service.delete_alert(id, ChatId(1)).await.unwrap());
+    assert!(db.get_all_alerts().await.unwrap().is_empty());
+}
+
+#[test]
+fn rearm_modes_parse_and_display() {
+    assert_eq!(AlertService::parse_rearm_mode("once").unwrap(), RearmMode::OneShot);
+    assert_eq!(AlertService::parse_rearm_mode("cooldown 15m").unwrap(), RearmMode::Cooldown(900));
+    assert_eq!(AlertService::parse_rearm_mode("band 1.5%").unwrap(), RearmMode::Band(1.5));
+    assert_eq!(RearmMode::Cooldown(7200).to_string(), "cooldown 2h");
+    assert_eq!(RearmMode::Cooldown(90).to_string(), "cooldown 90s");
+
+    assert!(AlertService::parse_rearm_mode("cooldown 0m").is_err());
+    assert!(AlertService::parse_rearm_mode("cooldown 15").is_err());
+    assert!(AlertService::parse_rearm_mode("band 1").is_err());
+    assert!(AlertService::parse_rearm_mode("once more").is_err());
+}
+
+#[test]
+fn trailing_targets_need_a_direction() {
+    let target = AlertService::parse_target("trail-10%").unwrap();
+    assert_eq!((target.kind, target.direction, target.change_pct), (AlertKind::Trailing, AlertDirection::Below, Some(10.0)));
+    assert_eq!(AlertTarget { price: 50.0, ..target }.to_string(), "drops 10% from its high of $50.00");
+    let target = AlertService::parse_target("trail+5%").unwrap();
+    assert_eq!(AlertTarget { price: 30.0, ..target }.to_string(), "rises 5% from its low of $30.00");
+
+    assert!(AlertService::parse_target("trail10%").is_err());
+    assert!(AlertService::parse_target("trail-10").is_err());
+    assert!(AlertService::parse_target("trail-10%/1h").is_err());
+}
+
+#[test]
+fn active_windows_parse_display_and_contain() {
+    let window: ActiveWindow = "22:00-06:00 weekdays".parse().unwrap();
+    assert_eq!(window.to_string(), "22:00-06:00 weekdays");
+    // 2026-10-16 is a Friday and 2026-10-17 a Saturday.
+    assert!(window.contains(Utc.with_ymd_and_hms(2026, 10, 16, 23, 30, 0).unwrap()));
+    assert!(window.contains(Utc.with_ymd_and_hms(2026, 10, 16, 5, 59, 0).unwrap()));
+    assert!(!window.contains(Utc.with_ymd_and_hms(2026, 10, 16, 12, 0, 0).unwrap()));
+    assert!(!window.contains(Utc.with_ymd_and_hms(2026, 10, 17, 23, 30, 0).unwrap()));
+
+    assert_eq!("sat,sun".parse::<ActiveWindow>().unwrap().to_string(), "weekends");
+    assert_eq!("mon,wed".parse::<ActiveWindow>().unwrap().to_string(), "mon,wed");
+    assert!("25:00-06:00".parse::<ActiveWindow>().is_err());
+    assert!("weekdays weekends".parse::<ActiveWindow>().is_err());
+    assert!("".parse::<ActiveWindow>().is_err());
+
+    assert!(AlertService::parse_expiry("never", Tz::UTC).unwrap().is_none());
+    assert!(AlertService::parse_expiry("7d", Tz::UTC).unwrap().unwrap() > Utc::now());
+    assert_eq!(AlertService::parse_expiry("2099-12-31 18:00", Tz::UTC).unwrap(), Some(Utc.with_ymd_and_hms(2099, 12, 31, 18, 0, 0).unwrap()));
+    assert!(AlertService::parse_expiry("2020-01-01", Tz::UTC).is_err());
+    assert!(AlertService::parse_expiry("soon", Tz::UTC).is_err());
+}
+
+#[test]
+fn windows_and_expiry_dates_are_in_local_time() {
+    let window: ActiveWindow = "09:00-17:00 weekdays America/New_York".parse().unwrap();
+    assert_eq!(window.timezone, Tz::America__New_York);
+    assert_eq!(window.to_string(), "09:00-17:00 weekdays America/New_York");
+    assert_eq!(window.to_string().parse::<ActiveWindow>().unwrap(), window);
+    // 09:30 in New York is 13:30 UTC on Friday 2026-10-16 (EDT).
+    assert!(window.contains(Utc.with_ymd_and_hms(2026, 10, 16, 13, 30, 0).unwrap()));
+    assert!(!window.contains(Utc.with_ymd_and_hms(2026, 10, 16, 9, 30, 0).unwrap()));
+    // 20:00 on Friday in New York is already Saturday in UTC.
+    assert!(!window.contains(Utc.with_ymd_and_hms(2026, 10, 17, 0, 0, 0).unwrap()));
+    assert_eq!("weekends UTC".parse::<ActiveWindow>().unwrap().timezone, Tz::UTC);
+
+    assert_eq!(
+        AlertService::parse_expiry("2099-07-01 18:00", Tz::Europe__London).unwrap(),
+        Some(Utc.with_ymd_and_hms(2099, 7, 1, 17, 0, 0).unwrap())
+    );
+    assert_eq!(
+        AlertService::parse_expiry("2099-12-01", Tz::Europe__London).unwrap(),
+        Some(Utc.with_ymd_and_hms(2099, 12, 1, 0, 0, 0).unwrap())
+    );
+    // Clocks in London skip from 01:00 to 02:00 on 2099-03-29.
+    assert!(AlertService::parse_expiry("2099-03-29 01:30", Tz::Europe__London).is_err());
+}
+
+#[tokio::test]
+async fn expired_alerts_are_deactivated_until_given_a_new_expiry() {
+    let (db, alert_service) = setup().await;
+    db.insert_alert(&alert(ChatId(1), "HYPE", "@107", MarketType::Spot, 40.0, AlertDirection::Cross)).await.unwrap();
+    db.insert_alert(&alert(ChatId(1), "HYPE", "@107", MarketType::Spot, 50.0, AlertDirection::Cross)).await.unwrap();
+    let ids: Vec<i64> = db.get_all_alerts().await.unwrap().iter().map(|alert| alert.id).collect();
+
+    assert!(!alert_service.set_expiry(ids[0], ChatId(2), Some(Utc::now())).await.unwrap());
+    assert!(alert_service.set_expiry(ids[0], ChatId(1), Some(Utc::now() - chrono::Duration::minutes(1))).await.unwrap());
+    assert!(alert_service.set_expiry(ids[1], ChatId(1), Some(Utc::now() + chrono::Duration::days(1))).await.unwrap());
+
+    let expired = alert_service.expire_alerts().await.unwrap();
+    assert_eq!(expired.iter().map(|alert| alert.id).collect::<Vec<_>>(), vec![ids[0]]);
+    let active = |alerts: Vec<AlertTable>| alerts.iter().map(|alert| (alert.id, alert.is_active)).collect::<Vec<_>>();
+    assert_eq!(active(db.get_all_alerts().await.unwrap()), vec![(ids[0], false), (ids[1], true)]);
+    assert!(alert_service.expire_alerts().await.unwrap().is_empty());
+
+    assert!(alert_service.set_expiry(ids[0], ChatId(1), None).await.unwrap());
+    assert_eq!(active(db.get_all_alerts().await.unwrap()), vec![(ids[0], true), (ids[1], true)]);
+}
+
+#[tokio::test]
+async fn only_price_alerts_can_rearm_on_a_band() {
+    let (db, alert_service) = setup().await;
+    db.insert_alert(&alert(ChatId(1), "HYPE", "@107", MarketType::Spot, 40.0, AlertDirection::Cross)).await.unwrap();
+    let id = db.get_all_alerts().await.unwrap()[0].id;
+
+    assert!(alert_service.set_rearm_mode(id, ChatId(1), RearmMode::Band(1.0)).await.unwrap());
+    assert!(alert_service.edit_alert(id, ChatId(1), AlertService::parse_target("+5%").unwrap()).await.is_err());
+    assert!(!alert_service.set_rearm_mode(id, ChatId(2), RearmMode::OneShot).await.unwrap());
+
+    let alert = db.get_alert_for_chat(id, ChatId(1)).await.unwrap().unwrap();
+    assert_eq!(alert.rearm, RearmMode::Band(1.0));
+    assert!(alert.to_string().contains("[re-arms 1% from target, armed]"));
+}
+
+#[test]
+fn change_targets_parse_into_their_kind() {
+    let target = AlertService::parse_target("+5%").unwrap();
+    assert_eq!((target.kind, target.direction, target.change_pct), (AlertKind::DayChange, AlertDirection::Above, Some(5.0)));
+
+    let target = AlertService::parse_target("-2.5%/4h").unwrap();
+    assert_eq!((target.kind, target.direction, target.window_minutes), (AlertKind::WindowChange, AlertDirection::Below, Some(240)));
+
+    let target = AlertService::parse_target("3%/set").unwrap();
+    assert_eq!((target.kind, target.direction), (AlertKind::FromSet, AlertDirection::Cross));
+
+    assert!(AlertService::parse_target("+5%/2d").is_err());
+    assert!(AlertService::parse_target("+0%").is_err());
+}
@@ -0,0 +1,60 @@
+mod common;
+
+use backend::{
+    assets::{AssetRegistry, UnknownCoin},
+    db::MarketType,
+};
+use common::spot_token;
+use hyperliquid_rust_sdk::{BaseUrl, InfoClient, Meta, SpotMeta};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+async fn registry() -> AssetRegistry {
+    let meta: Meta = serde_json::from_value(serde_json::json!({
+        "universe": [
+            {"name": "BTC", "szDecimals": 5},
+            {"name": "kPEPE", "szDecimals": 0},
+            {"name": "MATIC", "szDecimals": 1, "isDelisted": true},
+        ]
+    }))
+    .unwrap();
+    let spot_meta: SpotMeta = serde_json::from_value(serde_json::json!({
+        "universe": [
+            {"tokens": [1, 0], "name": "PURR/USDC", "index": 0, "isCanonical": true},
+            {"tokens": [150, 360], "name": "@5", "index": 5, "isCanonical": false},
+            {"tokens": [150, 0], "name": "@107", "index": 107, "isCanonical": false},
+        ],
+        "tokens": [spot_token("USDC", 0), spot_token("PURR", 1), spot_token("HYPE", 150), spot_token("USDH", 360)],
+    }))
+    .unwrap();
+    let registry = AssetRegistry::new(Arc::new(Mutex::new(InfoClient::new(None, Some(BaseUrl::Mainnet)).await.unwrap())));
+    registry.load(&meta, &spot_meta);
+    registry
+}
+
+#[tokio::test]
+async fn coins_resolve_by_name_pair_and_alias_in_any_case() {
+    let registry = registry().await;
+    let spot = |token: &str| Ok((MarketType::Spot, token.to_string()));
+    let perp = |token: &str| Ok((MarketType::Perp, token.to_string()));
+
+    assert_eq!(registry.lookup("HYPE"), spot("@107"));
+    assert_eq!(registry.lookup("hype/usdc"), spot("@107"));
+    assert_eq!(registry.lookup("@107"), spot("@107"));
+    assert_eq!(registry.lookup("HYPE/USDH"), spot("@5"));
+    assert_eq!(registry.lookup("purr"), spot("PURR/USDC"));
+    assert_eq!(registry.lookup("btc-perp"), perp("BTC"));
+    assert_eq!(registry.lookup("KPEPE-PERP"), perp("kPEPE"));
+}
+
+#[tokio::test]
+async fn unknown_and_delisted_coins_are_typed_errors() {
+    let registry = registry().await;
+    assert_eq!(registry.lookup("MATIC-PERP"), Err(UnknownCoin("MATIC-PERP".to_string())));
+    assert_eq!(registry.lookup("USDH"), Err(UnknownCoin("USDH".to_string())));
+
+    let unknown = registry.resolve("FOO").await.unwrap_err();
+    assert_eq!(unknown.to_string(), "Unknown coin: FOO (use e.g. HYPE, HYPE/USDC, @107 or BTC-PERP)");
+    let error: anyhow::Error = unknown.into();
+    assert!(error.downcast_ref::<UnknownCoin>().is_some());
+}
@@ -0,0 +1,141 @@
+#![allow(dead_code)]
+
+use backend::{
+    alerts::AlertService,
+    assets::AssetRegistry,
+    db::{AlertDirection, AlertTarget, Database, MarketType, NewAlert},
+    subscriptions::SubscriptionManager,
+};
+use hyperliquid_rust_sdk::{BaseUrl, InfoClient, Message, Meta, SpotMeta};
+use std::sync::Arc;
+use teloxide::types::ChatId;
+use tokio::sync::{mpsc::unbounded_channel, Mutex};
+
+pub async fn setup() -> (Database, AlertService) {
+    let db = Database::new(":memory:").unwrap();
+    db.initialize().await.unwrap();
+    let info_client = Arc::new(Mutex::new(InfoClient::new(None, Some(BaseUrl::Mainnet)).await.unwrap()));
+    let (sender, _receiver) = unbounded_channel();
+    let subscriptions = SubscriptionManager::new(InfoClient::new(None, Some(BaseUrl::Mainnet)).await.unwrap(), sender);
+    let alert_service = AlertService::new(db.clone(), info_client.clone(), subscriptions, AssetRegistry::new(info_client));
+    (db, alert_service)
+}
+
+/// Like `setup`, with an asset registry that knows the perps `coins`.
+pub async fn setup_with_perps(coins: &[&str]) -> (Database, AlertService) {
+    let db = Database::new(":memory:").unwrap();
+    db.initialize().await.unwrap();
+    let info_client = Arc::new(Mutex::new(InfoClient::new(None, Some(BaseUrl::Mainnet)).await.unwrap()));
+    let (sender, _receiver) = unbounded_channel();
+    let subscriptions = SubscriptionManager::new(InfoClient::new(None, Some(BaseUrl::Mainnet)).await.unwrap(), sender);
+    let universe: Vec<_> = coins.iter().map(|coin| serde_json::json!({"name": coin, "szDecimals": 2})).collect();
+    let meta: Meta = serde_json::from_value(serde_json::json!({"universe": universe})).unwrap();
+    let spot_meta: SpotMeta = serde_json::from_value(serde_json::json!({"universe": [], "tokens": []})).unwrap();
+    let assets = AssetRegistry::new(info_client.clone());
+    assets.load(&meta, &spot_meta);
+    let alert_service = AlertService::new(db.clone(), info_client, subscriptions, assets);
+    (db, alert_service)
+}
+
+pub fn alert(chat_id: ChatId, coin: &str, token: &str, market: MarketType, price: f64, direction: AlertDirection) -> NewAlert {
+    NewAlert {
+        public_key: "0x00".to_string(),
+        chat_id,
+        coin: coin.to_string(),
+        token: token.to_string(),
+        market,
+        target: AlertTarget::price(direction, price),
+    }
+}
+
+/// Like `alert`, with `target` in place of a plain price target.
+pub fn target_alert(chat_id: ChatId, coin: &str, token: &str, market: MarketType, target: AlertTarget) -> NewAlert {
+    NewAlert { target, ..alert(chat_id, coin, token, market, 0.0, AlertDirection::Above) }
+}
+
+pub fn spot_ctx(coin: &str, mark_px: &str) -> Message {
+    spot_ctx_with_prev_day(coin, mark_px, mark_px)
+}
+
+pub fn spot_ctx_with_prev_day(coin: &str, mark_px: &str, prev_day_px: &str) -> Message {
+    serde_json::from_value(serde_json::json!({
+        "channel": "activeSpotAssetCtx",
+        "data": {
+            "coin": coin,
+            "ctx": {
+                "dayNtlVlm": "0.0",
+                "prevDayPx": prev_day_px,
+                "markPx": mark_px,
+                "midPx": mark_px,
+                "circulatingSupply": "0.0"
+            }
+        }
+    }))
+    .unwrap()
+}
+
+pub fn perp_ctx(coin: &str, mark_px: &str) -> Message {
+    perp_ctx_with(coin, mark_px, mark_px, "0.0000125", "0.0")
+}
+
+pub fn perp_ctx_with(coin: &str, mark_px: &str, oracle_px: &str, funding: &str, open_interest: &str) -> Message {
+    serde_json::from_value(serde_json::json!({
+        "channel": "activeAssetCtx",
+        "data": {
+            "coin": coin,
+            "ctx": {
+                "dayNtlVlm": "0.0",
+                "prevDayPx": mark_px,
+                "markPx": mark_px,
+                "midPx": mark_px,
+                "funding": funding,
+                "openInterest": open_interest,
+                "oraclePx": oracle_px
+            }
+        }
+    }))
+    .unwrap()
+}
+
+fn book_levels(levels: &[(&str, &str)]) -> serde_json::Value {
+    levels
+        .iter()
+        .map(|(px, sz)| serde_json::json!({ "px": px, "sz": sz, "n": 1 }))
+        .collect()
+}
+
+pub fn l2_book(coin: &str, bids: &[(&str, &str)], asks: &[(&str, &str)]) -> Message {
+    serde_json::from_value(serde_json::json!({
+        "channel": "l2Book",
+        "data": {
+            "coin": coin,
+            "time": 0,
+            "levels": [book_levels(bids), book_levels(asks)]
+        }
+    }))
+    .unwrap()
+}
+
+pub fn bbo(coin: &str, bid: (&str, &str), ask: (&str, &str)) -> Message {
+    serde_json::from_value(serde_json::json!({
+        "channel": "bbo",
+        "data": {
+            "coin": coin,
+            "time": 0,
+            "bbo": [book_levels(&[bid])[0], book_levels(&[ask])[0]]
+        }
+    }))
+    .unwrap()
+}
+
+/// A `spot_meta` token entry.
+pub fn spot_token(name: &str, index: usize) -> serde_json::Value {
+    serde_json::json!({
+        "name": name,
+        "szDecimals": 2,
+        "weiDecimals": 8,
+        "index": index,
+        "tokenId": format!("0x{index:032x}"),
+        "isCanonical": false,
+    })
+}
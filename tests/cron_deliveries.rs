@@ -0,0 +1,130 @@
+mod common;
+
+use backend::assets::AssetRegistry;
+use backend::cron::{CronService, MAX_CATCH_UP_FIRES, MAX_DELIVERY_ATTEMPTS};
+use backend::db::{CronAlert, CronAlertKind, DeliveryStatus, MisfirePolicy, ReportTemplate};
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use chrono_tz::Tz;
+use hyperliquid_rust_sdk::{BaseUrl, InfoClient};
+use std::sync::Arc;
+use teloxide::types::ChatId;
+use tokio::sync::Mutex;
+
+fn utc(day: u32, hour: u32, minute: u32, second: u32) -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(2026, 10, day, hour, minute, second).unwrap()
+}
+
+fn cron_alert(cron_schedule: &str, next_trigger: DateTime<Utc>, misfire: MisfirePolicy) -> CronAlert {
+    CronAlert {
+        id: 1,
+        chat_id: 1,
+        coin: "HYPE".to_string(),
+        token: "@107".to_string(),
+        cron_schedule: cron_schedule.to_string(),
+        is_active: true,
+        created_at: next_trigger,
+        updated_at: next_trigger,
+        last_triggered: None,
+        next_trigger: Some(next_trigger),
+        template: ReportTemplate::Price,
+        misfire,
+        kind: CronAlertKind::Market,
+        address: None,
+    }
+}
+
+#[test]
+fn misfire_policies_decide_what_catches_up() {
+    let now = utc(17, 8, 0, 30);
+    let plan = |misfire| CronService::plan_fires(&cron_alert("0 * * * *", utc(17, 5, 0, 0), misfire), Tz::UTC, now).unwrap();
+
+    let once = plan(MisfirePolicy::FireOnce);
+    assert_eq!(once.fires, vec![utc(17, 8, 0, 0)]);
+    assert_eq!(once.skipped, 3);
+    assert_eq!(once.next_trigger, utc(17, 9, 0, 0));
+
+    let all = plan(MisfirePolicy::FireAll);
+    assert_eq!(all.fires, vec![utc(17, 5, 0, 0), utc(17, 6, 0, 0), utc(17, 7, 0, 0), utc(17, 8, 0, 0)]);
+    assert_eq!(all.skipped, 0);
+
+    // 08:00 is only 30s late, so it is on time and fires even when skipping.
+    let skip = plan(MisfirePolicy::Skip);
+    assert_eq!(skip.fires, vec![utc(17, 8, 0, 0)]);
+    assert_eq!(skip.skipped, 3);
+
+    let late = CronService::plan_fires(&cron_alert("0 * * * *", utc(17, 5, 0, 0), MisfirePolicy::Skip), Tz::UTC, utc(17, 8, 5, 0)).unwrap();
+    assert!(late.fires.is_empty());
+    assert_eq!((late.skipped, late.next_trigger), (4, utc(17, 9, 0, 0)));
+
+    let minutely = CronService::plan_fires(&cron_alert("* * * * *", utc(15, 0, 0, 0), MisfirePolicy::FireAll), Tz::UTC, utc(17, 0, 0, 0)).unwrap();
+    assert_eq!(minutely.fires.len(), MAX_CATCH_UP_FIRES);
+    assert_eq!(minutely.fires.last(), Some(&utc(17, 0, 0, 0)));
+
+    let not_due = CronService::plan_fires(&cron_alert("0 * * * *", utc(17, 9, 0, 0), MisfirePolicy::FireAll), Tz::UTC, now).unwrap();
+    assert!(not_due.fires.is_empty());
+    assert_eq!(not_due.next_trigger, utc(17, 9, 0, 0));
+}
+
+#[tokio::test]
+async fn fires_are_claimed_once_and_completed_or_retried() {
+    let (db, _) = common::setup().await;
+    let info_client = Arc::new(Mutex::new(InfoClient::new(None, Some(BaseUrl::Mainnet)).await.unwrap()));
+    let service = CronService::new(db.clone(), info_client.clone(), AssetRegistry::new(info_client));
+
+    let due_at = Utc::now() - Duration::minutes(150);
+    db.insert_cron_alert(ChatId(1), "HYPE", "@107", "0 * * * *", ReportTemplate::Price, due_at).await.unwrap();
+    let id = db.get_cron_alerts_for_chat(ChatId(1)).await.unwrap()[0].id;
+    assert!(service.set_misfire_policy(id, ChatId(1), MisfirePolicy::FireAll).await.unwrap());
+    assert!(!service.set_misfire_policy(id, ChatId(2), MisfirePolicy::Skip).await.unwrap());
+
+    let claimed = service.claim_due().await.unwrap();
+    assert!((3..=4).contains(&claimed.len()));
+    assert!(claimed.iter().all(|(_, delivery)| delivery.status == DeliveryStatus::Claimed));
+    assert_eq!(claimed[0].1.scheduled_for, due_at);
+    // The alert moved on, so claiming again finds nothing to send.
+    assert!(service.claim_due().await.unwrap().is_empty());
+    let cron_alert = db.get_cron_alert(id).await.unwrap().unwrap();
+    assert!(cron_alert.next_trigger.unwrap() > Utc::now());
+    // A fire that already has a delivery is never claimed again.
+    let next_trigger = cron_alert.next_trigger.unwrap();
+    assert!(db.claim_cron_deliveries(id, next_trigger, &[due_at], next_trigger).await.unwrap().is_empty());
+
+    let (sent, failed) = (claimed[0].1.id, claimed[1].1.id);
+    service.complete_delivery(sent).await.unwrap();
+    assert!(db.get_cron_alert(id).await.unwrap().unwrap().last_triggered.is_some());
+    for attempt in 1..=MAX_DELIVERY_ATTEMPTS {
+        service.fail_delivery(failed, "network down").await.unwrap();
+        // A failure is not retried straight away.
+        assert!(service.claim_due().await.unwrap().is_empty());
+        let retried = db.claim_failed_cron_deliveries(MAX_DELIVERY_ATTEMPTS, Utc::now()).await.unwrap();
+        if attempt < MAX_DELIVERY_ATTEMPTS {
+            assert_eq!(retried.iter().map(|delivery| delivery.id).collect::<Vec<_>>(), vec![failed]);
+        } else {
+            assert!(retried.is_empty());
+        }
+    }
+
+    assert_eq!(service.abandon_interrupted_deliveries().await.unwrap(), claimed.len() - 2);
+    let deliveries = db.get_cron_deliveries(id).await.unwrap();
+    assert_eq!(deliveries[0].status, DeliveryStatus::Sent);
+    assert_eq!(deliveries[1].status, DeliveryStatus::Failed);
+    assert_eq!(deliveries[1].attempts, MAX_DELIVERY_ATTEMPTS);
+    assert_eq!(deliveries[1].error.as_deref(), Some("network down"));
+    assert!(deliveries[2..].iter().all(|delivery| delivery.status == DeliveryStatus::Abandoned));
+
+    assert!(service.until_next_trigger().await.unwrap() <= std::time::Duration::from_secs(3_600));
+    assert!(service.delete_cron_alert(id, ChatId(1)).await.unwrap());
+    assert!(db.get_cron_deliveries(id).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn alerts_that_cant_be_planned_are_deactivated() {
+    let (db, _) = common::setup().await;
+    let info_client = Arc::new(Mutex::new(InfoClient::new(None, Some(BaseUrl::Mainnet)).await.unwrap()));
+    let service = CronService::new(db.clone(), info_client.clone(), AssetRegistry::new(info_client));
+    db.insert_cron_alert(ChatId(1), "HYPE", "@107", "not a schedule", ReportTemplate::Price, Utc::now() - Duration::minutes(5)).await.unwrap();
+
+    assert!(service.until_next_trigger().await.unwrap() >= std::time::Duration::from_secs(1));
+    assert!(service.claim_due().await.unwrap().is_empty());
+    assert!(db.get_cron_alerts_for_chat(ChatId(1)).await.unwrap().is_empty());
+}
@@ -0,0 +1,96 @@
+mod common;
+
+use backend::assets::AssetRegistry;
+use backend::cron::CronService;
+use backend::db::ReportTemplate;
+use chrono::{DateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use hyperliquid_rust_sdk::{BaseUrl, InfoClient};
+use std::sync::Arc;
+use teloxide::types::ChatId;
+use tokio::sync::Mutex;
+
+fn utc(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(year, month, day, hour, minute, 0).unwrap()
+}
+
+#[test]
+fn schedules_parse_into_cron_expressions() {
+    for (schedule, cron) in [
+        ("hourly", "0 * * * *"),
+        ("hourly :15", "15 * * * *"),
+        ("every 15m", "*/15 * * * *"),
+        ("every 4h", "0 */4 * * *"),
+        ("daily 08:00", "0 8 * * *"),
+        ("Monday 08:05", "5 8 * * 1"),
+        ("sun 20:00", "0 20 * * 0"),
+        ("weekdays 09:30", "30 9 * * 1-5"),
+        ("weekends 10:00", "0 10 * * 0,6"),
+        ("monthly 1 09:00", "0 9 1 * *"),
+        ("0 9 * * 1-5", "0 9 * * 1-5"),
+        ("*/5  *  *  *  *", "*/5 * * * *"),
+    ] {
+        assert_eq!(CronService::parse_schedule(schedule).unwrap(), cron, "{schedule}");
+    }
+}
+
+#[test]
+fn bad_schedules_are_errors() {
+    for schedule in [
+        "",
+        "daily",
+        "daily 25:00",
+        "daily 8am",
+        "hourly :60",
+        "every 7m",
+        "every 24h",
+        "every 0h",
+        "every 4ч",
+        "monthly 32 09:00",
+        "someday 08:00",
+        "61 * * * *",
+        "0 0 31 2 *",
+        "* * * *",
+    ] {
+        assert!(CronService::parse_schedule(schedule).is_err(), "{schedule}");
+    }
+}
+
+#[test]
+fn schedules_follow_the_local_wall_clock_across_dst() {
+    let london = Tz::Europe__London;
+    assert_eq!(CronService::next_trigger("0 8 * * *", london, utc(2026, 7, 1, 0, 0)).unwrap(), utc(2026, 7, 1, 7, 0));
+    assert_eq!(CronService::next_trigger("0 8 * * *", london, utc(2026, 12, 1, 0, 0)).unwrap(), utc(2026, 12, 1, 8, 0));
+    assert_eq!(CronService::next_trigger("0 8 * * *", Tz::UTC, utc(2026, 7, 1, 0, 0)).unwrap(), utc(2026, 7, 1, 8, 0));
+
+    // Clocks go forward at 01:00 on 2026-03-29, so 01:30 is skipped that day.
+    assert_eq!(CronService::next_trigger("30 1 * * *", london, utc(2026, 3, 29, 0, 0)).unwrap(), utc(2026, 3, 30, 0, 30));
+    // Clocks go back at 02:00 on 2026-10-25, so 01:30 happens twice and
+    // fires once, at the first.
+    assert_eq!(CronService::next_trigger("30 1 * * *", london, utc(2026, 10, 24, 23, 0)).unwrap(), utc(2026, 10, 25, 0, 30));
+    assert_eq!(CronService::next_trigger("30 1 * * *", london, utc(2026, 10, 25, 0, 30)).unwrap(), utc(2026, 10, 26, 1, 30));
+}
+
+#[tokio::test]
+async fn changing_the_timezone_reschedules_cron_alerts() {
+    let (db, _) = common::setup().await;
+    let info_client = Arc::new(Mutex::new(InfoClient::new(None, Some(BaseUrl::Mainnet)).await.unwrap()));
+    let service = CronService::new(db.clone(), info_client.clone(), AssetRegistry::new(info_client));
+    assert_eq!(service.timezone(ChatId(1)).await.unwrap(), Tz::UTC);
+
+    let now = Utc::now();
+    let schedule = "0 8 * * *";
+    db.insert_cron_alert(ChatId(1), "HYPE", "@107", schedule, ReportTemplate::Full, CronService::next_trigger(schedule, Tz::UTC, now).unwrap()).await.unwrap();
+    service.set_timezone(ChatId(1), Tz::Asia__Tokyo).await.unwrap();
+    assert_eq!(service.timezone(ChatId(1)).await.unwrap(), Tz::Asia__Tokyo);
+    assert_eq!(service.timezone(ChatId(2)).await.unwrap(), Tz::UTC);
+
+    let cron_alert = &service.get_cron_alerts_for_chat(ChatId(1)).await.unwrap()[0];
+    let next_trigger = cron_alert.next_trigger.unwrap();
+    assert!(next_trigger > now);
+    assert_eq!(next_trigger, CronService::next_trigger(schedule, Tz::Asia__Tokyo, now).unwrap());
+    // 08:00 in Tokyo is 23:00 UTC.
+    assert_eq!(next_trigger.format("%H:%M").to_string(), "23:00");
+    assert!(cron_alert.localized(Tz::Asia__Tokyo).to_string().contains("(next trigger: "));
+    assert!(cron_alert.localized(Tz::Asia__Tokyo).to_string().ends_with("08:00:00 JST)"));
+}
@@ -0,0 +1,99 @@
+mod common;
+
+use backend::{
+    alerts::AlertService,
+    db::{AlertKind, MarketType, NewAlert},
+    expressions::{Expression, Metric},
+    monitor::PriceMonitor,
+};
+use common::{perp_ctx, perp_ctx_with, target_alert};
+use teloxide::types::ChatId;
+
+fn expression_alert(chat_id: i64, coin: &str, token: &str, expression: &str) -> NewAlert {
+    target_alert(ChatId(chat_id), coin, token, MarketType::Perp, AlertService::parse_expression(expression).unwrap())
+}
+
+#[test]
+fn parses_expressions() {
+    let expression: Expression = "HYPE > 40 AND funding(HYPE) < 0".parse().unwrap();
+    assert_eq!(expression.to_string(), "HYPE > 40 AND funding(HYPE-PERP) < 0");
+    assert_eq!(expression.coins(), vec!["HYPE", "HYPE-PERP"]);
+
+    let expression: Expression = "price(ETH-PERP)/price(BTC-PERP)<0.05".parse().unwrap();
+    assert_eq!(expression.to_string(), "ETH-PERP / BTC-PERP < 0.05");
+
+    let expression: Expression = "(BTC-PERP > 1 or ETH-PERP > 2) and (oi(BTC) - 10) * 2 >= -5".parse().unwrap();
+    assert_eq!(expression.to_string(), "(BTC-PERP > 1 OR ETH-PERP > 2) AND (oi(BTC-PERP) - 10) * 2 >= -5");
+
+    let target = AlertService::parse_expression("HYPE > 40 OR HYPE < 30").unwrap();
+    assert_eq!(target.kind, AlertKind::Expression);
+    assert_eq!(target.expression.as_deref(), Some("HYPE > 40 OR HYPE < 30"));
+
+    assert!("HYPE > 40 AND".parse::<Expression>().is_err());
+    assert!("HYPE + 40".parse::<Expression>().is_err());
+    assert!("volume(HYPE) > 40".parse::<Expression>().is_err());
+    assert!("1 > 0".parse::<Expression>().is_err());
+}
+
+#[test]
+fn expressions_evaluate_with_missing_inputs() {
+    let expression: Expression = "BTC-PERP > 70000 OR funding(ETH) < 0".parse().unwrap();
+    let only_btc = |price| {
+        move |metric: Metric, coin: &str| (metric == Metric::Price && coin == "BTC-PERP").then_some(price)
+    };
+    assert_eq!(expression.evaluate(&only_btc(71000.0)), Some(true));
+    assert_eq!(expression.evaluate(&only_btc(69000.0)), None);
+
+    let ratio: Expression = "ETH / BTC < 0.05".parse().unwrap();
+    assert_eq!(ratio.evaluate(&|_, coin: &str| Some(if coin == "ETH" { 3000.0 } else { 0.0 })), None);
+}
+
+#[tokio::test]
+async fn expressions_fire_when_they_become_true_across_markets() {
+    let (db, alert_service) = common::setup().await;
+    let monitor = PriceMonitor::new(alert_service);
+    db.insert_alert(&expression_alert(1, "BTC-PERP", "BTC", "BTC-PERP > 70000 AND funding(BTC) < 0")).await.unwrap();
+    db.insert_alert(&expression_alert(2, "ETH-PERP", "ETH", "ETH-PERP / BTC-PERP < 0.05")).await.unwrap();
+
+    assert!(monitor.handle_message(perp_ctx_with("BTC", "69000", "69000", "-0.00001", "0")).await.unwrap().is_empty());
+    assert!(monitor.handle_message(perp_ctx("ETH", "3500")).await.unwrap().is_empty());
+
+    let fired = monitor.handle_message(perp_ctx_with("BTC", "71000", "71000", "-0.00001", "0")).await.unwrap();
+    let mut chat_ids: Vec<i64> = fired.iter().map(|alert| alert.chat_id).collect();
+    chat_ids.sort();
+    assert_eq!(chat_ids, vec![1, 2]);
+}
+
+#[tokio::test]
+async fn expressions_are_stored_against_every_coin_they_read() {
+    let (_db, alert_service) = common::setup_with_perps(&["BTC", "ETH"]).await;
+    let monitor = PriceMonitor::new(alert_service.clone());
+    assert!(monitor.handle_message(perp_ctx("ETH", "3500")).await.unwrap().is_empty());
+
+    // Alerts set after the first tick are picked up by the next one.
+    let target = AlertService::parse_expression("ETH-PERP / BTC-PERP < 0.05").unwrap();
+    alert_service.create_expression_alert("0x00", ChatId(1), target).await.unwrap();
+    let stored = &alert_service.get_all_alerts_for_chat(ChatId(1)).await.unwrap()[0];
+    assert_eq!((stored.coin.as_str(), stored.token.as_str()), ("ETH-PERP,BTC-PERP", "ETH,BTC"));
+
+    assert!(monitor.handle_message(perp_ctx("BTC", "60000")).await.unwrap().is_empty());
+    let fired = monitor.handle_message(perp_ctx("BTC", "80000")).await.unwrap();
+    assert_eq!(fired.iter().map(|alert| alert.id).collect::<Vec<_>>(), vec![stored.id]);
+
+    let target = AlertService::parse_expression("BTC-PERP > 90000").unwrap();
+    alert_service.edit_alert(stored.id, ChatId(1), target).await.unwrap();
+    let edited = &alert_service.get_all_alerts_for_chat(ChatId(1)).await.unwrap()[0];
+    assert_eq!((edited.coin.as_str(), edited.token.as_str()), ("BTC-PERP", "BTC"));
+    assert!(alert_service.edit_alert(stored.id, ChatId(1), AlertService::parse_target(">40").unwrap()).await.is_err());
+}
+
+#[tokio::test]
+async fn alerts_on_coins_that_no_longer_resolve_are_skipped_at_startup() {
+    let (db, alert_service) = common::setup_with_perps(&["BTC"]).await;
+    db.insert_alert(&expression_alert(1, "FOO-PERP", "FOO", "FOO-PERP / BTC-PERP < 0.05")).await.unwrap();
+    let ratio = AlertService::parse_target("ratio(FOO-PERP)<0.05").unwrap();
+    db.insert_alert(&target_alert(ChatId(1), "BTC-PERP", "BTC", MarketType::Perp, ratio)).await.unwrap();
+
+    alert_service.subscribe_existing_alerts().await.unwrap();
+    assert_eq!(db.get_all_alerts().await.unwrap().len(), 2);
+}
@@ -0,0 +1,129 @@
+mod common;
+
+use backend::{
+    alerts::AlertService,
+    db::{AlertDirection, AlertKind, MarketType, NewAlert},
+    indicators::{IndicatorCondition, IndicatorEngine, Interval, Operand},
+    monitor::PriceMonitor,
+};
+use common::target_alert;
+use hyperliquid_rust_sdk::{BaseUrl, InfoClient, Message};
+use std::sync::Arc;
+use teloxide::types::ChatId;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio::sync::Mutex;
+
+fn candle(coin: &str, interval: &str, time_open: u64, close: &str) -> Message {
+    serde_json::from_value(serde_json::json!({
+        "channel": "candle",
+        "data": {
+            "T": time_open + 59_999,
+            "c": close,
+            "h": close,
+            "i": interval,
+            "l": close,
+            "n": 1,
+            "o": close,
+            "s": coin,
+            "t": time_open,
+            "v": "1.0"
+        }
+    }))
+    .unwrap()
+}
+
+fn indicator_alert(chat_id: i64, target: &str) -> NewAlert {
+    target_alert(ChatId(chat_id), "PURR", "PURR/USDC", MarketType::Spot, AlertService::parse_target(target).unwrap())
+}
+
+#[test]
+fn parses_indicator_conditions() {
+    let condition: IndicatorCondition = "rsi14(1h) < 30".parse().unwrap();
+    assert_eq!(condition.lhs, Operand::Rsi(14));
+    assert_eq!(condition.rhs, Operand::Number(30.0));
+    assert_eq!(condition.direction, AlertDirection::Below);
+    assert_eq!(condition.interval, Interval::OneHour);
+    assert_eq!(condition.to_string(), "rsi14(1h) < 30");
+
+    let target = AlertService::parse_target("ema12(4h)>ema26(4h)").unwrap();
+    assert_eq!(target.kind, AlertKind::Indicator);
+    assert_eq!(target.expression.as_deref(), Some("ema12(4h) > ema26(4h)"));
+
+    assert!("ema12(4h)>ema26(1h)".parse::<IndicatorCondition>().is_err());
+    assert!("rsi14(5h)<30".parse::<IndicatorCondition>().is_err());
+    assert!("close>30".parse::<IndicatorCondition>().is_err());
+}
+
+#[test]
+fn indicator_conditions_fire_on_crossing() {
+    let condition: IndicatorCondition = "close>sma3(1m)".parse().unwrap();
+    assert!(condition.is_triggered(&[1.0, 1.0, 1.0, 0.9], &[1.0, 1.0, 1.0, 1.2]));
+    assert!(!condition.is_triggered(&[1.0, 1.0, 1.0, 1.1], &[1.0, 1.0, 1.0, 1.2]));
+    assert!(!condition.is_triggered(&[1.0, 0.9], &[1.0, 1.2]));
+
+    let oversold: IndicatorCondition = "rsi2(1m)<30".parse().unwrap();
+    assert!(oversold.is_triggered(&[1.0, 1.1, 1.2], &[1.0, 1.1, 0.7]));
+}
+
+#[tokio::test]
+async fn candles_trigger_indicator_alerts_on_their_interval() {
+    let (db, alert_service) = common::setup().await;
+    let monitor = PriceMonitor::new(alert_service);
+    db.insert_alert(&indicator_alert(1, "close>sma3(1m)")).await.unwrap();
+    db.insert_alert(&indicator_alert(2, "close>sma3(5m)")).await.unwrap();
+
+    for (minute, close) in [(0, "1.0"), (1, "1.0"), (2, "1.0"), (3, "0.9")] {
+        let fired = monitor.handle_message(candle("PURR/USDC", "1m", minute * 60_000, close)).await.unwrap();
+        assert!(fired.is_empty());
+    }
+
+    let fired = monitor.handle_message(candle("PURR/USDC", "1m", 3 * 60_000, "1.2")).await.unwrap();
+    assert_eq!(fired.iter().map(|alert| alert.chat_id).collect::<Vec<_>>(), vec![1]);
+}
+
+/// Answers one request to the API `BaseUrl::Localhost` points at with
+/// `body`.
+async fn serve_once(body: serde_json::Value) {
+    let listener = TcpListener::bind("127.0.0.1:3001").await.unwrap();
+    tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut request = [0; 8192];
+        let _ = stream.read(&mut request).await.unwrap();
+        let body = body.to_string();
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        );
+        stream.write_all(response.as_bytes()).await.unwrap();
+    });
+}
+
+#[tokio::test]
+async fn a_failed_warm_up_is_backfilled_under_live_candles() {
+    let engine = IndicatorEngine::new(Arc::new(Mutex::new(InfoClient::new(None, Some(BaseUrl::Localhost)).await.unwrap())));
+    let live = |close: &str| match candle("PURR/USDC", "1m", 180_000, close) {
+        Message::Candle(candle) => candle.data,
+        _ => unreachable!(),
+    };
+
+    // Nothing is listening yet, so the warm-up fails and live candles start
+    // the series cold.
+    assert!(engine.warm_up("PURR/USDC", Interval::OneMinute).await.is_err());
+    assert_eq!(engine.apply_candle(&live("13")).await.unwrap().current, vec![13.0]);
+
+    let snapshot: Vec<serde_json::Value> = [(0, "10"), (60_000, "11"), (120_000, "12"), (180_000, "12.5")]
+        .into_iter()
+        .map(|(t, c)| serde_json::json!({"t": t, "T": t + 59_999, "s": "PURR/USDC", "i": "1m", "o": c, "c": c, "h": c, "l": c, "v": "1", "n": 1}))
+        .collect();
+    serve_once(serde_json::Value::Array(snapshot)).await;
+    engine.warm_up("PURR/USDC", Interval::OneMinute).await.unwrap();
+
+    // The snapshot fills in the history and the live close of the open
+    // candle is kept over the snapshot's.
+    let update = engine.apply_candle(&live("13.5")).await.unwrap();
+    assert_eq!(update.previous, vec![10.0, 11.0, 12.0, 13.0]);
+    assert_eq!(update.current, vec![10.0, 11.0, 12.0, 13.5]);
+    // Loaded series are not fetched again.
+    engine.warm_up("PURR/USDC", Interval::OneMinute).await.unwrap();
+}
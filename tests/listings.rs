@@ -0,0 +1,77 @@
+mod common;
+
+use backend::{
+    db::{ListingKind, MarketListing},
+    listings::{ListingService, market_listings},
+};
+use common::spot_token;
+use hyperliquid_rust_sdk::{BaseUrl, InfoClient, Meta, SpotMeta};
+use std::sync::Arc;
+use teloxide::types::ChatId;
+use tokio::sync::Mutex;
+
+fn listing(kind: ListingKind, name: &str, label: &str) -> MarketListing {
+    MarketListing {
+        kind,
+        name: name.to_string(),
+        label: label.to_string(),
+    }
+}
+
+#[test]
+fn listings_skip_delisted_perps_and_name_spot_pairs() {
+    let meta: Meta = serde_json::from_value(serde_json::json!({
+        "universe": [
+            {"name": "BTC", "szDecimals": 5},
+            {"name": "MATIC", "szDecimals": 1, "isDelisted": true},
+        ]
+    }))
+    .unwrap();
+    let spot_meta: SpotMeta = serde_json::from_value(serde_json::json!({
+        "universe": [
+            {"tokens": [1, 0], "name": "PURR/USDC", "index": 0, "isCanonical": true},
+            {"tokens": [150, 0], "name": "@107", "index": 107, "isCanonical": false},
+        ],
+        "tokens": [spot_token("USDC", 0), spot_token("PURR", 1), spot_token("HYPE", 150)],
+    }))
+    .unwrap();
+
+    assert_eq!(
+        market_listings(&meta, &spot_meta),
+        vec![
+            listing(ListingKind::Perp, "BTC", "BTC-PERP"),
+            listing(ListingKind::SpotPair, "PURR/USDC", "PURR/USDC"),
+            listing(ListingKind::SpotPair, "@107", "HYPE/USDC (@107)"),
+            listing(ListingKind::SpotToken, "USDC", "USDC"),
+            listing(ListingKind::SpotToken, "PURR", "PURR"),
+            listing(ListingKind::SpotToken, "HYPE", "HYPE"),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn subscribed_chats_hear_about_changes_after_the_first_snapshot() {
+    let (db, _) = common::setup().await;
+    let info_client = Arc::new(Mutex::new(InfoClient::new(None, Some(BaseUrl::Mainnet)).await.unwrap()));
+    let service = ListingService::new(db, info_client);
+    assert!(service.subscribe(ChatId(1)).await.unwrap());
+    assert!(!service.subscribe(ChatId(1)).await.unwrap());
+    assert!(service.subscribe(ChatId(2)).await.unwrap());
+    assert!(service.unsubscribe(ChatId(2)).await.unwrap());
+    assert!(!service.unsubscribe(ChatId(3)).await.unwrap());
+
+    let btc = listing(ListingKind::Perp, "BTC", "BTC-PERP");
+    let hype = listing(ListingKind::SpotPair, "@107", "HYPE/USDC (@107)");
+    let eth = listing(ListingKind::Perp, "ETH", "ETH-PERP");
+    assert!(service.sync(vec![btc.clone(), hype.clone()]).await.unwrap().is_empty());
+    assert!(service.sync(vec![btc.clone(), hype.clone()]).await.unwrap().is_empty());
+
+    let notifications = service.sync(vec![btc.clone(), eth.clone()]).await.unwrap();
+    assert_eq!(notifications.len(), 1);
+    assert_eq!(notifications[0].chat_id, 1);
+    assert_eq!(
+        notifications[0].to_string(),
+        "🆕 Listed on Hyperliquid:\n- ETH-PERP (perp)\n🚫 Delisted from Hyperliquid:\n- HYPE/USDC (@107) (spot pair)"
+    );
+    assert!(service.sync(vec![btc, eth]).await.unwrap().is_empty());
+}
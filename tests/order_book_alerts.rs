@@ -0,0 +1,34 @@
+mod common;
+
+use backend::{
+    alerts::AlertService,
+    db::{MarketType, NewAlert},
+    monitor::PriceMonitor,
+};
+use common::{bbo, l2_book, target_alert};
+use teloxide::types::ChatId;
+
+fn book_alert(chat_id: i64, target: &str) -> NewAlert {
+    target_alert(ChatId(chat_id), "PURR", "PURR/USDC", MarketType::Spot, AlertService::parse_target(target).unwrap())
+}
+
+#[tokio::test]
+async fn book_alerts_fire_when_their_threshold_is_crossed() {
+    let (db, alert_service) = common::setup().await;
+    let monitor = PriceMonitor::new(alert_service);
+    db.insert_alert(&book_alert(1, "spread>50bps")).await.unwrap();
+    db.insert_alert(&book_alert(2, "biddepth1%<500")).await.unwrap();
+    db.insert_alert(&book_alert(3, "level>10000")).await.unwrap();
+
+    let thick = l2_book("PURR/USDC", &[("0.200", "5000"), ("0.199", "1000")], &[("0.201", "5000")]);
+    assert!(monitor.handle_message(thick).await.unwrap().is_empty());
+    assert!(monitor.handle_message(bbo("PURR/USDC", ("0.200", "5000"), ("0.201", "5000"))).await.unwrap().is_empty());
+
+    let fired = monitor.handle_message(bbo("PURR/USDC", ("0.200", "5000"), ("0.202", "5000"))).await.unwrap();
+    assert_eq!(fired.iter().map(|alert| alert.chat_id).collect::<Vec<_>>(), vec![1]);
+
+    let thin = l2_book("PURR/USDC", &[("0.200", "1000"), ("0.150", "20000")], &[("0.201", "5000")]);
+    let mut fired: Vec<i64> = monitor.handle_message(thin).await.unwrap().iter().map(|alert| alert.chat_id).collect();
+    fired.sort();
+    assert_eq!(fired, vec![2, 3]);
+}
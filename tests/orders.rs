@@ -0,0 +1,62 @@
+use backend::orders::{OrderEvent, OrderNotification, TriggerKind, TriggerOrder};
+use hyperliquid_rust_sdk::{OrderUpdate, UserFillsResponse};
+
+fn update(status: &str, oid: u64) -> OrderUpdate {
+    serde_json::from_value(serde_json::json!({
+        "order": {
+            "coin": "BTC", "side": "A", "limitPx": "90000", "sz": "0", "oid": oid,
+            "timestamp": 0, "origSz": "0.2", "cloid": null,
+        },
+        "status": status,
+        "statusTimestamp": 0,
+    }))
+    .unwrap()
+}
+
+fn fill(oid: u64, px: &str, sz: &str) -> serde_json::Value {
+    serde_json::json!({
+        "closedPnl": "0", "coin": "BTC", "crossed": true, "dir": "Close Long", "hash": "0x0", "oid": oid,
+        "px": px, "side": "A", "startPosition": "0.2", "sz": sz, "time": 0, "fee": "0",
+    })
+}
+
+fn notification(event: OrderEvent) -> String {
+    let order = TriggerOrder {
+        address: "0xabc".to_string(),
+        oid: 7,
+        coin: "BTC".to_string(),
+        side: "A".to_string(),
+        sz: "0.2".to_string(),
+        trigger_px: 95000.0,
+        kind: TriggerKind::StopLoss,
+    };
+    OrderNotification { chat_id: 1, order, event }.to_string()
+}
+
+#[test]
+fn fills_report_the_average_fill_price() {
+    let fills: Vec<UserFillsResponse> =
+        serde_json::from_value(serde_json::json!([fill(7, "94900", "0.15"), fill(7, "94800", "0.05"), fill(8, "1", "1")])).unwrap();
+
+    let Some(OrderEvent::Filled { avg_px, limit_px }) = OrderEvent::from_update(&update("filled", 7), &fills) else {
+        panic!("expected a fill");
+    };
+    assert_eq!(avg_px, Some(94875.0));
+    assert_eq!(limit_px, "90000");
+    assert_eq!(notification(OrderEvent::Filled { avg_px, limit_px }), "✅ Your SL on BTC filled at 94875 (size 0.2) [0xabc]");
+
+    // Without its fills the limit price is shown, labelled as such.
+    let event = OrderEvent::from_update(&update("filled", 7), &[]).unwrap();
+    assert_eq!(notification(event), "✅ Your SL on BTC filled (limit price 90000, size 0.2) [0xabc]");
+}
+
+#[test]
+fn statuses_map_to_events() {
+    assert!(matches!(OrderEvent::from_update(&update("triggered", 7), &[]), Some(OrderEvent::Triggered)));
+    for status in ["canceled", "marginCanceled", "rejected", "reduceOnlyRejected"] {
+        let event = OrderEvent::from_update(&update(status, 7), &[]);
+        assert!(matches!(&event, Some(OrderEvent::Cancelled { status: s }) if s == status), "{status}");
+    }
+    assert!(OrderEvent::from_update(&update("open", 7), &[]).is_none());
+    assert_eq!(notification(OrderEvent::Triggered), "⚡ Your SL on BTC triggered at 95000 [0xabc]");
+}
@@ -0,0 +1,47 @@
+mod common;
+
+use backend::{
+    alerts::AlertService,
+    db::{AlertDirection, AlertKind, MarketType, NewAlert},
+    monitor::PriceMonitor,
+};
+use common::{perp_ctx, spot_ctx, target_alert};
+use teloxide::types::ChatId;
+
+fn pair_alert(chat_id: i64, coin: &str, token: &str, market: MarketType, target: &str) -> NewAlert {
+    target_alert(ChatId(chat_id), coin, token, market, AlertService::parse_target(target).unwrap())
+}
+
+#[test]
+fn pair_targets_parse_into_their_kind() {
+    let target = AlertService::parse_target("ratio(BTC-PERP)<0.05").unwrap();
+    assert_eq!((target.kind, target.direction, target.price), (AlertKind::Ratio, AlertDirection::Below, 0.05));
+    assert_eq!(target.expression.as_deref(), Some("BTC-PERP"));
+    assert_eq!(target.to_string(), "ratio to BTC-PERP below 0.05");
+
+    let target = AlertService::parse_target("basis(HYPE-PERP)>-20bps").unwrap();
+    assert_eq!((target.kind, target.direction, target.price), (AlertKind::Basis, AlertDirection::Above, -20.0));
+
+    assert!(AlertService::parse_target("ratio()<0.05").is_err());
+    assert!(AlertService::parse_target("ratio(BTC-PERP)0.05").is_err());
+    assert!(AlertService::parse_target("basis(HYPE-PERP)>20").is_err());
+}
+
+#[tokio::test]
+async fn pair_alerts_fire_when_either_leg_moves_the_value_across() {
+    let (db, alert_service) = common::setup().await;
+    let monitor = PriceMonitor::new(alert_service);
+    db.insert_alert(&pair_alert(1, "ETH-PERP", "ETH", MarketType::Perp, "ratio(BTC-PERP)<0.05")).await.unwrap();
+    db.insert_alert(&pair_alert(2, "HYPE", "@107", MarketType::Spot, "basis(HYPE-PERP)>50bps")).await.unwrap();
+
+    for message in [perp_ctx("ETH", "3600"), perp_ctx("BTC", "70000"), spot_ctx("@107", "40.0"), perp_ctx("HYPE", "40.0")] {
+        assert!(monitor.handle_message(message).await.unwrap().is_empty());
+    }
+
+    let fired = monitor.handle_message(perp_ctx("BTC", "73000")).await.unwrap();
+    assert_eq!(fired.iter().map(|alert| alert.chat_id).collect::<Vec<_>>(), vec![1]);
+
+    assert!(monitor.handle_message(perp_ctx("HYPE", "39.9")).await.unwrap().is_empty());
+    let fired = monitor.handle_message(spot_ctx("@107", "40.2")).await.unwrap();
+    assert_eq!(fired.iter().map(|alert| alert.chat_id).collect::<Vec<_>>(), vec![2]);
+}
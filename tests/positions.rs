@@ -0,0 +1,98 @@
+mod common;
+
+use backend::db::WatchedAddress;
+use backend::positions::PositionService;
+use chrono::Utc;
+use hyperliquid_rust_sdk::{BaseUrl, InfoClient, UserStateResponse};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+const ADDRESS: &str = "0x0000000000000000000000000000000000000001";
+
+fn watcher(chat_id: i64, liquidation_threshold: f64) -> WatchedAddress {
+    WatchedAddress {
+        id: chat_id,
+        chat_id,
+        address: ADDRESS.to_string(),
+        liquidation_threshold,
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+        tpsl_threshold: 1.0,
+    }
+}
+
+/// A user state with a BTC position per (szi, position value, liquidation
+/// price).
+fn user_state(positions: &[(&str, &str, &str)]) -> UserStateResponse {
+    let summary = serde_json::json!({"accountValue": "10000", "totalMarginUsed": "2000", "totalNtlPos": "10000", "totalRawUsd": "0"});
+    let asset_positions: Vec<serde_json::Value> = positions
+        .iter()
+        .map(|(szi, position_value, liquidation_px)| {
+            serde_json::json!({
+                "type": "oneWay",
+                "position": {
+                    "coin": "BTC",
+                    "entryPx": "100000",
+                    "leverage": {"type": "cross", "value": 10},
+                    "liquidationPx": liquidation_px,
+                    "marginUsed": "1000",
+                    "positionValue": position_value,
+                    "returnOnEquity": "0",
+                    "szi": szi,
+                    "unrealizedPnl": "0",
+                    "maxLeverage": 40,
+                    "cumFunding": {"allTime": "0", "sinceChange": "0", "sinceOpen": "0"},
+                },
+            })
+        })
+        .collect();
+    serde_json::from_value(serde_json::json!({
+        "assetPositions": asset_positions,
+        "crossMarginSummary": summary,
+        "marginSummary": summary,
+        "withdrawable": "8000",
+    }))
+    .unwrap()
+}
+
+async fn service() -> PositionService {
+    let (db, _) = common::setup().await;
+    PositionService::new(db, Arc::new(Mutex::new(InfoClient::new(None, Some(BaseUrl::Mainnet)).await.unwrap())))
+}
+
+#[tokio::test]
+async fn warns_within_each_watchers_threshold() {
+    let service = service().await;
+    let watchers = [watcher(1, 10.0), watcher(2, 3.0)];
+
+    // Mark 100000, liquidation 95000: 5% away, inside 10% but not 3%.
+    let warnings = service.warnings_for(ADDRESS, &watchers, &user_state(&[("1", "100000", "95000")])).await;
+    assert_eq!(warnings.len(), 1);
+    assert_eq!(warnings[0].chat_id, 1);
+    assert!((warnings[0].distance_pct - 5.0).abs() < 1e-9);
+    assert_eq!(warnings[0].mark_px, 100000.0);
+
+    // Shorts measure the distance upwards. Chat 1 was already warned about
+    // BTC and it has stayed within its threshold.
+    let warnings = service.warnings_for(ADDRESS, &watchers, &user_state(&[("-1", "100000", "102000")])).await;
+    assert_eq!(warnings.iter().map(|warning| warning.chat_id).collect::<Vec<_>>(), vec![2]);
+    assert!(warnings[0].to_string().contains("BTC short 1"));
+}
+
+#[tokio::test]
+async fn warnings_rearm_once_the_position_recovers_or_closes() {
+    let service = service().await;
+    let watchers = [watcher(1, 10.0)];
+    let near = user_state(&[("1", "100000", "95000")]);
+    let recovered = user_state(&[("1", "100000", "80000")]);
+
+    assert_eq!(service.warnings_for(ADDRESS, &watchers, &near).await.len(), 1);
+    assert!(service.warnings_for(ADDRESS, &watchers, &near).await.is_empty());
+
+    assert!(service.warnings_for(ADDRESS, &watchers, &recovered).await.is_empty());
+    assert_eq!(service.warnings_for(ADDRESS, &watchers, &near).await.len(), 1);
+
+    // Closing the position re-arms it too.
+    assert!(service.warnings_for(ADDRESS, &watchers, &user_state(&[])).await.is_empty());
+    assert_eq!(service.warnings_for(ADDRESS, &watchers, &near).await.len(), 1);
+}
@@ -0,0 +1,236 @@
+mod common;
+
+use backend::{
+    alerts::AlertService,
+    db::{AlertDirection, AlertKind, AlertTarget, Database, MarketType, NewAlert, RearmMode},
+    monitor::PriceMonitor,
+};
+use common::{alert, perp_ctx, perp_ctx_with, spot_ctx, spot_ctx_with_prev_day, target_alert};
+use chrono::{Duration, Utc};
+use hyperliquid_rust_sdk::PerpAssetContext;
+use teloxide::types::ChatId;
+use tokio::sync::mpsc::unbounded_channel;
+
+async fn setup() -> (Database, PriceMonitor) {
+    let (db, alert_service) = common::setup().await;
+    (db, PriceMonitor::new(alert_service))
+}
+
+#[tokio::test]
+async fn only_alerts_for_the_updated_token_fire() {
+    let (db, monitor) = setup().await;
+    db.insert_alert(&alert(ChatId(1), "HYPE", "@107", MarketType::Spot, 40.0, AlertDirection::Cross)).await.unwrap();
+    db.insert_alert(&alert(ChatId(2), "PURR", "PURR/USDC", MarketType::Spot, 40.0, AlertDirection::Cross)).await.unwrap();
+    db.insert_alert(&alert(ChatId(3), "PURR", "PURR/USDC", MarketType::Spot, 0.2, AlertDirection::Cross)).await.unwrap();
+
+    let (sender, receiver) = unbounded_channel();
+    let (alert_sender, mut alert_receiver) = unbounded_channel();
+    sender.send(spot_ctx("@107", "40.0")).unwrap();
+    sender.send(spot_ctx("@1", "40.0")).unwrap();
+    sender.send(spot_ctx("PURR/USDC", "0.2")).unwrap();
+    drop(sender);
+
+    monitor.run(receiver, alert_sender).await;
+
+    let mut fired = Vec::new();
+    while let Some(alert) = alert_receiver.recv().await {
+        fired.push((alert.chat_id, alert.token));
+    }
+    assert_eq!(fired, vec![(1, "@107".to_string()), (3, "PURR/USDC".to_string())]);
+}
+
+#[tokio::test]
+async fn triggered_alerts_are_put_on_cooldown() {
+    let (db, monitor) = setup().await;
+    db.insert_alert(&alert(ChatId(1), "HYPE", "@107", MarketType::Spot, 40.0, AlertDirection::Cross)).await.unwrap();
+
+    assert_eq!(monitor.handle_message(spot_ctx("@107", "40.01")).await.unwrap().len(), 1);
+    assert!(monitor.handle_message(spot_ctx("@107", "40.01")).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn alerts_outside_their_window_or_past_expiry_do_not_fire() {
+    let (db, monitor) = setup().await;
+    for chat_id in 1..=3 {
+        db.insert_alert(&alert(ChatId(chat_id), "HYPE", "@107", MarketType::Spot, 40.0, AlertDirection::Cross)).await.unwrap();
+    }
+    let ids: Vec<i64> = db.get_all_alerts().await.unwrap().iter().map(|alert| alert.id).collect();
+    let now = Utc::now();
+    db.set_alert_expiry(ids[0], ChatId(1), Some(now - Duration::minutes(1))).await.unwrap();
+    let outside = format!("{}-{}", (now + Duration::hours(1)).format("%H:00"), (now + Duration::hours(2)).format("%H:00"));
+    db.set_alert_window(ids[1], ChatId(2), Some(&outside.parse().unwrap())).await.unwrap();
+
+    let fired = monitor.handle_message(spot_ctx("@107", "40.0")).await.unwrap();
+    assert_eq!(fired.iter().map(|alert| alert.chat_id).collect::<Vec<_>>(), vec![3]);
+}
+
+#[tokio::test]
+async fn trailing_alerts_fire_on_a_retrace_from_the_persisted_extreme() {
+    let (db, monitor) = setup().await;
+    let trailing = |chat_id, target| NewAlert {
+        target: AlertTarget { price: 40.0, ..AlertService::parse_target(target).unwrap() },
+        ..alert(ChatId(chat_id), "HYPE", "@107", MarketType::Spot, 0.0, AlertDirection::Cross)
+    };
+    db.insert_alert(&trailing(1, "trail-10%")).await.unwrap();
+    db.insert_alert(&trailing(2, "trail+10%")).await.unwrap();
+
+    for px in ["40.0", "38.0", "41.0"] {
+        assert!(monitor.handle_message(spot_ctx("@107", px)).await.unwrap().is_empty());
+    }
+    let extremes: Vec<f64> = db.get_all_alerts().await.unwrap().iter().map(|alert| alert.price).collect();
+    assert_eq!(extremes, vec![41.0, 38.0]);
+
+    let fired = monitor.handle_message(spot_ctx("@107", "42.0")).await.unwrap();
+    assert_eq!(fired.iter().map(|alert| alert.chat_id).collect::<Vec<_>>(), vec![2]);
+    let fired = monitor.handle_message(spot_ctx("@107", "37.5")).await.unwrap();
+    assert_eq!(fired.iter().map(|alert| alert.chat_id).collect::<Vec<_>>(), vec![1]);
+}
+
+#[tokio::test]
+async fn one_shot_and_band_alerts_rearm_as_configured() {
+    let (db, monitor) = setup().await;
+    db.insert_alert(&alert(ChatId(1), "HYPE", "@107", MarketType::Spot, 40.0, AlertDirection::Cross)).await.unwrap();
+    db.insert_alert(&alert(ChatId(2), "HYPE", "@107", MarketType::Spot, 40.0, AlertDirection::Cross)).await.unwrap();
+    let ids: Vec<i64> = db.get_all_alerts().await.unwrap().iter().map(|alert| alert.id).collect();
+    db.set_alert_rearm(ids[0], ChatId(1), RearmMode::OneShot).await.unwrap();
+    db.set_alert_rearm(ids[1], ChatId(2), RearmMode::Band(5.0)).await.unwrap();
+
+    assert_eq!(monitor.handle_message(spot_ctx("@107", "40.0")).await.unwrap().len(), 2);
+    assert_eq!(db.reset_cooldowns().await.unwrap(), 0);
+    assert!(monitor.handle_message(spot_ctx("@107", "41.0")).await.unwrap().is_empty());
+    assert!(monitor.handle_message(spot_ctx("@107", "40.0")).await.unwrap().is_empty());
+
+    assert!(monitor.handle_message(spot_ctx("@107", "42.5")).await.unwrap().is_empty());
+    let fired = monitor.handle_message(spot_ctx("@107", "40.0")).await.unwrap();
+    assert_eq!(fired.iter().map(|alert| alert.chat_id).collect::<Vec<_>>(), vec![2]);
+}
+
+#[tokio::test]
+async fn directional_alerts_fire_when_price_jumps_over_the_target() {
+    let (db, monitor) = setup().await;
+    db.insert_alert(&alert(ChatId(1), "HYPE", "@107", MarketType::Spot, 40.0, AlertDirection::Above)).await.unwrap();
+    db.insert_alert(&alert(ChatId(2), "HYPE", "@107", MarketType::Spot, 40.0, AlertDirection::Below)).await.unwrap();
+    db.insert_alert(&alert(ChatId(3), "HYPE", "@107", MarketType::Spot, 40.0, AlertDirection::Cross)).await.unwrap();
+
+    assert!(monitor.handle_message(spot_ctx("@107", "38.0")).await.unwrap().is_empty());
+    let fired = monitor.handle_message(spot_ctx("@107", "42.0")).await.unwrap();
+    let chat_ids: Vec<i64> = fired.iter().map(|alert| alert.chat_id).collect();
+    assert_eq!(chat_ids, vec![1, 3]);
+}
+
+#[tokio::test]
+async fn perp_updates_fire_perp_alerts_and_keep_the_loop_running() {
+    let (db, monitor) = setup().await;
+    db.insert_alert(&alert(ChatId(1), "BTC-PERP", "BTC", MarketType::Perp, 70000.0, AlertDirection::Cross)).await.unwrap();
+    db.insert_alert(&alert(ChatId(2), "HYPE", "@107", MarketType::Spot, 40.0, AlertDirection::Cross)).await.unwrap();
+
+    let (sender, receiver) = unbounded_channel();
+    let (alert_sender, mut alert_receiver) = unbounded_channel();
+    sender.send(perp_ctx("BTC", "70000.0")).unwrap();
+    sender.send(spot_ctx("@107", "40.0")).unwrap();
+    drop(sender);
+
+    monitor.run(receiver, alert_sender).await;
+
+    let mut fired = Vec::new();
+    while let Some(alert) = alert_receiver.recv().await {
+        fired.push((alert.chat_id, alert.market));
+    }
+    assert_eq!(fired, vec![(1, MarketType::Perp), (2, MarketType::Spot)]);
+}
+
+fn change_alert(chat_id: ChatId, kind: AlertKind, direction: AlertDirection, change_pct: f64, window_minutes: Option<i64>) -> NewAlert {
+    let target = AlertTarget { kind, direction, price: 40.0, change_pct: Some(change_pct), window_minutes, expression: None };
+    target_alert(chat_id, "HYPE", "@107", MarketType::Spot, target)
+}
+
+#[tokio::test]
+async fn day_change_alerts_fire_when_the_move_reaches_the_threshold() {
+    let (db, monitor) = setup().await;
+    db.insert_alert(&change_alert(ChatId(1), AlertKind::DayChange, AlertDirection::Above, 5.0, None)).await.unwrap();
+    db.insert_alert(&change_alert(ChatId(2), AlertKind::DayChange, AlertDirection::Below, 5.0, None)).await.unwrap();
+    db.insert_alert(&change_alert(ChatId(3), AlertKind::DayChange, AlertDirection::Cross, 5.0, None)).await.unwrap();
+
+    assert!(monitor.handle_message(spot_ctx_with_prev_day("@107", "41.0", "40.0")).await.unwrap().is_empty());
+    let fired = monitor.handle_message(spot_ctx_with_prev_day("@107", "42.5", "40.0")).await.unwrap();
+    let chat_ids: Vec<i64> = fired.iter().map(|alert| alert.chat_id).collect();
+    assert_eq!(chat_ids, vec![1, 3]);
+}
+
+#[tokio::test]
+async fn window_and_from_set_alerts_compare_against_their_reference() {
+    let (db, monitor) = setup().await;
+    db.insert_alert(&change_alert(ChatId(1), AlertKind::WindowChange, AlertDirection::Below, 3.0, Some(15))).await.unwrap();
+    db.insert_alert(&change_alert(ChatId(2), AlertKind::FromSet, AlertDirection::Below, 10.0, None)).await.unwrap();
+
+    assert!(monitor.handle_message(spot_ctx("@107", "40.0")).await.unwrap().is_empty());
+    let fired = monitor.handle_message(spot_ctx("@107", "38.0")).await.unwrap();
+    assert_eq!(fired.iter().map(|alert| alert.chat_id).collect::<Vec<_>>(), vec![1]);
+    let fired = monitor.handle_message(spot_ctx("@107", "35.9")).await.unwrap();
+    assert_eq!(fired.iter().map(|alert| alert.chat_id).collect::<Vec<_>>(), vec![2]);
+}
+
+#[tokio::test]
+async fn perp_metric_alerts_fire_on_funding_premium_and_open_interest() {
+    let (db, monitor) = setup().await;
+    let perp_alert = |chat_id, target| NewAlert {
+        target,
+        ..alert(ChatId(chat_id), "BTC-PERP", "BTC", MarketType::Perp, 0.0, AlertDirection::Above)
+    };
+    db.insert_alert(&perp_alert(1, AlertService::parse_target("funding>0.002%").unwrap())).await.unwrap();
+    db.insert_alert(&perp_alert(2, AlertService::parse_target("premium>20bps").unwrap())).await.unwrap();
+    db.insert_alert(&perp_alert(3, AlertService::parse_target("oi+10%/1h").unwrap())).await.unwrap();
+
+    assert!(monitor.handle_message(perp_ctx_with("BTC", "70000", "70000", "0.0000125", "100")).await.unwrap().is_empty());
+    let fired = monitor.handle_message(perp_ctx_with("BTC", "70000", "70000", "0.00003", "105")).await.unwrap();
+    assert_eq!(fired.iter().map(|alert| alert.chat_id).collect::<Vec<_>>(), vec![1]);
+    let fired = monitor.handle_message(perp_ctx_with("BTC", "70000", "69800", "0.00003", "111")).await.unwrap();
+    let mut chat_ids: Vec<i64> = fired.iter().map(|alert| alert.chat_id).collect();
+    chat_ids.sort();
+    assert_eq!(chat_ids, vec![2, 3]);
+}
+
+#[tokio::test]
+async fn the_first_update_after_seeding_compares_like_with_like() {
+    let (db, monitor) = setup().await;
+    let perp_alert = |chat_id, target| NewAlert {
+        target,
+        ..alert(ChatId(chat_id), "BTC-PERP", "BTC", MarketType::Perp, 0.0, AlertDirection::Above)
+    };
+    db.insert_alert(&perp_alert(1, AlertService::parse_target("premium>20bps").unwrap())).await.unwrap();
+    db.insert_alert(&perp_alert(2, AlertService::parse_target("funding>0.002%").unwrap())).await.unwrap();
+    db.insert_alert(&perp_alert(3, AlertService::parse_target("oi+10%/1h").unwrap())).await.unwrap();
+
+    // Already past both thresholds at startup: 30bps premium, 0.003% funding.
+    let ctx: PerpAssetContext = serde_json::from_value(serde_json::json!({
+        "dayNtlVlm": "0", "markPx": "70210", "midPx": "70210", "prevDayPx": "70000",
+        "funding": "0.00003", "openInterest": "100", "oraclePx": "70000",
+    }))
+    .unwrap();
+    monitor.seed_perp_context("BTC", &ctx).await;
+    assert!(monitor.handle_message(perp_ctx_with("BTC", "70210", "70000", "0.00003", "100")).await.unwrap().is_empty());
+
+    // Open interest is measured from the seeded sample.
+    let fired = monitor.handle_message(perp_ctx_with("BTC", "70210", "70000", "0.00003", "111")).await.unwrap();
+    assert_eq!(fired.iter().map(|alert| alert.chat_id).collect::<Vec<_>>(), vec![3]);
+}
+
+#[tokio::test]
+async fn crossings_since_the_seed_fire_on_the_first_update() {
+    let (db, monitor) = setup().await;
+    db.insert_alert(&NewAlert {
+        target: AlertService::parse_target("premium>20bps").unwrap(),
+        ..alert(ChatId(1), "BTC-PERP", "BTC", MarketType::Perp, 0.0, AlertDirection::Above)
+    })
+    .await
+    .unwrap();
+
+    let ctx: PerpAssetContext = serde_json::from_value(serde_json::json!({
+        "dayNtlVlm": "0", "markPx": "70070", "midPx": "70070", "prevDayPx": "70000",
+        "funding": "0.0000125", "openInterest": "100", "oraclePx": "70000",
+    }))
+    .unwrap();
+    monitor.seed_perp_context("BTC", &ctx).await;
+    let fired = monitor.handle_message(perp_ctx_with("BTC", "70210", "70000", "0.0000125", "100")).await.unwrap();
+    assert_eq!(fired.len(), 1);
+}
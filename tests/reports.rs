@@ -0,0 +1,196 @@
+mod common;
+
+use backend::cron::CronService;
+use backend::db::{CronAlertKind, ReportTemplate};
+use backend::reports::{MarketReport, MarketStats, PortfolioReport, USER_FILLS_LIMIT};
+use chrono::{TimeZone, Utc};
+use chrono_tz::Tz;
+use hyperliquid_rust_sdk::{
+    CandlesSnapshotResponse, FundingHistoryResponse, MetaAndAssetCtxs, PerpAssetContext, SpotAssetContext,
+    UserFillsResponse, UserStateResponse, UserTokenBalanceResponse,
+};
+use teloxide::types::ChatId;
+
+fn hype_spot() -> MarketStats {
+    let ctx: SpotAssetContext = serde_json::from_value(serde_json::json!({
+        "coin": "@107",
+        "dayNtlVlm": "12345678.9",
+        "markPx": "40.1",
+        "midPx": "40.0",
+        "prevDayPx": "38.0",
+        "circulatingSupply": "1000000",
+    }))
+    .unwrap();
+    MarketStats::spot("HYPE", &ctx).unwrap()
+}
+
+fn btc_perp() -> MarketStats {
+    let ctx: PerpAssetContext = serde_json::from_value(serde_json::json!({
+        "dayNtlVlm": "2500000000",
+        "markPx": "100000",
+        "midPx": null,
+        "prevDayPx": "102000",
+        "funding": "0.0000125",
+        "openInterest": "12000",
+        "oraclePx": "100010",
+        "premium": "0.0001",
+        "impactPxs": ["99999", "100001"],
+    }))
+    .unwrap();
+    let candles: Vec<CandlesSnapshotResponse> = serde_json::from_value(serde_json::json!([
+        {"t": 0, "T": 1, "s": "BTC", "i": "1h", "o": "101000", "c": "99000", "h": "103000", "l": "98500", "v": "1", "n": 1},
+        {"t": 1, "T": 2, "s": "BTC", "i": "1h", "o": "99000", "c": "100000", "h": "100500", "l": "97000", "v": "1", "n": 1},
+    ]))
+    .unwrap();
+    let history: Vec<FundingHistoryResponse> = serde_json::from_value(serde_json::json!([
+        {"coin": "BTC", "fundingRate": "0.00001", "premium": "0", "time": 0},
+        {"coin": "BTC", "fundingRate": "0.00003", "premium": "0", "time": 1},
+    ]))
+    .unwrap();
+    MarketStats::perp("BTC-PERP", &ctx)
+        .unwrap()
+        .with_candles(&candles)
+        .unwrap()
+        .with_funding_history(&history)
+        .unwrap()
+}
+
+#[test]
+fn perp_contexts_deserialize_alongside_meta() {
+    let response: Vec<MetaAndAssetCtxs> = serde_json::from_value(serde_json::json!([
+        {"universe": [{"name": "BTC", "szDecimals": 5}], "marginTables": []},
+        [{"dayNtlVlm": "1", "markPx": "2", "midPx": "2", "prevDayPx": "1", "funding": "0", "openInterest": "3", "oraclePx": "2"}],
+    ]))
+    .unwrap();
+    assert!(matches!(&response[0], MetaAndAssetCtxs::Meta(meta) if meta.universe[0].name == "BTC"));
+    assert!(matches!(&response[1], MetaAndAssetCtxs::Context(ctxs) if ctxs[0].open_interest == "3"));
+}
+
+#[test]
+fn stats_take_high_low_and_average_funding() {
+    let btc = btc_perp();
+    assert_eq!((btc.high, btc.low), (Some(103000.0), Some(97000.0)));
+    let perp = btc.perp.as_ref().unwrap();
+    assert!((perp.avg_funding.unwrap() - 0.00002).abs() < 1e-12);
+    assert!((btc.change_pct().unwrap() + 1.9607843).abs() < 1e-6);
+
+    let hype = hype_spot();
+    assert_eq!(hype.price, 40.0);
+    assert!(hype.perp.is_none());
+}
+
+#[test]
+fn reports_render_by_template() {
+    let stats = vec![hype_spot(), btc_perp()];
+    let report = |template| MarketReport { template, stats: stats.clone() }.to_string();
+
+    assert_eq!(report(ReportTemplate::Price), "⏰ HYPE: 40\n⏰ BTC-PERP: 100000");
+    assert_eq!(report(ReportTemplate::Summary), "📊 Market report\nHYPE 40 (+5.26% 24h)\nBTC-PERP 100000 (-1.96% 24h)");
+    assert_eq!(
+        report(ReportTemplate::Full),
+        "📊 Market report\n\
+         HYPE 40 (+5.26% 24h)\n  volume $12.35M\n\
+         BTC-PERP 100000 (-1.96% 24h)\n  24h high 103000, low 97000, volume $2.50B\n  funding 0.0013%/h (24h avg 0.0020%/h), OI $1.20B"
+    );
+}
+
+#[tokio::test]
+async fn cron_alerts_keep_their_watchlist_and_template() {
+    let (db, _) = common::setup().await;
+    let next_trigger = CronService::next_trigger("0 8 * * *", Tz::UTC, Utc::now()).unwrap();
+    db.insert_cron_alert(ChatId(1), "HYPE,BTC-PERP", "@107,BTC", "0 8 * * *", ReportTemplate::Summary, next_trigger).await.unwrap();
+
+    let cron_alert = &db.get_cron_alerts_for_chat(ChatId(1)).await.unwrap()[0];
+    assert_eq!(cron_alert.coins(), vec!["HYPE", "BTC-PERP"]);
+    assert_eq!(cron_alert.template, ReportTemplate::Summary);
+    assert!(cron_alert.to_string().contains("(report: summary)"));
+}
+
+fn position(coin: &str, szi: &str, entry_px: &str, unrealized_pnl: &str, return_on_equity: &str) -> serde_json::Value {
+    serde_json::json!({
+        "type": "oneWay",
+        "position": {
+            "coin": coin,
+            "entryPx": entry_px,
+            "leverage": {"type": "cross", "value": 5},
+            "liquidationPx": null,
+            "marginUsed": "2000",
+            "positionValue": "10000",
+            "returnOnEquity": return_on_equity,
+            "szi": szi,
+            "unrealizedPnl": unrealized_pnl,
+            "maxLeverage": 40,
+            "cumFunding": {"allTime": "0", "sinceChange": "0", "sinceOpen": "0"},
+        },
+    })
+}
+
+fn fill(closed_pnl: &str, fee: &str, time: u64) -> serde_json::Value {
+    serde_json::json!({
+        "closedPnl": closed_pnl, "coin": "BTC", "crossed": true, "dir": "Close Long", "hash": "0x0", "oid": 1,
+        "px": "100000", "side": "A", "startPosition": "1", "sz": "0.1", "time": time, "fee": fee,
+    })
+}
+
+#[test]
+fn portfolio_reports_sum_todays_fills() {
+    let summary = serde_json::json!({"accountValue": "25000", "totalMarginUsed": "5000", "totalNtlPos": "30000", "totalRawUsd": "0"});
+    let state: UserStateResponse = serde_json::from_value(serde_json::json!({
+        "assetPositions": [
+            position("BTC", "0.2", "98000", "400", "0.2"),
+            position("ETH", "-2", "4000", "-150.5", "-0.0752"),
+        ],
+        "crossMarginSummary": summary,
+        "marginSummary": summary,
+        "withdrawable": "19500",
+    }))
+    .unwrap();
+    let balances: UserTokenBalanceResponse = serde_json::from_value(serde_json::json!({
+        "balances": [
+            {"coin": "USDC", "hold": "0", "total": "0", "entryNtl": "0"},
+            {"coin": "HYPE", "hold": "0", "total": "12.5", "entryNtl": "450"},
+        ],
+    }))
+    .unwrap();
+    let since = Utc.with_ymd_and_hms(2026, 10, 17, 0, 0, 0).unwrap();
+    let today = since.timestamp_millis() as u64;
+    let fills: Vec<UserFillsResponse> =
+        serde_json::from_value(serde_json::json!([fill("120", "1.5", today + 1_000), fill("-20", "0.5", today + 2_000), fill("999", "9", today - 1)]))
+            .unwrap();
+
+    let report = PortfolioReport::new("0xabc", &state, &balances, &fills, since).unwrap();
+    assert_eq!((report.realized_pnl, report.fees, report.fills), (100.0, 2.0, 2));
+    assert_eq!(report.balances, vec![("HYPE".to_string(), 12.5)]);
+    assert_eq!(
+        report.to_string(),
+        "💼 Portfolio 0xabc\n\
+         Account value $25.00K, margin used $5.00K (20.0%), withdrawable $19.50K\n\
+         Positions:\n\
+         - BTC long 0.2 @ 98000: PnL +$400.00 (ROE +20.00%)\n\
+         - ETH short 2 @ 4000: PnL -$150.50 (ROE -7.52%)\n\
+         Spot: HYPE 12.5\n\
+         Today: realised PnL +$100.00, fees $2.00 over 2 fills"
+    );
+    assert!(!report.truncated);
+
+    // A full page of fills that doesn't reach back to midnight may be
+    // missing some of the day's.
+    let fills: Vec<UserFillsResponse> =
+        serde_json::from_value(serde_json::Value::Array(vec![fill("1", "0.1", today + 1_000); USER_FILLS_LIMIT])).unwrap();
+    let report = PortfolioReport::new("0xabc", &state, &balances, &fills, since).unwrap();
+    assert!(report.truncated);
+    assert!(report.to_string().ends_with(&format!("over {USER_FILLS_LIMIT} fills (only the latest {USER_FILLS_LIMIT} fills are available, earlier ones today are not counted)")));
+}
+
+#[tokio::test]
+async fn portfolio_cron_alerts_target_an_address() {
+    let (db, _) = common::setup().await;
+    let address = "0x0000000000000000000000000000000000000001";
+    let next_trigger = CronService::next_trigger("0 8 * * 1-5", Tz::UTC, Utc::now()).unwrap();
+    db.insert_portfolio_cron_alert(ChatId(1), address, "0 8 * * 1-5", next_trigger).await.unwrap();
+
+    let cron_alert = &db.get_cron_alerts_for_chat(ChatId(1)).await.unwrap()[0];
+    assert_eq!(cron_alert.kind, CronAlertKind::Portfolio);
+    assert_eq!(cron_alert.address.as_deref(), Some(address));
+    assert!(cron_alert.to_string().contains(&format!("portfolio of {address}")));
+}
@@ -0,0 +1,52 @@
+use backend::subscriptions::{Feed, SubscriptionManager};
+use futures_util::StreamExt;
+use hyperliquid_rust_sdk::{BaseUrl, InfoClient};
+use tokio::net::TcpListener;
+use tokio::sync::mpsc::{UnboundedReceiver, unbounded_channel};
+
+/// Serves the websocket `BaseUrl::Localhost` points at, forwarding each
+/// subscribe and unsubscribe request as "<method> <coin>".
+async fn serve_websocket() -> UnboundedReceiver<String> {
+    let listener = TcpListener::bind("127.0.0.1:3001").await.unwrap();
+    let (sender, receiver) = unbounded_channel();
+    tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut websocket = tokio_tungstenite::accept_async(stream).await.unwrap();
+        while let Some(Ok(message)) = websocket.next().await {
+            let Ok(request) = serde_json::from_str::<serde_json::Value>(message.to_text().unwrap_or_default()) else {
+                continue;
+            };
+            if request["method"] != "ping" {
+                let _ = sender.send(format!("{} {}", request["method"].as_str().unwrap(), request["subscription"]["coin"].as_str().unwrap()));
+            }
+        }
+    });
+    receiver
+}
+
+#[tokio::test]
+async fn subscriptions_live_until_the_last_release() {
+    let mut requests = serve_websocket().await;
+    let (sender, _receiver) = unbounded_channel();
+    let subscriptions = SubscriptionManager::new(InfoClient::new(None, Some(BaseUrl::Localhost)).await.unwrap(), sender);
+
+    subscriptions.acquire(Feed::AssetCtx, "BTC").await.unwrap();
+    subscriptions.acquire(Feed::AssetCtx, "BTC").await.unwrap();
+    subscriptions.acquire(Feed::Bbo, "BTC").await.unwrap();
+    assert_eq!(requests.recv().await.unwrap(), "subscribe BTC");
+    assert_eq!(requests.recv().await.unwrap(), "subscribe BTC");
+
+    // One alert still watches the asset context, so only the releases of
+    // the last references unsubscribe.
+    subscriptions.release(Feed::AssetCtx, "BTC").await.unwrap();
+    subscriptions.release(Feed::Bbo, "BTC").await.unwrap();
+    assert_eq!(requests.recv().await.unwrap(), "unsubscribe BTC");
+    subscriptions.release(Feed::AssetCtx, "BTC").await.unwrap();
+    assert_eq!(requests.recv().await.unwrap(), "unsubscribe BTC");
+    assert!(requests.try_recv().is_err());
+
+    // Releasing what is no longer held is a no-op.
+    subscriptions.release(Feed::AssetCtx, "BTC").await.unwrap();
+    subscriptions.acquire(Feed::AssetCtx, "ETH").await.unwrap();
+    assert_eq!(requests.recv().await.unwrap(), "subscribe ETH");
+}
@@ -0,0 +1,52 @@
+mod common;
+
+use backend::{assets::AssetRegistry, db::MarketType, subscriptions::SubscriptionManager, whales::WhaleService};
+use hyperliquid_rust_sdk::{BaseUrl, InfoClient, Message};
+use std::sync::Arc;
+use teloxide::types::ChatId;
+use tokio::sync::{mpsc::unbounded_channel, Mutex};
+
+fn trades(trades: &[(&str, &str, &str, u64)]) -> Message {
+    let trades: Vec<serde_json::Value> = trades
+        .iter()
+        .enumerate()
+        .map(|(tid, (side, px, sz, offset_ms))| {
+            serde_json::json!({
+                "coin": "HYPE",
+                "side": side,
+                "px": px,
+                "sz": sz,
+                "time": chrono::Utc::now().timestamp_millis() as u64 - 10_000 + offset_ms,
+                "hash": "0x00",
+                "tid": tid,
+                "users": ["0xbuyer", "0xseller"]
+            })
+        })
+        .collect();
+    serde_json::from_value(serde_json::json!({ "channel": "trades", "data": trades })).unwrap()
+}
+
+#[tokio::test]
+async fn large_trades_and_same_side_bursts_alert_each_chat_once() {
+    let (db, _) = common::setup().await;
+    let (sender, _receiver) = unbounded_channel();
+    let service = WhaleService::new(
+        db.clone(),
+        AssetRegistry::new(Arc::new(Mutex::new(InfoClient::new(None, Some(BaseUrl::Mainnet)).await.unwrap()))),
+        SubscriptionManager::new(InfoClient::new(None, Some(BaseUrl::Mainnet)).await.unwrap(), sender),
+    );
+    db.upsert_whale_alert(ChatId(1), "HYPE-PERP", "HYPE", MarketType::Perp, 100_000.0, None).await.unwrap();
+    db.upsert_whale_alert(ChatId(2), "HYPE-PERP", "HYPE", MarketType::Perp, 100_000.0, Some(30)).await.unwrap();
+
+    let notifications = service.handle_message(trades(&[("B", "40", "3000", 0)])).await.unwrap();
+    assert_eq!(notifications.iter().map(|n| n.chat_id).collect::<Vec<_>>(), vec![1, 2]);
+    assert!(notifications[0].to_string().contains("0xbuyer"));
+
+    let burst = trades(&[("A", "40", "1000", 1000), ("B", "40", "1500", 2000), ("A", "40", "1500", 3000)]);
+    let notifications = service.handle_message(burst).await.unwrap();
+    assert_eq!(notifications.len(), 1);
+    assert_eq!((notifications[0].chat_id, notifications[0].trades.len()), (2, 2));
+
+    let notifications = service.handle_message(trades(&[("A", "40", "100", 4000)])).await.unwrap();
+    assert!(notifications.is_empty());
+}